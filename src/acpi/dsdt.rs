@@ -0,0 +1,52 @@
+//! Differentiated System Description Table, holding the AML definition blocks of the system.
+//!
+//! Interpreting AML is out of reach, but some simple objects can be located by scanning the
+//! encoded bytes for their name.
+
+use crate::acpi::SdtHeader;
+
+/// (AML) Opcode introducing a named object.
+const NAME_OP: u8 = 0x08;
+/// (AML) Opcode introducing a package.
+const PACKAGE_OP: u8 = 0x12;
+/// (AML) Prefix of a byte constant.
+const BYTE_PREFIX: u8 = 0x0A;
+/// (AML) Prefix of a name relative to the root namespace.
+const ROOT_PREFIX: u8 = b'\\';
+
+#[derive(Clone, Copy)]
+pub struct Dsdt(&'static SdtHeader);
+
+impl Dsdt {
+    pub const SIGNATURE: &[u8; 4] = b"DSDT";
+
+    pub(super) fn new(header: &'static SdtHeader) -> Self {
+        Self(header)
+    }
+
+    /// Returns the SLP_TYP values to write to the PM1a and PM1b control registers to enter the
+    /// S5 (soft off) sleep state, from the `\_S5` package.
+    pub fn s5_sleep_types(&self) -> Option<(u8, u8)> {
+        let aml = self.0.body();
+        let start = aml.windows(4).position(|name| name == b"_S5_")?;
+
+        let is_name = matches!(
+            aml.get(..start)?,
+            [.., NAME_OP] | [.., NAME_OP, ROOT_PREFIX]
+        );
+        let mut bytes = aml.get(start + 4..)?.iter().copied();
+        if !is_name || bytes.next()? != PACKAGE_OP {
+            return None;
+        }
+
+        // The two high bits of the first byte of the package length count the bytes following it.
+        let length_bytes = bytes.next()? >> 6;
+        let mut bytes = bytes.skip(length_bytes as usize + 1);
+        let mut integer = || match bytes.next()? {
+            BYTE_PREFIX => bytes.next(),
+            // Zero, One and small constants are encoded as their value.
+            value => Some(value),
+        };
+        Some((integer()?, integer()?))
+    }
+}
@@ -0,0 +1,134 @@
+//! Fixed ACPI Description Table, describing the fixed hardware registers used for power
+//! management.
+
+use crate::acpi::SdtHeader;
+use crate::memory::PhysicalAddress;
+
+/// (Flags) The reset register is supported.
+const RESET_REGISTER_SUPPORTED: u32 = 1 << 10;
+
+/// Layout of the FADT fields used by the kernel, following the [`SdtHeader`].
+#[repr(C, packed)]
+struct FadtFields {
+    firmware_control: u32,
+    dsdt: u32,
+    _reserved_0: u8,
+    preferred_power_profile: u8,
+    sci_interrupt: u16,
+    smi_command_port: u32,
+    acpi_enable: u8,
+    acpi_disable: u8,
+    s4bios_request: u8,
+    pstate_control: u8,
+    pm1a_event_block: u32,
+    pm1b_event_block: u32,
+    pm1a_control_block: u32,
+    pm1b_control_block: u32,
+    pm2_control_block: u32,
+    pm_timer_block: u32,
+    gpe0_block: u32,
+    gpe1_block: u32,
+    pm1_event_length: u8,
+    pm1_control_length: u8,
+    pm2_control_length: u8,
+    pm_timer_length: u8,
+    gpe0_length: u8,
+    gpe1_length: u8,
+    gpe1_base: u8,
+    c_state_control: u8,
+    worst_c2_latency: u16,
+    worst_c3_latency: u16,
+    flush_size: u16,
+    flush_stride: u16,
+    duty_offset: u8,
+    duty_width: u8,
+    day_alarm: u8,
+    month_alarm: u8,
+    century: u8,
+    boot_architecture_flags: u16,
+    _reserved_1: u8,
+    flags: u32,
+    reset_register: GenericAddress,
+    reset_value: u8,
+}
+
+/// (GAS) Location of a register, in one of the ACPI address spaces.
+#[derive(Clone, Copy, Debug)]
+#[repr(C, packed)]
+pub struct GenericAddress {
+    /// 0 for system memory, 1 for system I/O.
+    pub address_space: u8,
+    pub bit_width: u8,
+    pub bit_offset: u8,
+    pub access_size: u8,
+    pub address: u64,
+}
+
+#[derive(Clone, Copy)]
+pub struct Fadt(&'static SdtHeader);
+
+impl Fadt {
+    pub const SIGNATURE: &[u8; 4] = b"FACP";
+
+    pub(super) fn new(header: &'static SdtHeader) -> Self {
+        Self(header)
+    }
+
+    /// Returns the fields of the table, which must be long enough to hold `end` bytes of them.
+    fn fields(&self, end: usize) -> Option<&FadtFields> {
+        if self.0.body().len() < end {
+            return None;
+        }
+        let fields = self.0.body().as_ptr().cast::<FadtFields>();
+        Some(unsafe { &*fields })
+    }
+
+    fn fixed_fields(&self) -> &FadtFields {
+        // Every FADT revision at least holds the fields up to the flags.
+        self.fields(core::mem::offset_of!(FadtFields, reset_register))
+            .expect("The FADT is too short")
+    }
+
+    /// Physical address of the Differentiated System Description Table.
+    pub fn dsdt(&self) -> PhysicalAddress {
+        PhysicalAddress::from(self.fixed_fields().dsdt as usize)
+    }
+
+    /// Legacy interrupt line of the System Control Interrupt.
+    pub fn sci_interrupt(&self) -> u16 {
+        self.fixed_fields().sci_interrupt
+    }
+
+    /// I/O ports of the PM1a and, if present, PM1b control registers.
+    pub fn pm1_control_blocks(&self) -> (u16, Option<u16>) {
+        let fields = self.fixed_fields();
+        let pm1b = fields.pm1b_control_block;
+        (
+            fields.pm1a_control_block as u16,
+            (pm1b != 0).then_some(pm1b as u16),
+        )
+    }
+
+    /// Returns the I/O port of the SMI command register and the value to write to it to hand the
+    /// power management over to the OS, if the system is not already in ACPI mode.
+    pub fn acpi_enable_command(&self) -> Option<(u16, u8)> {
+        let fields = self.fixed_fields();
+        let port = fields.smi_command_port;
+        (port != 0 && fields.acpi_enable != 0).then_some((port as u16, fields.acpi_enable))
+    }
+
+    /// Index of the century register in the RTC CMOS RAM, if the RTC has one.
+    pub fn century_register(&self) -> Option<u8> {
+        let century = self.fixed_fields().century;
+        (century != 0).then_some(century)
+    }
+
+    /// Returns the register and the value to write to it to reset the system, if supported.
+    pub fn reset_register(&self) -> Option<(GenericAddress, u8)> {
+        let fields = self.fields(size_of::<FadtFields>())?;
+        if fields.flags & RESET_REGISTER_SUPPORTED == 0 {
+            return None;
+        }
+        Some((fields.reset_register, fields.reset_value))
+    }
+}
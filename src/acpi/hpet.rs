@@ -0,0 +1,40 @@
+//! High Precision Event Timer description table, locating the registers of the HPET.
+
+use crate::acpi::{GenericAddress, SdtHeader};
+use crate::memory::PhysicalAddress;
+
+/// (GAS) Address space of registers in system memory.
+const ADDRESS_SPACE_MEMORY: u8 = 0;
+
+/// Layout of the HPET table fields, following the [`SdtHeader`].
+#[repr(C, packed)]
+struct HpetFields {
+    event_timer_block_id: u32,
+    base_address: GenericAddress,
+    hpet_number: u8,
+    minimum_tick: u16,
+    page_protection: u8,
+}
+
+#[derive(Clone, Copy)]
+pub struct Hpet(&'static SdtHeader);
+
+impl Hpet {
+    pub const SIGNATURE: &[u8; 4] = b"HPET";
+
+    pub(super) fn new(header: &'static SdtHeader) -> Self {
+        Self(header)
+    }
+
+    /// Physical address of the registers of the timer block, if they are memory-mapped as
+    /// required by the specification.
+    pub fn base_address(&self) -> Option<PhysicalAddress> {
+        if self.0.body().len() < size_of::<HpetFields>() {
+            return None;
+        }
+        let fields = unsafe { &*self.0.body().as_ptr().cast::<HpetFields>() };
+        let base_address = fields.base_address;
+        (base_address.address_space == ADDRESS_SPACE_MEMORY)
+            .then(|| PhysicalAddress::from_u64(base_address.address))
+    }
+}
@@ -0,0 +1,124 @@
+//! Multiple APIC Description Table, describing the interrupt controllers of the system.
+
+use crate::acpi::SdtHeader;
+use crate::cpu::interrupts::ioapic::{InterruptSourceOverride, Polarity, TriggerMode};
+use crate::memory::PhysicalAddress;
+
+/// (Local APIC flags) The processor is usable.
+const LOCAL_APIC_ENABLED: u32 = 1 << 0;
+/// (Local APIC flags) The processor can be enabled at runtime.
+const LOCAL_APIC_ONLINE_CAPABLE: u32 = 1 << 1;
+
+/// (MPS INTI flags) Mask of the polarity of the interrupt line.
+const POLARITY_MASK: u16 = 0b11;
+/// (MPS INTI flags) Active low polarity, any other value is active high for ISA interrupts.
+const POLARITY_ACTIVE_LOW: u16 = 0b11;
+/// (MPS INTI flags) Shift of the trigger mode of the interrupt line.
+const TRIGGER_MODE_SHIFT: u16 = 2;
+/// (MPS INTI flags) Level-triggered mode, any other value is edge-triggered for ISA interrupts.
+const TRIGGER_MODE_LEVEL: u16 = 0b11;
+
+#[derive(Clone, Copy)]
+pub struct Madt(&'static SdtHeader);
+
+impl Madt {
+    pub const SIGNATURE: &[u8; 4] = b"APIC";
+
+    pub(super) fn new(header: &'static SdtHeader) -> Self {
+        Self(header)
+    }
+
+    /// Physical address of the local APIC registers, unless overridden by a
+    /// [`MadtEntry::LocalApicAddressOverride`].
+    pub fn local_apic_address(&self) -> PhysicalAddress {
+        PhysicalAddress::from(read_u32(self.0.body(), 0) as usize)
+    }
+
+    /// Returns the interrupt controller structures following the fixed fields of the table.
+    pub fn entries(&self) -> impl Iterator<Item = MadtEntry> {
+        let mut bytes = &self.0.body()[8..];
+        core::iter::from_fn(move || {
+            let (&kind, &length) = (bytes.first()?, bytes.get(1)?);
+            if length < 2 || length as usize > bytes.len() {
+                return None;
+            }
+
+            let (entry, rest) = bytes.split_at(length as usize);
+            bytes = rest;
+            Some(MadtEntry::parse(kind, &entry[2..]))
+        })
+    }
+
+    /// Returns how the legacy ISA IRQs are wired to the I/O APICs when they are not identity
+    /// mapped.
+    pub fn interrupt_source_overrides(&self) -> impl Iterator<Item = InterruptSourceOverride> {
+        self.entries().filter_map(|entry| match entry {
+            MadtEntry::InterruptSourceOverride(source_override) => Some(source_override),
+            _ => None,
+        })
+    }
+}
+
+/// An interrupt controller structure of the [`Madt`].
+#[derive(Clone, Copy, Debug)]
+pub enum MadtEntry {
+    LocalApic {
+        processor_id: u8,
+        apic_id: u8,
+        usable: bool,
+    },
+    IoApic {
+        id: u8,
+        address: PhysicalAddress,
+        gsi_base: u32,
+    },
+    InterruptSourceOverride(InterruptSourceOverride),
+    LocalApicAddressOverride(PhysicalAddress),
+    /// A structure that is not decoded, identified by its type.
+    Other(u8),
+}
+
+impl MadtEntry {
+    /// Decode the structure of type `kind` from its `data`, which follows its type and length.
+    fn parse(kind: u8, data: &[u8]) -> Self {
+        match (kind, data.len()) {
+            (0, 6..) => {
+                let flags = read_u32(data, 2);
+                Self::LocalApic {
+                    processor_id: data[0],
+                    apic_id: data[1],
+                    usable: flags & (LOCAL_APIC_ENABLED | LOCAL_APIC_ONLINE_CAPABLE) != 0,
+                }
+            }
+            (1, 10..) => Self::IoApic {
+                id: data[0],
+                address: PhysicalAddress::from(read_u32(data, 2) as usize),
+                gsi_base: read_u32(data, 6),
+            },
+            (2, 8..) => {
+                let flags = u16::from_le_bytes([data[6], data[7]]);
+                Self::InterruptSourceOverride(InterruptSourceOverride {
+                    irq: data[1],
+                    gsi: read_u32(data, 2),
+                    polarity: match flags & POLARITY_MASK {
+                        POLARITY_ACTIVE_LOW => Polarity::ActiveLow,
+                        _ => Polarity::ActiveHigh,
+                    },
+                    trigger_mode: match (flags >> TRIGGER_MODE_SHIFT) & 0b11 {
+                        TRIGGER_MODE_LEVEL => TriggerMode::Level,
+                        _ => TriggerMode::Edge,
+                    },
+                })
+            }
+            (5, 10..) => {
+                let address = u64::from_le_bytes(data[2..10].try_into().unwrap());
+                Self::LocalApicAddressOverride(PhysicalAddress::from_u64(address))
+            }
+            _ => Self::Other(kind),
+        }
+    }
+}
+
+fn read_u32(bytes: &[u8], offset: usize) -> u32 {
+    u32::from_le_bytes(bytes[offset..offset + 4].try_into().unwrap())
+}
@@ -0,0 +1,224 @@
+//! Minimal discovery of the ACPI tables.
+//!
+//! The RSDP provided by Limine points to the root table (the RSDT, or the XSDT from ACPI 2.0),
+//! which lists the physical addresses of every other table. Tables are identified by the
+//! signature at the start of their [`SdtHeader`].
+
+mod dsdt;
+mod fadt;
+mod hpet;
+mod madt;
+
+use core::{error, fmt, ptr, slice};
+
+use spin::Once;
+
+pub use dsdt::Dsdt;
+pub use fadt::{Fadt, GenericAddress};
+pub use hpet::Hpet;
+pub use madt::{Madt, MadtEntry};
+
+use crate::limine;
+use crate::memory::{PhysicalAddress, paging};
+use crate::terminal::logger;
+
+const RSDP_SIGNATURE: &[u8; 8] = b"RSD PTR ";
+const RSDT_SIGNATURE: &[u8; 4] = b"RSDT";
+const XSDT_SIGNATURE: &[u8; 4] = b"XSDT";
+
+/// Size of the RSDP structure defined by ACPI 1.0, covered by its checksum.
+const RSDP_V1_LENGTH: usize = 20;
+
+/// Root System Description Pointer.
+#[repr(C, packed)]
+struct Rsdp {
+    signature: [u8; 8],
+    checksum: u8,
+    oem_id: [u8; 6],
+    revision: u8,
+    rsdt_address: u32,
+    // Fields below only exist from revision 2 (ACPI 2.0).
+    length: u32,
+    xsdt_address: u64,
+    extended_checksum: u8,
+    _reserved: [u8; 3],
+}
+
+/// Header common to every System Description Table.
+#[repr(C, packed)]
+pub struct SdtHeader {
+    signature: [u8; 4],
+    length: u32,
+    revision: u8,
+    checksum: u8,
+    oem_id: [u8; 6],
+    oem_table_id: [u8; 8],
+    oem_revision: u32,
+    creator_id: u32,
+    creator_revision: u32,
+}
+
+impl SdtHeader {
+    pub fn signature(&self) -> [u8; 4] {
+        self.signature
+    }
+
+    /// Length of the table in bytes, header included.
+    pub fn length(&self) -> usize {
+        self.length as usize
+    }
+
+    pub fn revision(&self) -> u8 {
+        self.revision
+    }
+
+    /// Returns the bytes of the whole table.
+    fn bytes(&self) -> &[u8] {
+        unsafe { slice::from_raw_parts(ptr::from_ref(self).cast::<u8>(), self.length()) }
+    }
+
+    /// Returns the bytes following the header.
+    fn body(&self) -> &[u8] {
+        &self.bytes()[size_of::<Self>()..]
+    }
+}
+
+#[derive(Debug)]
+pub enum AcpiError {
+    MissingRsdp,
+    InvalidRsdp,
+    InvalidChecksum([u8; 4]),
+}
+
+impl fmt::Display for AcpiError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::MissingRsdp => write!(f, "The bootloader did not provide an RSDP"),
+            Self::InvalidRsdp => write!(f, "The RSDP has an invalid signature or checksum"),
+            Self::InvalidChecksum(signature) => write!(
+                f,
+                "The {} table has an invalid checksum",
+                signature.escape_ascii()
+            ),
+        }
+    }
+}
+
+impl error::Error for AcpiError {}
+
+/// Root table listing the addresses of the other tables.
+struct RootTable {
+    header: &'static SdtHeader,
+    /// Size of each address, 4 bytes in the RSDT and 8 bytes in the XSDT.
+    entry_size: usize,
+}
+
+impl RootTable {
+    fn tables(&self) -> impl Iterator<Item = PhysicalAddress> {
+        self.header
+            .body()
+            .chunks_exact(self.entry_size)
+            .map(|entry| {
+                let mut address = [0; 8];
+                address[..entry.len()].copy_from_slice(entry);
+                PhysicalAddress::from_u64(u64::from_le_bytes(address))
+            })
+    }
+}
+
+static ROOT_TABLE: Once<RootTable> = Once::new();
+
+/// Locate and validate the root table from the RSDP provided by the bootloader.
+pub fn init() -> Result<(), AcpiError> {
+    let rsdp_address = limine::acquire_rsdp().ok_or(AcpiError::MissingRsdp)?;
+    let rsdp = unsafe { &*paging::map_mmio(rsdp_address, size_of::<Rsdp>()).to_ptr::<Rsdp>() };
+    if rsdp.signature != *RSDP_SIGNATURE || !rsdp_is_valid(rsdp) {
+        return Err(AcpiError::InvalidRsdp);
+    }
+
+    let (root_address, entry_size) = if rsdp.revision >= 2 && rsdp.xsdt_address != 0 {
+        (PhysicalAddress::from_u64(rsdp.xsdt_address), 8)
+    } else {
+        (PhysicalAddress::from(rsdp.rsdt_address as usize), 4)
+    };
+    let header = map_table(root_address)?;
+    let expected = if entry_size == 8 {
+        XSDT_SIGNATURE
+    } else {
+        RSDT_SIGNATURE
+    };
+    if header.signature != *expected {
+        return Err(AcpiError::InvalidRsdp);
+    }
+
+    let root = ROOT_TABLE.call_once(|| RootTable { header, entry_size });
+    logger::info!(
+        "ACPI revision {} with {} tables",
+        rsdp.revision,
+        root.tables().count()
+    );
+    Ok(())
+}
+
+/// Returns the first table with the given `signature`, if any. Tables with an invalid checksum
+/// are skipped.
+pub fn find_table(signature: &[u8; 4]) -> Option<&'static SdtHeader> {
+    ROOT_TABLE
+        .get()?
+        .tables()
+        .filter_map(|address| map_table(address).ok())
+        .find(|table| table.signature == *signature)
+}
+
+/// Returns the Multiple APIC Description Table, if any.
+pub fn madt() -> Option<Madt> {
+    find_table(Madt::SIGNATURE).map(Madt::new)
+}
+
+/// Returns the Fixed ACPI Description Table, if any.
+pub fn fadt() -> Option<Fadt> {
+    find_table(Fadt::SIGNATURE).map(Fadt::new)
+}
+
+/// Returns the High Precision Event Timer table, if any.
+pub fn hpet() -> Option<Hpet> {
+    find_table(Hpet::SIGNATURE).map(Hpet::new)
+}
+
+/// Returns the Differentiated System Description Table, referenced by the FADT rather than the
+/// root table, if any.
+pub fn dsdt() -> Option<Dsdt> {
+    map_table(fadt()?.dsdt())
+        .ok()
+        .filter(|table| table.signature == *Dsdt::SIGNATURE)
+        .map(Dsdt::new)
+}
+
+/// Map the table located at `address` and validate its checksum.
+fn map_table(address: PhysicalAddress) -> Result<&'static SdtHeader, AcpiError> {
+    let header = paging::map_mmio(address, size_of::<SdtHeader>()).to_ptr::<SdtHeader>();
+    let length = unsafe { (*header).length() };
+    let table = unsafe { &*paging::map_mmio(address, length).to_ptr::<SdtHeader>() };
+
+    if checksum(table.bytes()) != 0 {
+        return Err(AcpiError::InvalidChecksum(table.signature));
+    }
+    Ok(table)
+}
+
+fn rsdp_is_valid(rsdp: &Rsdp) -> bool {
+    let bytes =
+        unsafe { slice::from_raw_parts(ptr::from_ref(rsdp).cast::<u8>(), size_of::<Rsdp>()) };
+    if checksum(&bytes[..RSDP_V1_LENGTH]) != 0 {
+        return false;
+    }
+    rsdp.revision < 2
+        || bytes
+            .get(..rsdp.length as usize)
+            .is_some_and(|bytes| checksum(bytes) == 0)
+}
+
+/// Sum of `bytes`, which must be zero for a valid table.
+fn checksum(bytes: &[u8]) -> u8 {
+    bytes.iter().fold(0, |sum, byte| sum.wrapping_add(*byte))
+}
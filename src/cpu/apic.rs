@@ -0,0 +1,151 @@
+//! Driver for the processor's Local APIC (Advanced Programmable Interrupt Controller).
+
+use core::ptr;
+
+use spin::Once;
+
+use crate::cpu::registers::Msr;
+use crate::memory::{PAGE_SIZE, PhysicalAddress, VirtualAddress, paging};
+use crate::terminal::logger;
+
+/// (IA32_APIC_BASE) Global enable flag of the local APIC.
+const APIC_BASE_GLOBAL_ENABLE: u64 = 1 << 11;
+/// (IA32_APIC_BASE) Mask of the physical base address of the local APIC registers.
+const APIC_BASE_ADDRESS_MASK: u64 = 0x000F_FFFF_FFFF_F000;
+
+/// (ID) Local APIC id register offset.
+const LOCAL_APIC_ID: usize = 0x20;
+/// (ID) Shift of the id in the local APIC id register.
+const LOCAL_APIC_ID_SHIFT: u32 = 24;
+/// (EOI) End of interrupt register offset.
+const END_OF_INTERRUPT: usize = 0xB0;
+/// (SVR) Spurious interrupt vector register offset.
+const SPURIOUS_INTERRUPT_VECTOR: usize = 0xF0;
+/// (SVR) APIC software enable flag.
+const SVR_SOFTWARE_ENABLE: u32 = 1 << 8;
+/// (SVR) Mask of the spurious interrupt vector.
+const SVR_VECTOR_MASK: u32 = 0xFF;
+
+/// (LVT) Timer local vector table register offset.
+const LVT_TIMER: usize = 0x320;
+/// (LVT) Interrupt mask flag.
+const LVT_MASKED: u32 = 1 << 16;
+/// (LVT Timer) Periodic timer mode.
+const LVT_TIMER_PERIODIC: u32 = 1 << 17;
+/// (TMICT) Timer initial count register offset.
+const TIMER_INITIAL_COUNT: usize = 0x380;
+/// (TMCCT) Timer current count register offset.
+const TIMER_CURRENT_COUNT: usize = 0x390;
+/// (TDCR) Timer divide configuration register offset.
+const TIMER_DIVIDE_CONFIGURATION: usize = 0x3E0;
+/// (TDCR) Divide the timer input clock by 16.
+const TIMER_DIVIDE_BY_16: u32 = 0b0011;
+
+/// Vector on which the local APIC timer interrupt is delivered, right after the PIC vectors.
+pub const TIMER_VECTOR: u8 = 0x30;
+
+/// Vector on which spurious interrupts are delivered by the local APIC.
+/// Its low nibble is set as required by older processors.
+pub const SPURIOUS_VECTOR: u8 = 0xFF;
+
+static LOCAL_APIC: Once<VirtualAddress> = Once::new();
+
+/// Map the local APIC registers on first use and software-enable the local APIC of the current
+/// processor, delivering spurious interrupts on [`SPURIOUS_VECTOR`]. Must be called on each
+/// processor.
+pub fn init() {
+    let apic_base = unsafe { Msr::IA32_APIC_BASE.read() };
+    unsafe { Msr::IA32_APIC_BASE.write(apic_base | APIC_BASE_GLOBAL_ENABLE) };
+
+    let base = LOCAL_APIC.call_once(|| {
+        let physical = PhysicalAddress::from_u64(apic_base & APIC_BASE_ADDRESS_MASK);
+        paging::map_mmio(physical, PAGE_SIZE)
+    });
+
+    set_spurious_vector(SPURIOUS_VECTOR);
+    logger::info!("Local APIC {} enabled at {:?}", id(), base);
+}
+
+/// Returns the id of the local APIC of the current processor.
+pub fn id() -> u8 {
+    (read_register(LOCAL_APIC_ID) >> LOCAL_APIC_ID_SHIFT) as u8
+}
+
+/// Write the spurious interrupt `vector` in the spurious interrupt vector register, along with
+/// the APIC software enable flag.
+fn set_spurious_vector(vector: u8) {
+    let svr = read_register(SPURIOUS_INTERRUPT_VECTOR) & !SVR_VECTOR_MASK;
+    write_register(
+        SPURIOUS_INTERRUPT_VECTOR,
+        svr | SVR_SOFTWARE_ENABLE | vector as u32,
+    );
+}
+
+/// Returns the vector set in the spurious interrupt vector register and whether the local APIC
+/// is software-enabled.
+pub fn spurious_vector() -> (u8, bool) {
+    let svr = read_register(SPURIOUS_INTERRUPT_VECTOR);
+    (
+        (svr & SVR_VECTOR_MASK) as u8,
+        svr & SVR_SOFTWARE_ENABLE != 0,
+    )
+}
+
+/// Signal the end of the interrupt currently being serviced to the local APIC. Does nothing if
+/// the local APIC is not initialized, as it cannot have delivered the interrupt.
+pub fn end_of_interrupt() {
+    if LOCAL_APIC.get().is_some() {
+        write_register(END_OF_INTERRUPT, 0);
+    }
+}
+
+/// Returns the number of timer counts elapsed while running `wait`, to calibrate the timer
+/// against another clock. The timer is left stopped.
+pub fn measure_timer(wait: impl FnOnce()) -> u32 {
+    write_register(TIMER_DIVIDE_CONFIGURATION, TIMER_DIVIDE_BY_16);
+    write_register(LVT_TIMER, LVT_MASKED);
+    write_register(TIMER_INITIAL_COUNT, u32::MAX);
+
+    wait();
+
+    let elapsed = u32::MAX - read_register(TIMER_CURRENT_COUNT);
+    write_register(TIMER_INITIAL_COUNT, 0);
+    elapsed
+}
+
+/// Start the timer of the current processor in periodic mode, delivering an interrupt on
+/// `vector` every `initial_count` counts, at the rate measured by [`measure_timer`].
+pub fn start_periodic_timer(vector: u8, initial_count: u32) {
+    write_register(TIMER_DIVIDE_CONFIGURATION, TIMER_DIVIDE_BY_16);
+    write_register(LVT_TIMER, LVT_TIMER_PERIODIC | vector as u32);
+    write_register(TIMER_INITIAL_COUNT, initial_count);
+}
+
+#[inline]
+fn register_ptr(offset: usize) -> *mut u32 {
+    let base = LOCAL_APIC
+        .get()
+        .expect("Cannot access the local APIC before it is initialized");
+    (*base + offset).to_ptr::<u32>()
+}
+
+#[inline]
+fn read_register(offset: usize) -> u32 {
+    unsafe { ptr::read_volatile(register_ptr(offset)) }
+}
+
+#[inline]
+fn write_register(offset: usize, value: u32) {
+    unsafe { ptr::write_volatile(register_ptr(offset), value) }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test_case]
+    fn init_enables_the_spurious_vector() {
+        init();
+        assert_eq!(spurious_vector(), (SPURIOUS_VECTOR, true));
+    }
+}
@@ -0,0 +1,169 @@
+//! Stack backtraces, built by walking the chain of saved frame pointers.
+//!
+//! This requires the kernel to be built with frame pointers, which the target specification
+//! enforces with `"frame-pointer": "always"`. Return addresses are resolved to the function
+//! containing them from the symbol table of the kernel file, for source lines use
+//! `addr2line -e max-os.elf <address>`.
+
+use core::fmt;
+
+use crate::cpu::registers;
+use crate::limine;
+
+/// Maximum number of frames walked, protects against corrupt frame chains.
+const MAX_DEPTH: usize = 32;
+/// Size of the largest kernel stack, the one set up by the bootloader. Frames are only read up to
+/// this far above the stack pointer.
+const MAX_STACK_SIZE: usize = 64 * 1024;
+
+/// (ELF) Magic number at the start of the file.
+const ELF_MAGIC: &[u8; 4] = b"\x7fELF";
+/// (ELF) Section type of a symbol table.
+const SECTION_TYPE_SYMBOL_TABLE: u32 = 2;
+/// (ELF) Symbol type of a function, in the low bits of the symbol info.
+const SYMBOL_TYPE_FUNCTION: u8 = 2;
+/// (ELF) Size of an entry of a 64-bit symbol table.
+const SYMBOL_ENTRY_SIZE: usize = 24;
+
+/// Layout of the start of a stack frame, as pushed by the function prologue.
+#[repr(C)]
+struct Frame {
+    previous: *const Frame,
+    return_address: usize,
+}
+
+/// Return addresses of the calls leading to [`Backtrace::capture`], innermost first.
+pub struct Backtrace {
+    addresses: [usize; MAX_DEPTH],
+    len: usize,
+}
+
+impl Backtrace {
+    /// Walk the frame pointers of the current call stack.
+    ///
+    /// The walk stops at the first misaligned frame pointer or at one outside of the current
+    /// stack, so that a corrupt chain can't cause a page fault, and at a frame that is not above
+    /// the previous one on the stack, so that it can't cause a loop.
+    #[inline(always)]
+    pub fn capture() -> Self {
+        let mut frame: *const Frame = registers::read_rbp().to_ptr();
+        let stack_bottom = registers::read_rsp().value();
+        let stack_top = stack_bottom.saturating_add(MAX_STACK_SIZE);
+        let on_stack = |frame: *const Frame| {
+            let start = frame as usize;
+            start >= stack_bottom
+                && start
+                    .checked_add(size_of::<Frame>())
+                    .is_some_and(|end| end <= stack_top)
+        };
+
+        let mut backtrace = Self {
+            addresses: [0; MAX_DEPTH],
+            len: 0,
+        };
+
+        while backtrace.len < MAX_DEPTH && on_stack(frame) && frame.is_aligned() {
+            let Frame {
+                previous,
+                return_address,
+            } = unsafe { frame.read() };
+            if return_address == 0 {
+                break;
+            }
+
+            backtrace.addresses[backtrace.len] = return_address;
+            backtrace.len += 1;
+
+            if previous <= frame {
+                break;
+            }
+            frame = previous;
+        }
+
+        backtrace
+    }
+
+    pub fn addresses(&self) -> &[usize] {
+        &self.addresses[..self.len]
+    }
+}
+
+impl fmt::Display for Backtrace {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(f, "Backtrace:")?;
+        for (i, address) in self.addresses().iter().enumerate() {
+            write!(f, "  {i:>2}: {address:#018x}")?;
+            if let Some((name, offset)) = symbolize(*address) {
+                write!(f, " {name}+{offset:#x}")?;
+            }
+            writeln!(f)?;
+        }
+        Ok(())
+    }
+}
+
+/// Returns the name of the function containing `address` and the offset of `address` from its
+/// start, looked up in the symbol table of the kernel file.
+///
+/// The symbol table is scanned in place for the nearest function starting at or before
+/// `address`, so this does not allocate and can be used while panicking. Returns `None` if the
+/// bootloader did not provide the kernel file or if it was stripped.
+pub fn symbolize(address: usize) -> Option<(&'static str, usize)> {
+    let elf = limine::kernel_file()?;
+    if elf.get(..ELF_MAGIC.len())? != ELF_MAGIC {
+        return None;
+    }
+
+    let section_count = u16::from_le_bytes(read(elf, 0x3C)?) as usize;
+    let (symbols, names_index) = (0..section_count).find_map(|index| {
+        let (kind, link, contents) = section(elf, index)?;
+        (kind == SECTION_TYPE_SYMBOL_TABLE).then_some((contents, link))
+    })?;
+    let (_, _, names) = section(elf, names_index as usize)?;
+
+    let (name, start) = symbols
+        .chunks_exact(SYMBOL_ENTRY_SIZE)
+        .filter(|symbol| symbol[4] & 0xF == SYMBOL_TYPE_FUNCTION)
+        .filter_map(|symbol| {
+            let name = u32::from_le_bytes(read(symbol, 0)?) as usize;
+            let start = u64::from_le_bytes(read(symbol, 8)?) as usize;
+            (start != 0 && start <= address).then_some((name, start))
+        })
+        .max_by_key(|&(_, start)| start)?;
+
+    let name = names.get(name..)?;
+    let length = name.iter().position(|&byte| byte == 0)?;
+    let name = core::str::from_utf8(&name[..length]).ok()?;
+    Some((name, address - start))
+}
+
+/// Returns the type, the link and the contents of the section of `elf` at `index`.
+fn section(elf: &[u8], index: usize) -> Option<(u32, u32, &[u8])> {
+    let headers = u64::from_le_bytes(read(elf, 0x28)?) as usize;
+    let header_size = u16::from_le_bytes(read(elf, 0x3A)?) as usize;
+    let header = elf.get(headers.checked_add(index.checked_mul(header_size)?)?..)?;
+
+    let kind = u32::from_le_bytes(read(header, 4)?);
+    let offset = u64::from_le_bytes(read(header, 24)?) as usize;
+    let size = u64::from_le_bytes(read(header, 32)?) as usize;
+    let link = u32::from_le_bytes(read(header, 40)?);
+    Some((kind, link, elf.get(offset..offset.checked_add(size)?)?))
+}
+
+/// Returns the `N` bytes of `bytes` at `offset`, if in bounds.
+fn read<const N: usize>(bytes: &[u8], offset: usize) -> Option<[u8; N]> {
+    bytes.get(offset..offset.checked_add(N)?)?.try_into().ok()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test_case]
+    fn capture_walks_the_current_stack() {
+        let backtrace = Backtrace::capture();
+
+        assert!(!backtrace.addresses().is_empty());
+        assert!(backtrace.addresses().iter().all(|&address| address != 0));
+    }
+}
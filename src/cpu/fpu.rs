@@ -0,0 +1,52 @@
+//! Initialization of the x87 FPU and of SSE.
+//!
+//! The kernel itself is built with soft-float and never emits these instructions, but they must
+//! be usable by code that does, instead of faulting with `#NM` or `#UD`.
+
+use core::arch::asm;
+
+use crate::cpu::registers::{Cr0, Cr0Flags, Cr4, Cr4Flags};
+use crate::terminal::logger;
+
+/// Enable the x87 FPU and legacy SSE, with x87 and SIMD floating-point errors reported through
+/// their exceptions.
+pub fn init() {
+    unsafe {
+        let cr0 = Cr0::read() - Cr0Flags::EMULATION - Cr0Flags::TASK_SWITCHED;
+        Cr0::write(cr0 | Cr0Flags::MONITOR_COPROCESSOR | Cr0Flags::NUMERIC_ERROR);
+        Cr4::write(Cr4::read() | Cr4Flags::OS_FXSR | Cr4Flags::OS_XMM_EXCEPTIONS);
+
+        asm!("fninit", options(nomem, nostack));
+    }
+
+    logger::info!("FPU and SSE enabled");
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Add `a` and `b` on the x87 FPU, which faults unless [`init`] enabled it.
+    fn x87_add(a: f64, b: f64) -> f64 {
+        let mut result = 0.0;
+        unsafe {
+            asm!(
+                "fld qword ptr [{a}]",
+                "fadd qword ptr [{b}]",
+                "fstp qword ptr [{result}]",
+                a = in(reg) &a,
+                b = in(reg) &b,
+                result = in(reg) &mut result,
+                out("st(0)") _,
+                options(nostack, preserves_flags),
+            );
+        }
+        result
+    }
+
+    #[test_case]
+    fn x87_instructions_run_after_init() {
+        init();
+        assert_eq!(x87_add(1.5, 2.25), 3.75);
+    }
+}
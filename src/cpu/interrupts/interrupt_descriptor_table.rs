@@ -36,9 +36,12 @@ pub(super) struct InterruptDescriptorTable {
     pub(super) virtualization_exception: Descriptor<Handler>,
     pub(super) control_protection_exception: Descriptor<HandlerWithError<usize>>,
     _reserved_10: [Reserved; 10],
-    pub(super) _available: [Descriptor<Handler>; 256 - 32],
+    pub(super) _available: [Descriptor<Handler>; 256 - FIRST_AVAILABLE_VECTOR as usize],
 }
 
+/// First vector that is not reserved for CPU exceptions.
+pub(super) const FIRST_AVAILABLE_VECTOR: u8 = 32;
+
 impl InterruptDescriptorTable {
     pub(super) const fn new() -> Self {
         Self {
@@ -65,10 +68,19 @@ impl InterruptDescriptorTable {
             virtualization_exception: Descriptor::missing(),
             control_protection_exception: Descriptor::missing(),
             _reserved_10: [Reserved::new(); 10],
-            _available: [Descriptor::missing(); 256 - 32],
+            _available: [Descriptor::missing(); 256 - FIRST_AVAILABLE_VECTOR as usize],
         }
     }
 
+    /// Install `handler` on the available (non-exception) `vector`.
+    pub(super) fn set_irq_handler(&mut self, vector: u8, handler: Handler) -> &mut Attributes {
+        assert!(
+            vector >= FIRST_AVAILABLE_VECTOR,
+            "Vector {vector} is reserved for CPU exceptions"
+        );
+        self._available[(vector - FIRST_AVAILABLE_VECTOR) as usize].set_handler(handler)
+    }
+
     /// SAFETY: Callers must ensure that the provided pointer is valid as long as the table is loaded
     pub(super) unsafe fn load(table: *const Self) {
         let idt_ptr = &DescriptorTablePointer {
@@ -125,6 +137,16 @@ impl<T> Descriptor<T> {
         &mut self.attributes
     }
 
+    /// Mark the descriptor present or missing, keeping its handler and attributes. Firing the
+    /// vector of a missing descriptor raises a segment not present fault.
+    pub(super) fn set_present(&mut self, present: bool) -> &mut Attributes {
+        if present {
+            self.attributes.set_present()
+        } else {
+            self.attributes.set_missing()
+        }
+    }
+
     pub(super) fn address(&self) -> VirtualAddress {
         VirtualAddress::from(
             self.address_1 as usize
@@ -254,7 +276,7 @@ impl Attributes {
     }
 
     pub(super) fn set_missing(&mut self) -> &mut Self {
-        self.attributes &= (Presence::Missing as u8) << 7;
+        self.attributes &= !(1 << 7);
         self
     }
 
@@ -268,6 +290,14 @@ impl Attributes {
         self
     }
 
+    /// Switch to the stack of entry `index` of the Interrupt Stack Table of the TSS when the
+    /// vector fires, in 1..=7, or keep the current stack with 0.
+    pub(super) fn set_stack_index(&mut self, index: u8) -> &mut Self {
+        assert!(index <= 7, "The Interrupt Stack Table has no entry {index}");
+        self.interrupt_stack_table = (self.interrupt_stack_table & !0b111) | index;
+        self
+    }
+
     pub(super) fn status(&self) -> Presence {
         (self.attributes >> 7).try_into().unwrap()
     }
@@ -291,3 +321,29 @@ impl Debug for Attributes {
             .finish()
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test_case]
+    fn toggling_presence_keeps_gate_type_and_privilege_level() {
+        let mut descriptor = Descriptor::<Handler>::missing();
+        descriptor
+            .set_handler_address(0x1234)
+            .set_gate_type(GateType::Trap)
+            .set_privilege_level(PrivilegeLevel::Ring3);
+
+        descriptor.set_present(false);
+        assert!(matches!(descriptor.attributes.status(), Presence::Missing));
+        let attributes = descriptor.set_present(true);
+
+        assert!(matches!(attributes.status(), Presence::Present));
+        assert!(matches!(attributes.gate_type(), GateType::Trap));
+        assert!(matches!(
+            attributes.privilege_level(),
+            PrivilegeLevel::Ring3
+        ));
+        assert!(descriptor.address() == VirtualAddress::from(0x1234));
+    }
+}
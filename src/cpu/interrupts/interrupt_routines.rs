@@ -1,37 +1,62 @@
 use crate::{
-    cpu::interrupts::{InterruptStackFrame as ISF, PageFaultError, SegmentSelectorError as SSErr},
+    cpu::apic,
+    cpu::interrupts::{
+        Handler, InterruptStackFrame as ISF, PageFaultError, SegmentSelectorError as SSErr,
+        registry::{self, AVAILABLE_VECTORS},
+        stats,
+    },
+    cpu::registers::Cr2,
+    drivers::serial,
+    memory::paging,
     terminal::logger,
 };
 
+// Faults return to the faulting instruction, which would fault again as nothing here can fix its
+// cause: they are fatal. Traps return to the next instruction and can be logged and resumed.
+
 pub(super) extern "x86-interrupt" fn divide_error_handler(stack_frame: ISF) {
+    stats::record(0);
     panic!("DIVIDE ERROR INTERRUPT stack_frame: {:#?}", stack_frame);
 }
 
 pub(super) extern "x86-interrupt" fn debug_handler(stack_frame: ISF) {
+    stats::record(1);
     logger::warning!("DEBUG TRAP stack_frame: {:#?}", stack_frame);
 }
 
+/// Non-maskable interrupts report hardware events and are not tied to the interrupted
+/// instruction, so execution can resume. They can arrive while any lock is held, including the
+/// ones of the logger sinks, so the report goes straight to the serial port.
 pub(super) extern "x86-interrupt" fn non_maskable_interrupt_handler(stack_frame: ISF) {
-    panic!("NON-MASKABLE INTERRUPT stack_frame: {:#?}", stack_frame);
+    stats::record(2);
+    serial::emergency_print(format_args!(
+        "NON-MASKABLE INTERRUPT stack_frame: {:#?}\n",
+        stack_frame
+    ));
 }
 
 pub(super) extern "x86-interrupt" fn breakpoint_handler(stack_frame: ISF) {
+    stats::record(3);
     logger::warning!("BREAKPOINT TRAP stack_frame: {:#?}", stack_frame);
 }
 
 pub(super) extern "x86-interrupt" fn overflow_handler(stack_frame: ISF) {
+    stats::record(4);
     logger::warning!("OVERFLOW TRAP stack_frame: {:#?}", stack_frame);
 }
 
 pub(super) extern "x86-interrupt" fn bound_range_exceeded_handler(stack_frame: ISF) {
+    stats::record(5);
     panic!("BOUND RANGE INTERRUPT stack_frame: {:#?}", stack_frame);
 }
 
 pub(super) extern "x86-interrupt" fn invalid_opcode_handler(stack_frame: ISF) {
+    stats::record(6);
     panic!("INVALID OPCODE INTERRUPT stack_frame: {:#?}", stack_frame);
 }
 
 pub(super) extern "x86-interrupt" fn device_not_available_handler(stack_frame: ISF) {
+    stats::record(7);
     panic!(
         "DEVICE NOT AVAILABLE INTERRUPT stack_frame: {:#?}",
         stack_frame
@@ -39,6 +64,7 @@ pub(super) extern "x86-interrupt" fn device_not_available_handler(stack_frame: I
 }
 
 pub(super) extern "x86-interrupt" fn double_fault_handler(stack_frame: ISF, error: usize) -> ! {
+    stats::record(8);
     panic!(
         "DOUBLE FAULT INTERRUPT stack_frame: {:#?}, error: {}",
         stack_frame, error
@@ -46,6 +72,7 @@ pub(super) extern "x86-interrupt" fn double_fault_handler(stack_frame: ISF, erro
 }
 
 pub(super) extern "x86-interrupt" fn invalid_tss_handler(stack_frame: ISF, error: SSErr) {
+    stats::record(10);
     panic!(
         "INVALID TSS INTERRUPT stack_frame: {:#?}, error: {:?}",
         stack_frame, error
@@ -53,6 +80,7 @@ pub(super) extern "x86-interrupt" fn invalid_tss_handler(stack_frame: ISF, error
 }
 
 pub(super) extern "x86-interrupt" fn segment_not_present_handler(stack_frame: ISF, error: SSErr) {
+    stats::record(11);
     panic!(
         "SEGMENT NOT PRESENT INTERRUPT stack_frame: {:#?}, error: {:?}",
         stack_frame, error
@@ -60,6 +88,7 @@ pub(super) extern "x86-interrupt" fn segment_not_present_handler(stack_frame: IS
 }
 
 pub(super) extern "x86-interrupt" fn stack_segment_fault_handler(stack_frame: ISF, error: SSErr) {
+    stats::record(12);
     panic!(
         "STACK SEGMENT FAULT INTERRUPT stack_frame: {:#?}, error: {:?}",
         stack_frame, error
@@ -67,20 +96,31 @@ pub(super) extern "x86-interrupt" fn stack_segment_fault_handler(stack_frame: IS
 }
 
 pub(super) extern "x86-interrupt" fn general_protx_fault_handler(stack_frame: ISF, error: SSErr) {
+    stats::record(13);
     panic!(
-        "GENERAL PROTECTION FAULT INTERRUPT stack_frame: {:#?}, error: {:?}",
-        stack_frame, error
+        "GENERAL PROTECTION FAULT INTERRUPT at {:?} in segment {:?}, selector: {}, stack_frame: {:#?}",
+        stack_frame.instruction_pointer(),
+        stack_frame.code_segment,
+        error,
+        stack_frame
     );
 }
 
 pub(super) extern "x86-interrupt" fn page_fault_handler(stack_frame: ISF, error: PageFaultError) {
+    stats::record(14);
+    let address = Cr2::read();
+    // The mapping is part of the panic message, so it is reported whatever the log level is.
     panic!(
-        "PAGE FAULT INTERRUPT stack_frame: {:#?}, error: {:?}",
-        stack_frame, error
+        "PAGE FAULT INTERRUPT at {:?} stack_frame: {:#?}, error: {:?}\n{}",
+        address,
+        stack_frame,
+        error,
+        paging::Translation(address)
     );
 }
 
 pub(super) extern "x86-interrupt" fn x87_floating_point_exception_handler(stack_frame: ISF) {
+    stats::record(16);
     panic!(
         "x87 FLOATING POINT EXCEPTION INTERRUPT stack_frame: {:#?}",
         stack_frame
@@ -88,6 +128,7 @@ pub(super) extern "x86-interrupt" fn x87_floating_point_exception_handler(stack_
 }
 
 pub(super) extern "x86-interrupt" fn alignement_check_handler(stack_frame: ISF, error: usize) {
+    stats::record(17);
     panic!(
         "ALIGNMENT CHECK INTERRUPT stack_frame: {:#?}, error: {}",
         stack_frame, error
@@ -95,10 +136,12 @@ pub(super) extern "x86-interrupt" fn alignement_check_handler(stack_frame: ISF,
 }
 
 pub(super) extern "x86-interrupt" fn machine_check_handler(stack_frame: ISF) -> ! {
+    stats::record(18);
     panic!("MACHINE CHECK INTERRUPT stack_frame: {:#?}", stack_frame,);
 }
 
 pub(super) extern "x86-interrupt" fn simd_floating_point_handler(stack_frame: ISF) {
+    stats::record(19);
     panic!(
         "SIMD FLOATING POINT INTERRUPT stack_frame: {:#?}",
         stack_frame,
@@ -106,6 +149,7 @@ pub(super) extern "x86-interrupt" fn simd_floating_point_handler(stack_frame: IS
 }
 
 pub(super) extern "x86-interrupt" fn virtualization_exception_handler(stack_frame: ISF) {
+    stats::record(20);
     panic!(
         "VIRTUALIZATION EXCEPTION INTERRUPT stack_frame: {:#?}",
         stack_frame,
@@ -113,8 +157,60 @@ pub(super) extern "x86-interrupt" fn virtualization_exception_handler(stack_fram
 }
 
 pub(super) extern "x86-interrupt" fn ctrl_protx_exception_handler(stack_frame: ISF, error: usize) {
+    stats::record(21);
     panic!(
         "CONTROL PROTECTION EXCEPTION INTERRUPT stack_frame: {:#?}, error: {}",
         stack_frame, error
     );
 }
+
+/// Spurious interrupts from the local APIC must not be acknowledged.
+pub(super) extern "x86-interrupt" fn spurious_interrupt_handler(_stack_frame: ISF) {
+    stats::record(apic::SPURIOUS_VECTOR);
+}
+
+/// Entry point of the available vectors, forwarding the interrupt to the handler registered for
+/// `VECTOR`.
+extern "x86-interrupt" fn interrupt_trampoline<const VECTOR: u8>(stack_frame: ISF) {
+    registry::dispatch(VECTOR, &stack_frame);
+}
+
+/// Generates the table of trampolines for the listed vectors.
+macro_rules! interrupt_trampolines {
+    ($($vector:literal),* $(,)?) => {
+        /// Trampolines of each available vector, indexed by `vector - FIRST_AVAILABLE_VECTOR`.
+        pub(super) const INTERRUPT_TRAMPOLINES: [Handler; AVAILABLE_VECTORS] =
+            [$(interrupt_trampoline::<$vector>),*];
+    };
+}
+
+interrupt_trampolines! {
+    32, 33, 34, 35, 36, 37, 38, 39, 40, 41, 42, 43, 44, 45, 46, 47,
+    48, 49, 50, 51, 52, 53, 54, 55, 56, 57, 58, 59, 60, 61, 62, 63,
+    64, 65, 66, 67, 68, 69, 70, 71, 72, 73, 74, 75, 76, 77, 78, 79,
+    80, 81, 82, 83, 84, 85, 86, 87, 88, 89, 90, 91, 92, 93, 94, 95,
+    96, 97, 98, 99, 100, 101, 102, 103, 104, 105, 106, 107, 108, 109, 110, 111,
+    112, 113, 114, 115, 116, 117, 118, 119, 120, 121, 122, 123, 124, 125, 126, 127,
+    128, 129, 130, 131, 132, 133, 134, 135, 136, 137, 138, 139, 140, 141, 142, 143,
+    144, 145, 146, 147, 148, 149, 150, 151, 152, 153, 154, 155, 156, 157, 158, 159,
+    160, 161, 162, 163, 164, 165, 166, 167, 168, 169, 170, 171, 172, 173, 174, 175,
+    176, 177, 178, 179, 180, 181, 182, 183, 184, 185, 186, 187, 188, 189, 190, 191,
+    192, 193, 194, 195, 196, 197, 198, 199, 200, 201, 202, 203, 204, 205, 206, 207,
+    208, 209, 210, 211, 212, 213, 214, 215, 216, 217, 218, 219, 220, 221, 222, 223,
+    224, 225, 226, 227, 228, 229, 230, 231, 232, 233, 234, 235, 236, 237, 238, 239,
+    240, 241, 242, 243, 244, 245, 246, 247, 248, 249, 250, 251, 252, 253, 254, 255,
+}
+
+#[cfg(test)]
+mod tests {
+    use core::arch::asm;
+
+    use crate::cpu::interrupts::stats;
+
+    #[test_case]
+    fn breakpoint_resumes_execution() {
+        let count = stats::fire_count(3);
+        unsafe { asm!("int3", options(nomem, nostack)) };
+        assert_eq!(stats::fire_count(3), count + 1);
+    }
+}
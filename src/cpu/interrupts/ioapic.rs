@@ -0,0 +1,342 @@
+//! Driver for the I/O APIC, which routes external interrupts to local APICs.
+//!
+//! Legacy ISA IRQs are identity-mapped to global system interrupts (GSIs) unless the firmware
+//! reports an [`InterruptSourceOverride`] for them.
+
+use core::ptr;
+
+use spin::{Mutex, Once};
+
+use crate::acpi::{self, Madt, MadtEntry};
+use crate::cpu::apic;
+use crate::memory::{PhysicalAddress, VirtualAddress, paging};
+use crate::terminal::logger;
+
+/// (IOREGSEL) Offset of the register selecting the register accessed through [`IO_WINDOW`].
+const REGISTER_SELECT: usize = 0x00;
+/// (IOWIN) Offset of the window to the selected register.
+const IO_WINDOW: usize = 0x10;
+/// Size of the memory-mapped register block.
+const REGISTERS_SIZE: usize = 0x20;
+
+/// (IOAPICVER) Version register, also holds the index of the last redirection entry.
+const VERSION_REGISTER: u8 = 0x01;
+/// (IOREDTBL) First register of the redirection table, each entry spans two registers.
+const REDIRECTION_TABLE: u8 = 0x10;
+
+static IO_APIC: Once<IoApic> = Once::new();
+/// Table the interrupt source overrides of the legacy ISA IRQs are read from.
+static MADT: Once<Madt> = Once::new();
+
+/// Initialize the I/O APIC handling the legacy ISA IRQs, as described by the MADT. Every
+/// redirection entry starts masked, legacy IRQs are then delivered through the I/O APIC as they
+/// are enabled with [`enable_legacy_irq`]. Does nothing but log a warning if the machine has no
+/// such I/O APIC, leaving the legacy IRQs to the PIC.
+pub fn init() {
+    let Some(madt) = acpi::madt() else {
+        logger::warning!("No MADT found, legacy IRQs are delivered by the PIC");
+        return;
+    };
+    // The legacy IRQs are the first global system interrupts.
+    let Some((address, gsi_base)) = madt.entries().find_map(|entry| match entry {
+        MadtEntry::IoApic {
+            address, gsi_base, ..
+        } if gsi_base == 0 => Some((address, gsi_base)),
+        _ => None,
+    }) else {
+        logger::warning!("No I/O APIC found, legacy IRQs are delivered by the PIC");
+        return;
+    };
+
+    MADT.call_once(|| madt);
+    let io_apic = IO_APIC.call_once(|| IoApic::new(address, gsi_base));
+    for gsi in gsi_base..gsi_base + io_apic.entry_count() as u32 {
+        io_apic.set_masked(gsi, true);
+    }
+    logger::info!(
+        "I/O APIC enabled at {:?} with {} redirection entries",
+        address,
+        io_apic.entry_count()
+    );
+}
+
+/// Returns whether the I/O APIC has been initialized and delivers the legacy IRQs.
+#[inline]
+pub fn is_initialized() -> bool {
+    IO_APIC.is_completed()
+}
+
+/// Returns the initialized I/O APIC.
+pub fn get() -> &'static IoApic {
+    IO_APIC
+        .get()
+        .expect("Cannot access the I/O APIC before it is initialized")
+}
+
+/// Route the legacy ISA `irq` to the local APIC of the current processor on `vector`, honoring
+/// the interrupt source overrides of the MADT, and unmask it.
+pub fn enable_legacy_irq(irq: u8, vector: u8) {
+    get().route_legacy_irq(irq, apic::id(), vector, legacy_overrides());
+}
+
+/// Mask the legacy ISA `irq`.
+pub fn disable_legacy_irq(irq: u8) {
+    get().set_masked(legacy_route(irq, legacy_overrides()).gsi, true);
+}
+
+/// Returns the interrupt source overrides reported by the MADT.
+fn legacy_overrides() -> impl Iterator<Item = InterruptSourceOverride> {
+    MADT.get()
+        .into_iter()
+        .flat_map(Madt::interrupt_source_overrides)
+}
+
+/// Returns how the legacy ISA `irq` is wired, from the firmware-provided `overrides`. ISA IRQs
+/// default to edge-triggered, active-high lines identity-mapped to global system interrupts.
+fn legacy_route(
+    irq: u8,
+    overrides: impl IntoIterator<Item = InterruptSourceOverride>,
+) -> InterruptSourceOverride {
+    overrides
+        .into_iter()
+        .find(|o| o.irq == irq)
+        .unwrap_or(InterruptSourceOverride {
+            irq,
+            gsi: irq as u32,
+            polarity: Polarity::ActiveHigh,
+            trigger_mode: TriggerMode::Edge,
+        })
+}
+
+/// Pin polarity of an interrupt line.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Polarity {
+    ActiveHigh = 0,
+    ActiveLow = 1,
+}
+
+/// Trigger mode of an interrupt line.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum TriggerMode {
+    Edge = 0,
+    Level = 1,
+}
+
+/// Describes how a legacy ISA IRQ is actually wired to the I/O APIC, as reported by the firmware.
+#[derive(Clone, Copy, Debug)]
+pub struct InterruptSourceOverride {
+    pub irq: u8,
+    pub gsi: u32,
+    pub polarity: Polarity,
+    pub trigger_mode: TriggerMode,
+}
+
+/// A 64-bit entry of the redirection table.
+#[derive(Clone, Copy, PartialEq, Eq)]
+#[repr(transparent)]
+pub struct RedirectionEntry(u64);
+
+impl RedirectionEntry {
+    const VECTOR_MASK: u64 = 0xFF;
+    const POLARITY_SHIFT: u64 = 13;
+    const TRIGGER_MODE_SHIFT: u64 = 15;
+    const MASKED: u64 = 1 << 16;
+    const DESTINATION_SHIFT: u64 = 56;
+
+    /// Creates an unmasked entry delivering `vector` to the local APIC with id `destination`,
+    /// using fixed delivery and physical destination modes.
+    pub const fn new(
+        vector: u8,
+        destination: u8,
+        polarity: Polarity,
+        trigger_mode: TriggerMode,
+    ) -> Self {
+        Self(
+            vector as u64
+                | (polarity as u64) << Self::POLARITY_SHIFT
+                | (trigger_mode as u64) << Self::TRIGGER_MODE_SHIFT
+                | (destination as u64) << Self::DESTINATION_SHIFT,
+        )
+    }
+
+    pub const fn vector(&self) -> u8 {
+        (self.0 & Self::VECTOR_MASK) as u8
+    }
+
+    pub const fn destination(&self) -> u8 {
+        (self.0 >> Self::DESTINATION_SHIFT) as u8
+    }
+
+    pub const fn polarity(&self) -> Polarity {
+        match (self.0 >> Self::POLARITY_SHIFT) & 1 {
+            0 => Polarity::ActiveHigh,
+            _ => Polarity::ActiveLow,
+        }
+    }
+
+    pub const fn trigger_mode(&self) -> TriggerMode {
+        match (self.0 >> Self::TRIGGER_MODE_SHIFT) & 1 {
+            0 => TriggerMode::Edge,
+            _ => TriggerMode::Level,
+        }
+    }
+
+    pub const fn is_masked(&self) -> bool {
+        self.0 & Self::MASKED != 0
+    }
+
+    pub const fn with_masked(self, masked: bool) -> Self {
+        if masked {
+            Self(self.0 | Self::MASKED)
+        } else {
+            Self(self.0 & !Self::MASKED)
+        }
+    }
+}
+
+impl core::fmt::Debug for RedirectionEntry {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        f.debug_struct("RedirectionEntry")
+            .field("vector", &self.vector())
+            .field("destination", &self.destination())
+            .field("polarity", &self.polarity())
+            .field("trigger mode", &self.trigger_mode())
+            .field("masked", &self.is_masked())
+            .finish()
+    }
+}
+
+pub struct IoApic {
+    registers: Mutex<VirtualAddress>,
+    gsi_base: u32,
+    entry_count: u8,
+}
+
+impl IoApic {
+    fn new(physical: PhysicalAddress, gsi_base: u32) -> Self {
+        let mut io_apic = Self {
+            registers: Mutex::new(paging::map_mmio(physical, REGISTERS_SIZE)),
+            gsi_base,
+            entry_count: 0,
+        };
+        io_apic.entry_count = ((io_apic.read(VERSION_REGISTER) >> 16) & 0xFF) as u8 + 1;
+        io_apic
+    }
+
+    /// Number of entries in the redirection table.
+    #[inline]
+    pub fn entry_count(&self) -> u8 {
+        self.entry_count
+    }
+
+    /// Returns true if the global system interrupt `gsi` is handled by this I/O APIC.
+    #[inline]
+    pub fn handles(&self, gsi: u32) -> bool {
+        (self.gsi_base..self.gsi_base + self.entry_count as u32).contains(&gsi)
+    }
+
+    fn read(&self, register: u8) -> u32 {
+        let base = self.registers.lock();
+        unsafe {
+            ptr::write_volatile((*base + REGISTER_SELECT).to_ptr::<u32>(), register as u32);
+            ptr::read_volatile((*base + IO_WINDOW).to_ptr::<u32>())
+        }
+    }
+
+    fn write(&self, register: u8, value: u32) {
+        let base = self.registers.lock();
+        unsafe {
+            ptr::write_volatile((*base + REGISTER_SELECT).to_ptr::<u32>(), register as u32);
+            ptr::write_volatile((*base + IO_WINDOW).to_ptr::<u32>(), value);
+        }
+    }
+
+    /// Returns the first register of the redirection entry for `gsi`.
+    fn entry_register(&self, gsi: u32) -> u8 {
+        let index = gsi.wrapping_sub(self.gsi_base);
+        assert!(
+            gsi >= self.gsi_base && index < self.entry_count as u32,
+            "GSI {gsi} is not handled by this I/O APIC"
+        );
+        let register = REDIRECTION_TABLE as u32 + 2 * index;
+        u8::try_from(register).expect("The redirection entry is past the last register")
+    }
+
+    pub fn redirection_entry(&self, gsi: u32) -> RedirectionEntry {
+        let register = self.entry_register(gsi);
+        let low = self.read(register) as u64;
+        let high = self.read(register + 1) as u64;
+        RedirectionEntry(high << 32 | low)
+    }
+
+    pub fn set_redirection_entry(&self, gsi: u32, entry: RedirectionEntry) {
+        let register = self.entry_register(gsi);
+        // Mask the entry while it is being updated to avoid delivering a half-written entry
+        self.write(register, (entry.0 as u32) | RedirectionEntry::MASKED as u32);
+        self.write(register + 1, (entry.0 >> 32) as u32);
+        self.write(register, entry.0 as u32);
+    }
+
+    /// Route the global system interrupt `gsi` to the local APIC with id `destination` on
+    /// `vector`, and unmask it.
+    pub fn route(
+        &self,
+        gsi: u32,
+        destination: u8,
+        vector: u8,
+        polarity: Polarity,
+        trigger_mode: TriggerMode,
+    ) {
+        let entry = RedirectionEntry::new(vector, destination, polarity, trigger_mode);
+        self.set_redirection_entry(gsi, entry);
+    }
+
+    /// Route the legacy ISA `irq` to the local APIC with id `destination` on `vector`, honoring
+    /// the firmware-provided `overrides`.
+    pub fn route_legacy_irq(
+        &self,
+        irq: u8,
+        destination: u8,
+        vector: u8,
+        overrides: impl IntoIterator<Item = InterruptSourceOverride>,
+    ) {
+        let route = legacy_route(irq, overrides);
+        self.route(
+            route.gsi,
+            destination,
+            vector,
+            route.polarity,
+            route.trigger_mode,
+        );
+    }
+
+    /// Mask or unmask the global system interrupt `gsi`.
+    pub fn set_masked(&self, gsi: u32, masked: bool) {
+        let entry = self.redirection_entry(gsi);
+        self.set_redirection_entry(gsi, entry.with_masked(masked));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Base address of the I/O APIC emulated by QEMU.
+    const QEMU_IO_APIC: usize = 0xFEC0_0000;
+
+    #[test_case]
+    fn route_writes_the_redirection_entry() {
+        let io_apic = IoApic::new(PhysicalAddress::from(QEMU_IO_APIC), 0);
+        // The last entry is not wired to any device emulated by QEMU.
+        let gsi = io_apic.entry_count() as u32 - 1;
+
+        io_apic.route(gsi, 0, 0x40, Polarity::ActiveLow, TriggerMode::Level);
+        let entry = io_apic.redirection_entry(gsi);
+        io_apic.set_masked(gsi, true);
+
+        assert_eq!(
+            entry,
+            RedirectionEntry::new(0x40, 0, Polarity::ActiveLow, TriggerMode::Level)
+        );
+    }
+}
@@ -1,13 +1,20 @@
 mod interrupt_descriptor_table;
 mod interrupt_routines;
+pub mod ioapic;
+pub mod pic;
+mod registry;
+mod stats;
 
+use core::arch::asm;
 use core::fmt;
 
-use interrupt_descriptor_table::InterruptDescriptorTable;
+use interrupt_descriptor_table::{FIRST_AVAILABLE_VECTOR, InterruptDescriptorTable};
 use interrupt_routines::*;
+pub use registry::{register, unregister};
+pub use stats::{log_stats, stats};
 
 use crate::cpu::interrupts::interrupt_descriptor_table::GateType;
-use crate::cpu::{PrivilegeLevel, registers::RFlags, segments::SegmentSelector};
+use crate::cpu::{PrivilegeLevel, apic, registers::RFlags, segments::SegmentSelector};
 use crate::memory::VirtualAddress;
 
 static mut IDT: InterruptDescriptorTable = InterruptDescriptorTable::new();
@@ -22,6 +29,38 @@ pub struct InterruptStackFrame {
     stack_segment: SegmentSelector,
 }
 
+/// The frame is pushed by the processor on the stack of the handler and popped by `iretq` when it
+/// returns. The `x86-interrupt` ABI hands it to the handler in place rather than as a copy, so a
+/// handler taking it as `mut stack_frame` can change where the interrupted code resumes.
+impl InterruptStackFrame {
+    /// Returns the address of the instruction the interrupted code resumes at.
+    pub fn instruction_pointer(&self) -> VirtualAddress {
+        self.instruction_pointer
+    }
+
+    /// Returns the stack pointer of the interrupted code.
+    pub fn stack_pointer(&self) -> VirtualAddress {
+        self.stack_pointer
+    }
+
+    /// Resume the interrupted code at `address`, e.g. past a faulting instruction. The write is
+    /// volatile, as the compiler would otherwise see a dead store to an argument.
+    ///
+    /// SAFETY: `address` must be code that is valid to run with the registers and stack of the
+    /// interrupted code.
+    pub unsafe fn set_instruction_pointer(&mut self, address: VirtualAddress) {
+        unsafe { (&raw mut self.instruction_pointer).write_volatile(address) };
+    }
+
+    /// Resume the interrupted code with `address` as its stack pointer.
+    ///
+    /// SAFETY: `address` must point to a stack that is valid for the code resumed, in the same
+    /// state as the one it expects.
+    pub unsafe fn set_stack_pointer(&mut self, address: VirtualAddress) {
+        unsafe { (&raw mut self.stack_pointer).write_volatile(address) };
+    }
+}
+
 bitflags::bitflags! {
     #[derive(Copy, Clone)]
     #[repr(transparent)]
@@ -102,10 +141,12 @@ impl SegmentSelectorError {
         (self.0 >> 1 & 0b11).try_into().unwrap()
     }
 
+    /// Index of the selector in its table.
     pub const fn index(&self) -> u16 {
-        (self.0 & 0xFFF8) as u16
+        ((self.0 >> 3) & 0x1FFF) as u16
     }
 
+    /// Indicates whether the exception is related to a selector, the error code is 0 otherwise.
     pub const fn is_present(&self) -> bool {
         self.0 != 0
     }
@@ -113,7 +154,7 @@ impl SegmentSelectorError {
 
 impl fmt::Debug for SegmentSelectorError {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
-        if self.is_present() {
+        if !self.is_present() {
             f.write_str("SegmentSelectorError::None")
         } else {
             f.debug_struct("SegmentSelectorError")
@@ -125,6 +166,19 @@ impl fmt::Debug for SegmentSelectorError {
     }
 }
 
+impl fmt::Display for SegmentSelectorError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if !self.is_present() {
+            return f.write_str("none");
+        }
+        write!(f, "{:?} entry {}", self.table(), self.index())?;
+        if self.external() {
+            f.write_str(" (external event)")?;
+        }
+        Ok(())
+    }
+}
+
 type Handler = extern "x86-interrupt" fn(InterruptStackFrame);
 type HandlerWithError<T> = extern "x86-interrupt" fn(InterruptStackFrame, error: T);
 type DivergingHandler = extern "x86-interrupt" fn(InterruptStackFrame) -> !;
@@ -198,8 +252,100 @@ pub fn init() {
     idt.control_protection_exception
         .set_handler(ctrl_protx_exception_handler);
 
+    pic::remap(pic::MASTER_OFFSET, pic::SLAVE_OFFSET);
+    pic::mask_all();
+
+    for (vector, handler) in (FIRST_AVAILABLE_VECTOR..=u8::MAX).zip(INTERRUPT_TRAMPOLINES) {
+        idt.set_irq_handler(vector, handler);
+    }
+
+    idt.set_irq_handler(apic::SPURIOUS_VECTOR, spurious_interrupt_handler);
+
     unsafe {
         IDT = idt;
-        InterruptDescriptorTable::load(&raw const IDT);
+    }
+    load();
+}
+
+/// Unmask the legacy ISA `irq`, delivered on [`pic::vector_for`] to the current processor:
+/// through the I/O APIC once it is initialized, through the PIC otherwise.
+pub fn enable_legacy_irq(irq: u8) {
+    if ioapic::is_initialized() {
+        ioapic::enable_legacy_irq(irq, pic::vector_for(irq));
+    } else {
+        pic::clear_mask(irq);
+    }
+}
+
+/// Mask the legacy ISA `irq` on the controller delivering it.
+pub fn disable_legacy_irq(irq: u8) {
+    if ioapic::is_initialized() {
+        ioapic::disable_legacy_irq(irq);
+    } else {
+        pic::set_mask(irq);
+    }
+}
+
+/// Load the interrupt descriptor table built by [`init`] on the current processor.
+pub fn load() {
+    unsafe { InterruptDescriptorTable::load(&raw const IDT) };
+}
+
+/// Enable maskable hardware interrupts on the current processor.
+#[inline(always)]
+pub fn enable() {
+    unsafe { asm!("sti", options(nomem, nostack)) };
+}
+
+/// Disable maskable hardware interrupts on the current processor.
+#[inline(always)]
+pub fn disable() {
+    unsafe { asm!("cli", options(nomem, nostack)) };
+}
+
+/// Returns whether maskable hardware interrupts are enabled on the current processor.
+#[inline(always)]
+pub fn are_enabled() -> bool {
+    RFlags::read().contains(RFlags::INTERRUPT_ENABLE)
+}
+
+/// Run `f` with maskable hardware interrupts disabled, then restore their previous state.
+#[inline]
+pub fn without_interrupts<F, R>(f: F) -> R
+where
+    F: FnOnce() -> R,
+{
+    let were_enabled = are_enabled();
+    if were_enabled {
+        disable();
+    }
+
+    let result = f();
+
+    if were_enabled {
+        enable();
+    }
+    result
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test_case]
+    fn segment_selector_error_decodes_fields() {
+        // External event while delivering vector 13 through the IDT.
+        let error = SegmentSelectorError((13 << 3) | (0b01 << 1) | 1);
+        assert!(error.is_present());
+        assert!(error.external());
+        assert!(matches!(error.table(), SelectorErrorTable::IDT));
+        assert_eq!(error.index(), 13);
+
+        let error = SegmentSelectorError((5 << 3) | (0b10 << 1));
+        assert!(!error.external());
+        assert!(matches!(error.table(), SelectorErrorTable::LDT));
+        assert_eq!(error.index(), 5);
+
+        assert!(!SegmentSelectorError(0).is_present());
     }
 }
@@ -0,0 +1,149 @@
+//! Driver for the legacy 8259 Programmable Interrupt Controller (PIC).
+//!
+//! Two PICs are chained together: the master handles IRQs 0 to 7 and the slave handles IRQs 8 to
+//! 15, cascading through the master's IRQ 2. By default, the PICs deliver their interrupts on
+//! vectors 0x08 to 0x0F and 0x70 to 0x77, the first of which overlap with CPU exceptions, so they
+//! must be remapped before any hardware interrupt is enabled.
+
+use core::sync::atomic::{AtomicU8, Ordering};
+
+use crate::cpu::port::{inb, io_wait, outb};
+
+const MASTER_COMMAND: u16 = 0x20;
+const MASTER_DATA: u16 = 0x21;
+const SLAVE_COMMAND: u16 = 0xA0;
+const SLAVE_DATA: u16 = 0xA1;
+
+/// (ICW1) Indicates that ICW4 will be sent.
+const ICW1_ICW4: u8 = 0x01;
+/// (ICW1) Starts the initialization sequence.
+const ICW1_INIT: u8 = 0x10;
+/// (ICW3) Tells the master PIC that the slave is connected to its IRQ 2.
+const ICW3_MASTER_SLAVE_LINE: u8 = 1 << CASCADE_IRQ;
+/// (ICW3) Tells the slave PIC its cascade identity.
+const ICW3_SLAVE_IDENTITY: u8 = CASCADE_IRQ;
+/// (ICW4) Use 8086/88 mode.
+const ICW4_8086: u8 = 0x01;
+
+/// (OCW2) Non-specific end of interrupt command.
+const END_OF_INTERRUPT: u8 = 0x20;
+
+/// Master IRQ line the slave PIC is connected to.
+pub const CASCADE_IRQ: u8 = 2;
+/// Number of IRQ lines handled by each PIC.
+pub const LINES_PER_PIC: u8 = 8;
+/// Total number of IRQ lines handled by the chained PICs.
+pub const IRQ_COUNT: u8 = 2 * LINES_PER_PIC;
+
+/// Vector offset used for the master PIC, right after the CPU exceptions.
+pub const MASTER_OFFSET: u8 = 32;
+/// Vector offset used for the slave PIC, right after the master PIC.
+pub const SLAVE_OFFSET: u8 = MASTER_OFFSET + LINES_PER_PIC;
+
+static MASTER_VECTOR_OFFSET: AtomicU8 = AtomicU8::new(0x08);
+static SLAVE_VECTOR_OFFSET: AtomicU8 = AtomicU8::new(0x70);
+
+/// Remap the master PIC to vectors `offset1..offset1 + 8` and the slave PIC to vectors
+/// `offset2..offset2 + 8`. The interrupt masks are preserved.
+pub fn remap(offset1: u8, offset2: u8) {
+    unsafe {
+        let master_mask = inb(MASTER_DATA);
+        let slave_mask = inb(SLAVE_DATA);
+
+        outb(MASTER_COMMAND, ICW1_INIT | ICW1_ICW4);
+        io_wait();
+        outb(SLAVE_COMMAND, ICW1_INIT | ICW1_ICW4);
+        io_wait();
+
+        outb(MASTER_DATA, offset1);
+        io_wait();
+        outb(SLAVE_DATA, offset2);
+        io_wait();
+
+        outb(MASTER_DATA, ICW3_MASTER_SLAVE_LINE);
+        io_wait();
+        outb(SLAVE_DATA, ICW3_SLAVE_IDENTITY);
+        io_wait();
+
+        outb(MASTER_DATA, ICW4_8086);
+        io_wait();
+        outb(SLAVE_DATA, ICW4_8086);
+        io_wait();
+
+        outb(MASTER_DATA, master_mask);
+        outb(SLAVE_DATA, slave_mask);
+    }
+
+    MASTER_VECTOR_OFFSET.store(offset1, Ordering::Relaxed);
+    SLAVE_VECTOR_OFFSET.store(offset2, Ordering::Relaxed);
+}
+
+/// Returns the vector on which the interrupt for the specified `irq` line is delivered.
+pub fn vector_for(irq: u8) -> u8 {
+    assert!(irq < IRQ_COUNT, "Invalid IRQ line: {irq}");
+    if irq < LINES_PER_PIC {
+        MASTER_VECTOR_OFFSET.load(Ordering::Relaxed) + irq
+    } else {
+        SLAVE_VECTOR_OFFSET.load(Ordering::Relaxed) + irq - LINES_PER_PIC
+    }
+}
+
+/// Returns the IRQ line whose interrupts are delivered on `vector`, if any.
+pub fn irq_for(vector: u8) -> Option<u8> {
+    let master = MASTER_VECTOR_OFFSET.load(Ordering::Relaxed);
+    let slave = SLAVE_VECTOR_OFFSET.load(Ordering::Relaxed);
+    if (master..master + LINES_PER_PIC).contains(&vector) {
+        Some(vector - master)
+    } else if (slave..slave + LINES_PER_PIC).contains(&vector) {
+        Some(vector - slave + LINES_PER_PIC)
+    } else {
+        None
+    }
+}
+
+/// Signal the end of the interrupt for the specified `irq` line. Interrupts originating from the
+/// slave PIC must be acknowledged on both PICs.
+pub fn end_of_interrupt(irq: u8) {
+    unsafe {
+        if irq >= LINES_PER_PIC {
+            outb(SLAVE_COMMAND, END_OF_INTERRUPT);
+        }
+        outb(MASTER_COMMAND, END_OF_INTERRUPT);
+    }
+}
+
+/// Returns the data port and the bit within its mask corresponding to the `irq` line.
+fn mask_location(irq: u8) -> (u16, u8) {
+    assert!(irq < IRQ_COUNT, "Invalid IRQ line: {irq}");
+    if irq < LINES_PER_PIC {
+        (MASTER_DATA, irq)
+    } else {
+        (SLAVE_DATA, irq - LINES_PER_PIC)
+    }
+}
+
+/// Mask the specified `irq` line, preventing the PIC from raising its interrupt.
+pub fn set_mask(irq: u8) {
+    let (port, bit) = mask_location(irq);
+    unsafe {
+        let mask = inb(port) | (1 << bit);
+        outb(port, mask);
+    }
+}
+
+/// Unmask the specified `irq` line, allowing the PIC to raise its interrupt.
+pub fn clear_mask(irq: u8) {
+    let (port, bit) = mask_location(irq);
+    unsafe {
+        let mask = inb(port) & !(1 << bit);
+        outb(port, mask);
+    }
+}
+
+/// Mask every IRQ line except the cascade line, so that the slave PIC can still be unmasked
+/// line by line.
+pub fn mask_all() {
+    for irq in (0..IRQ_COUNT).filter(|&irq| irq != CASCADE_IRQ) {
+        set_mask(irq);
+    }
+}
@@ -0,0 +1,67 @@
+//! Runtime registry of the handlers of the available (non-exception) interrupt vectors.
+//!
+//! Every available vector is routed through a trampoline to [`dispatch`], which calls the
+//! handler registered for the vector and signals the end of the interrupt, so drivers only have
+//! to [`register`] a plain Rust function.
+
+use crate::cpu::interrupts::interrupt_descriptor_table::FIRST_AVAILABLE_VECTOR;
+use crate::cpu::interrupts::{InterruptStackFrame, ioapic, pic, stats};
+use crate::cpu::{apic, sched};
+use crate::sync::IrqMutex;
+
+/// Number of vectors that can be handled through the registry.
+pub(super) const AVAILABLE_VECTORS: usize = 256 - FIRST_AVAILABLE_VECTOR as usize;
+
+/// Function called in interrupt context when its vector fires.
+pub type InterruptHandler = fn(&InterruptStackFrame);
+
+static HANDLERS: IrqMutex<[Option<InterruptHandler>; AVAILABLE_VECTORS]> =
+    IrqMutex::new([None; AVAILABLE_VECTORS]);
+
+/// Returns the index of `vector` in the registry table.
+fn index(vector: u8) -> usize {
+    assert!(
+        vector >= FIRST_AVAILABLE_VECTOR,
+        "Vector {vector} is reserved for CPU exceptions"
+    );
+    (vector - FIRST_AVAILABLE_VECTOR) as usize
+}
+
+/// Register `handler` to be called whenever `vector` fires. The end of the interrupt is signaled
+/// after the handler returns, handlers must not do it themselves.
+///
+/// Handlers run in interrupt context: they must be short and must not wait on locks that may be
+/// held by the interrupted code.
+pub fn register(vector: u8, handler: InterruptHandler) {
+    let mut handlers = HANDLERS.lock();
+    let slot = &mut handlers[index(vector)];
+    assert!(
+        slot.is_none(),
+        "A handler is already registered for vector {vector}"
+    );
+    *slot = Some(handler);
+}
+
+/// Remove the handler registered for `vector` and returns it, if any.
+pub fn unregister(vector: u8) -> Option<InterruptHandler> {
+    HANDLERS.lock()[index(vector)].take()
+}
+
+/// Call the handler registered for `vector`, then acknowledge the interrupt to the controller
+/// that delivered it and let the scheduler preempt the interrupted task.
+pub(super) fn dispatch(vector: u8, stack_frame: &InterruptStackFrame) {
+    stats::record(vector);
+
+    let handler = HANDLERS.lock()[index(vector)];
+    match handler {
+        Some(handler) => handler(stack_frame),
+        None => stats::record_unhandled(vector),
+    }
+
+    // Legacy IRQs are delivered through the local APIC once the I/O APIC is initialized.
+    match pic::irq_for(vector).filter(|_| !ioapic::is_initialized()) {
+        Some(irq) => pic::end_of_interrupt(irq),
+        None => apic::end_of_interrupt(),
+    }
+    sched::preempt_if_pending();
+}
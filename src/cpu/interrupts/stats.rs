@@ -0,0 +1,49 @@
+//! Number of times each interrupt vector fired since boot, to diagnose interrupt storms.
+
+use core::sync::atomic::{AtomicU64, Ordering};
+
+use crate::terminal::logger;
+
+static COUNTS: [AtomicU64; 256] = [const { AtomicU64::new(0) }; 256];
+/// Number of times each vector fired without a registered handler.
+static UNHANDLED_COUNTS: [AtomicU64; 256] = [const { AtomicU64::new(0) }; 256];
+
+/// Count an occurrence of `vector`. Called at the top of every handler.
+#[inline(always)]
+pub(super) fn record(vector: u8) {
+    COUNTS[vector as usize].fetch_add(1, Ordering::Relaxed);
+}
+
+/// Count an occurrence of `vector` that no handler was registered for. Logging from the handler
+/// could deadlock on the logger, these are reported by [`log_stats`] instead.
+#[inline(always)]
+pub(super) fn record_unhandled(vector: u8) {
+    UNHANDLED_COUNTS[vector as usize].fetch_add(1, Ordering::Relaxed);
+}
+
+/// Returns the number of times `vector` fired since boot, whether or not it was handled.
+pub fn fire_count(vector: u8) -> u64 {
+    COUNTS[vector as usize].load(Ordering::Relaxed)
+}
+
+/// Returns a snapshot of the number of times each vector fired since boot, indexed by vector.
+pub fn stats() -> [u64; 256] {
+    core::array::from_fn(|vector| COUNTS[vector].load(Ordering::Relaxed))
+}
+
+/// Returns the number of times `vector` fired since boot without a registered handler.
+pub fn unhandled_count(vector: u8) -> u64 {
+    UNHANDLED_COUNTS[vector as usize].load(Ordering::Relaxed)
+}
+
+/// Log the number of times each vector that fired at least once did, warning about the ones that
+/// fired without a handler.
+pub fn log_stats() {
+    for (vector, count) in stats().iter().enumerate().filter(|(_, count)| **count != 0) {
+        logger::info!("Vector {:#04X}: {} interrupts", vector, count);
+        let unhandled = unhandled_count(vector as u8);
+        if unhandled != 0 {
+            logger::warning!("Vector {:#04X}: {} unhandled interrupts", vector, unhandled);
+        }
+    }
+}
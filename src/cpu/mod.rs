@@ -1,8 +1,22 @@
+use core::arch::asm;
+
 use crate::memory::VirtualAddress;
 
+pub mod apic;
+pub mod backtrace;
+pub mod fpu;
 pub mod interrupts;
+pub mod percpu;
+pub mod port;
+pub mod power;
+pub mod qemu;
 pub mod segments;
 pub mod registers;
+pub mod sched;
+pub mod smp;
+pub mod syscall;
+pub mod time;
+pub mod tlb;
 
 #[repr(C, packed)]
 struct DescriptorTablePointer {
@@ -32,3 +46,27 @@ impl TryFrom<u8> for PrivilegeLevel {
         }
     }
 }
+
+/// Enable interrupts and sleep until the next one is received.
+///
+/// `sti` only takes effect after the following instruction, so an interrupt can't be serviced
+/// between the two and missed by `hlt`.
+#[inline(always)]
+pub fn wait_for_interrupt() {
+    unsafe { asm!("sti; hlt", options(nomem, nostack)) };
+}
+
+/// Sleep forever with interrupts enabled, waking up only to service interrupts.
+pub fn idle() -> ! {
+    loop {
+        wait_for_interrupt();
+    }
+}
+
+/// Stop the processor for good, with interrupts disabled so that only an NMI can wake it up.
+pub fn halt() -> ! {
+    loop {
+        // loop over instruction in case CPU retakes control
+        unsafe { asm!("cli; hlt", options(nomem, nostack)) };
+    }
+}
@@ -0,0 +1,158 @@
+//! Data private to each processor, reached through its GS base.
+//!
+//! Every processor points its GS base and its kernel GS base, which `swapgs` exchanges with the
+//! former, to its own [`PerCpu`] area allocated from the frame allocator by the bootstrap
+//! processor. Fields are read with
+//! `gs:`-relative loads, and the area starts with its own address so that a reference to it is a
+//! single load away.
+
+use core::arch::asm;
+use core::mem::offset_of;
+use core::ptr;
+use core::sync::atomic::{AtomicPtr, AtomicUsize, Ordering};
+
+use crate::cpu::registers::Msr;
+use crate::cpu::segments::{self, TaskStateSegment};
+use crate::cpu::smp::MAX_CPUS;
+use crate::cpu::syscall::SYSCALL_STACK_SIZE;
+use crate::memory::{VirtualAddress, frame_allocator};
+
+#[repr(C)]
+#[derive(Debug)]
+pub struct PerCpu {
+    /// Address of this area, read by [`current`].
+    this: *const PerCpu,
+    /// Index of the processor, 0 being the bootstrap processor.
+    pub index: usize,
+    pub lapic_id: u32,
+    /// Index in the scheduler of the task running on the processor.
+    current_task: AtomicUsize,
+    /// Task State Segment of the processor.
+    tss: VirtualAddress,
+    /// Top of the stack system calls run on, as `syscall` keeps the stack pointer of the caller.
+    syscall_stack_top: VirtualAddress,
+    /// Stack pointer of the caller, saved while a system call runs on the system call stack.
+    caller_stack_pointer: usize,
+}
+
+/// Offset of the top of the system call stack in [`PerCpu`], for the `syscall` entry point.
+pub const SYSCALL_STACK_TOP_OFFSET: usize = offset_of!(PerCpu, syscall_stack_top);
+/// Offset of the saved stack pointer of the caller in [`PerCpu`], for the `syscall` entry point.
+pub const CALLER_STACK_POINTER_OFFSET: usize = offset_of!(PerCpu, caller_stack_pointer);
+
+/// Areas allocated by [`allocate`], by processor index.
+static AREAS: [AtomicPtr<PerCpu>; MAX_CPUS] = [const { AtomicPtr::new(ptr::null_mut()) }; MAX_CPUS];
+
+/// Allocate the per-CPU areas of the first `count` processors, along with their Task State
+/// Segments and system call stacks. The frame allocator is not thread-safe, so this must be done before the other
+/// processors are started.
+pub fn allocate(count: usize) {
+    for (index, area) in AREAS.iter().enumerate().take(count) {
+        let address: *mut PerCpu = frame_allocator::allocate(size_of::<PerCpu>())
+            .to_virtual()
+            .to_ptr();
+        let syscall_stack = frame_allocator::allocate(SYSCALL_STACK_SIZE).to_virtual();
+        let tss: *mut TaskStateSegment = frame_allocator::allocate(size_of::<TaskStateSegment>())
+            .to_virtual()
+            .to_ptr();
+        unsafe { tss.write(TaskStateSegment::new()) };
+        segments::set_tss(index, VirtualAddress::from_ptr(tss));
+
+        unsafe {
+            address.write(PerCpu {
+                this: address,
+                index,
+                lapic_id: 0,
+                current_task: AtomicUsize::new(0),
+                tss: VirtualAddress::from_ptr(tss),
+                syscall_stack_top: syscall_stack + SYSCALL_STACK_SIZE,
+                caller_stack_pointer: 0,
+            });
+        }
+        area.store(address, Ordering::Release);
+    }
+}
+
+/// Initialize the per-CPU area at `index`, allocated by [`allocate`], point the GS bases of the
+/// current processor to it and load its Task State Segment.
+pub fn init(index: usize, lapic_id: u32) {
+    let area = AREAS[index].load(Ordering::Acquire);
+    assert!(
+        !area.is_null(),
+        "The per-CPU area of processor {index} is not allocated"
+    );
+    unsafe {
+        (*area).lapic_id = lapic_id;
+        Msr::IA32_GS_BASE.write(area as u64);
+        Msr::IA32_KERNEL_GS_BASE.write(area as u64);
+    }
+    segments::load_tss(index);
+}
+
+/// Returns the per-CPU area of the current processor, which must be initialized.
+#[inline]
+pub fn current() -> &'static PerCpu {
+    unsafe { &*(read_field(offset_of!(PerCpu, this)) as *const PerCpu) }
+}
+
+/// Returns the index of the current processor, 0 being the bootstrap processor.
+#[inline]
+pub fn index() -> usize {
+    read_field(offset_of!(PerCpu, index))
+}
+
+/// Returns the local APIC id of the current processor.
+#[inline]
+pub fn lapic_id() -> u32 {
+    let value: u32;
+    unsafe {
+        asm!(
+            "mov {:e}, gs:[{}]",
+            out(reg) value,
+            in(reg) offset_of!(PerCpu, lapic_id),
+            options(nostack, preserves_flags, readonly)
+        );
+    }
+    value
+}
+
+/// Returns the index in the scheduler of the task running on the current processor.
+#[inline]
+pub fn current_task() -> usize {
+    read_field(offset_of!(PerCpu, current_task))
+}
+
+/// Record `task` as the index of the task running on the current processor.
+#[inline]
+pub fn set_current_task(task: usize) {
+    // The field is atomic, so it may be written while shared references to the area exist.
+    unsafe {
+        asm!(
+            "mov gs:[{}], {}",
+            in(reg) offset_of!(PerCpu, current_task),
+            in(reg) task,
+            options(nostack, preserves_flags)
+        );
+    }
+}
+
+/// Returns the Task State Segment of the current processor.
+#[inline]
+pub fn tss() -> VirtualAddress {
+    VirtualAddress::from(read_field(offset_of!(PerCpu, tss)))
+}
+
+/// Read the pointer-sized field at `offset` in the per-CPU area of the current processor.
+#[inline(always)]
+fn read_field(offset: usize) -> usize {
+    let value: usize;
+    unsafe {
+        asm!(
+            "mov {}, gs:[{}]",
+            out(reg) value,
+            in(reg) offset,
+            options(nostack, preserves_flags, readonly)
+        );
+    }
+    value
+}
@@ -0,0 +1,165 @@
+use core::arch::asm;
+use core::marker::PhantomData;
+
+/// Port used by [`io_wait`]. Historically used for POST codes, writing to it has no side effects.
+const UNUSED_PORT: u16 = 0x80;
+
+/// Read a byte from the I/O `port`.
+///
+/// SAFETY: Reading from an I/O port can have side effects on the underlying device, callers must
+/// ensure `port` is valid to read from in the current context.
+#[inline(always)]
+pub unsafe fn inb(port: u16) -> u8 {
+    let value: u8;
+    unsafe {
+        asm!("in al, dx", out("al") value, in("dx") port, options(nomem, nostack, preserves_flags));
+    }
+    value
+}
+
+/// Write the byte `value` to the I/O `port`.
+///
+/// SAFETY: Writing to an I/O port can have side effects on the underlying device, callers must
+/// ensure `port` is valid to write to in the current context.
+#[inline(always)]
+pub unsafe fn outb(port: u16, value: u8) {
+    unsafe {
+        asm!("out dx, al", in("dx") port, in("al") value, options(nomem, nostack, preserves_flags));
+    }
+}
+
+/// Read a word from the I/O `port`.
+///
+/// SAFETY: Reading from an I/O port can have side effects on the underlying device, callers must
+/// ensure `port` is valid to read from in the current context.
+#[inline(always)]
+pub unsafe fn inw(port: u16) -> u16 {
+    let value: u16;
+    unsafe {
+        asm!("in ax, dx", out("ax") value, in("dx") port, options(nomem, nostack, preserves_flags));
+    }
+    value
+}
+
+/// Write the word `value` to the I/O `port`.
+///
+/// SAFETY: Writing to an I/O port can have side effects on the underlying device, callers must
+/// ensure `port` is valid to write to in the current context.
+#[inline(always)]
+pub unsafe fn outw(port: u16, value: u16) {
+    unsafe {
+        asm!("out dx, ax", in("dx") port, in("ax") value, options(nomem, nostack, preserves_flags));
+    }
+}
+
+/// Read a double word from the I/O `port`.
+///
+/// SAFETY: Reading from an I/O port can have side effects on the underlying device, callers must
+/// ensure `port` is valid to read from in the current context.
+#[inline(always)]
+pub unsafe fn inl(port: u16) -> u32 {
+    let value: u32;
+    unsafe {
+        asm!("in eax, dx", out("eax") value, in("dx") port, options(nomem, nostack, preserves_flags));
+    }
+    value
+}
+
+/// Write the double word `value` to the I/O `port`.
+///
+/// SAFETY: Writing to an I/O port can have side effects on the underlying device, callers must
+/// ensure `port` is valid to write to in the current context.
+#[inline(always)]
+pub unsafe fn outl(port: u16, value: u32) {
+    unsafe {
+        asm!("out dx, eax", in("dx") port, in("eax") value, options(nomem, nostack, preserves_flags));
+    }
+}
+
+/// Value that can be transferred through an I/O port: `u8`, `u16` or `u32`.
+pub trait PortValue: Copy + private::Sealed {
+    /// SAFETY: See [`inb`].
+    unsafe fn read_from(port: u16) -> Self;
+    /// SAFETY: See [`outb`].
+    unsafe fn write_to(port: u16, value: Self);
+}
+
+mod private {
+    pub trait Sealed {}
+    impl Sealed for u8 {}
+    impl Sealed for u16 {}
+    impl Sealed for u32 {}
+}
+
+impl PortValue for u8 {
+    #[inline(always)]
+    unsafe fn read_from(port: u16) -> Self {
+        unsafe { inb(port) }
+    }
+
+    #[inline(always)]
+    unsafe fn write_to(port: u16, value: Self) {
+        unsafe { outb(port, value) }
+    }
+}
+
+impl PortValue for u16 {
+    #[inline(always)]
+    unsafe fn read_from(port: u16) -> Self {
+        unsafe { inw(port) }
+    }
+
+    #[inline(always)]
+    unsafe fn write_to(port: u16, value: Self) {
+        unsafe { outw(port, value) }
+    }
+}
+
+impl PortValue for u32 {
+    #[inline(always)]
+    unsafe fn read_from(port: u16) -> Self {
+        unsafe { inl(port) }
+    }
+
+    #[inline(always)]
+    unsafe fn write_to(port: u16, value: Self) {
+        unsafe { outl(port, value) }
+    }
+}
+
+/// An I/O port transferring values of type `T`, for devices whose registers have a fixed width.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Port<T: PortValue> {
+    port: u16,
+    _value: PhantomData<T>,
+}
+
+impl<T: PortValue> Port<T> {
+    pub const fn new(port: u16) -> Self {
+        Self {
+            port,
+            _value: PhantomData,
+        }
+    }
+
+    /// SAFETY: Reading from an I/O port can have side effects on the underlying device, callers
+    /// must ensure the port is valid to read from in the current context.
+    #[inline(always)]
+    pub unsafe fn read(&self) -> T {
+        unsafe { T::read_from(self.port) }
+    }
+
+    /// SAFETY: Writing to an I/O port can have side effects on the underlying device, callers
+    /// must ensure the port is valid to write to in the current context.
+    #[inline(always)]
+    pub unsafe fn write(&self, value: T) {
+        unsafe { T::write_to(self.port, value) }
+    }
+}
+
+/// Wait for a very small amount of time (1 to 4 microseconds) by writing to an unused port.
+/// Useful for devices that need some time to process a command, such as the 8259 PIC.
+#[inline(always)]
+pub fn io_wait() {
+    unsafe { outb(UNUSED_PORT, 0) };
+}
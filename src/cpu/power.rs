@@ -0,0 +1,118 @@
+//! Power off and reset of the machine through ACPI, with legacy fallbacks.
+
+use crate::acpi::{self, GenericAddress};
+use crate::cpu::port::{inb, inw, outb, outw};
+use crate::cpu::{self, interrupts};
+use crate::memory::{PhysicalAddress, paging};
+use crate::terminal::logger;
+
+/// (PM1 control) The system is in ACPI mode, power management events raise the SCI.
+const PM1_SCI_ENABLE: u16 = 1 << 0;
+/// (PM1 control) Shift of the sleep type to enter.
+const PM1_SLEEP_TYPE_SHIFT: u16 = 10;
+/// (PM1 control) Enter the sleep state given by the sleep type.
+const PM1_SLEEP_ENABLE: u16 = 1 << 13;
+
+/// (GAS) Address space of registers in system memory.
+const ADDRESS_SPACE_MEMORY: u8 = 0;
+/// (GAS) Address space of registers in system I/O.
+const ADDRESS_SPACE_IO: u8 = 1;
+
+/// Command and status port of the 8042 PS/2 controller.
+const PS2_COMMAND_PORT: u16 = 0x64;
+/// (Status) The input buffer is full, the controller is not ready for a command.
+const PS2_INPUT_FULL: u8 = 1 << 1;
+/// (Command) Pulse the reset line of the processor.
+const PS2_PULSE_RESET: u8 = 0xFE;
+
+/// Number of polls of the SCI enable bit after requesting ACPI mode.
+const ACPI_ENABLE_POLLS: usize = 1_000_000;
+
+/// Power off the machine by entering the ACPI S5 sleep state. Halts if the firmware does not
+/// describe how to enter it.
+pub fn shutdown() -> ! {
+    interrupts::disable();
+    logger::info!("Shutting down");
+
+    let sleep_types = acpi::dsdt().and_then(|dsdt| dsdt.s5_sleep_types());
+    if let (Some(fadt), Some((sleep_type_a, sleep_type_b))) = (acpi::fadt(), sleep_types) {
+        let (pm1a, pm1b) = fadt.pm1_control_blocks();
+        if let Some((smi_command, acpi_enable)) = fadt.acpi_enable_command() {
+            enable_acpi_mode(pm1a, smi_command, acpi_enable);
+        }
+        unsafe {
+            enter_sleep_state(pm1a, sleep_type_a);
+            if let Some(pm1b) = pm1b {
+                enter_sleep_state(pm1b, sleep_type_b);
+            }
+        }
+    }
+
+    logger::warning!("Could not enter the S5 sleep state, halting instead");
+    cpu::halt()
+}
+
+/// Reset the machine through the ACPI reset register, falling back to the reset line of the
+/// 8042 PS/2 controller. Halts if both fail.
+pub fn reboot() -> ! {
+    interrupts::disable();
+    logger::info!("Rebooting");
+
+    if let Some((register, value)) = acpi::fadt().and_then(|fadt| fadt.reset_register()) {
+        unsafe { write_register(register, value) };
+    }
+
+    unsafe {
+        while inb(PS2_COMMAND_PORT) & PS2_INPUT_FULL != 0 {
+            core::hint::spin_loop();
+        }
+        outb(PS2_COMMAND_PORT, PS2_PULSE_RESET);
+    }
+
+    logger::warning!("Could not reset the machine, halting instead");
+    cpu::halt()
+}
+
+/// Ask the firmware to hand the power management over to the OS through the SMI command port,
+/// unless the PM1a control register shows it is already done.
+fn enable_acpi_mode(pm1a: u16, smi_command: u16, acpi_enable: u8) {
+    unsafe {
+        if inw(pm1a) & PM1_SCI_ENABLE != 0 {
+            return;
+        }
+        outb(smi_command, acpi_enable);
+        for _ in 0..ACPI_ENABLE_POLLS {
+            if inw(pm1a) & PM1_SCI_ENABLE != 0 {
+                return;
+            }
+            core::hint::spin_loop();
+        }
+    }
+    logger::warning!("The firmware did not switch to ACPI mode");
+}
+
+/// SAFETY: Entering a sleep state stops the machine, `port` must be a PM1 control register.
+unsafe fn enter_sleep_state(port: u16, sleep_type: u8) {
+    unsafe {
+        let control = inw(port) & !(0b111 << PM1_SLEEP_TYPE_SHIFT);
+        outw(
+            port,
+            control | (sleep_type as u16 & 0b111) << PM1_SLEEP_TYPE_SHIFT | PM1_SLEEP_ENABLE,
+        );
+    }
+}
+
+/// Write `value` to the byte-wide `register`, if it is in system memory or I/O.
+///
+/// SAFETY: Writing to a hardware register can have any side effect on the machine.
+unsafe fn write_register(register: GenericAddress, value: u8) {
+    let address = register.address;
+    match register.address_space {
+        ADDRESS_SPACE_IO => unsafe { outb(address as u16, value) },
+        ADDRESS_SPACE_MEMORY => {
+            let pointer = paging::map_mmio(PhysicalAddress::from_u64(address), 1).to_ptr::<u8>();
+            unsafe { pointer.write_volatile(value) };
+        }
+        space => logger::warning!("Unsupported address space {space} for the reset register"),
+    }
+}
@@ -0,0 +1,29 @@
+//! Exit QEMU with a status code through its `isa-debug-exit` device.
+//!
+//! The device must be attached to the virtual machine with
+//! `-device isa-debug-exit,iobase=0xf4,iosize=0x04`. QEMU then exits with the status
+//! `(code << 1) | 1`, so [`QemuExitCode::Success`] exits with 33 and [`QemuExitCode::Failed`]
+//! with 35. Without the device, [`exit`] halts the processor instead.
+
+use core::arch::asm;
+
+use crate::cpu::port::outb;
+
+/// I/O port of the `isa-debug-exit` device.
+const ISA_DEBUG_EXIT_PORT: u16 = 0xF4;
+
+#[repr(u8)]
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum QemuExitCode {
+    Success = 0x10,
+    Failed = 0x11,
+}
+
+/// Exit QEMU with `code`.
+pub fn exit(code: QemuExitCode) -> ! {
+    unsafe { outb(ISA_DEBUG_EXIT_PORT, code as u8) };
+
+    loop {
+        unsafe { asm!("cli; hlt", options(nomem, nostack)) };
+    }
+}
@@ -1,7 +1,32 @@
 use core::arch::asm;
 use core::fmt;
 
-use crate::memory::PhysicalAddress;
+use crate::memory::{PhysicalAddress, VirtualAddress};
+
+pub struct Cr0;
+impl Cr0 {
+    pub fn read() -> Cr0Flags {
+        let content: usize;
+        unsafe { asm!("mov {}, cr0", out(reg) content, options(nomem, nostack, preserves_flags)) }
+        Cr0Flags::from_bits_retain(content)
+    }
+
+    /// SAFETY: Changing the control flags can break memory safety, e.g. by disabling paging or
+    /// write protection.
+    pub unsafe fn write(flags: Cr0Flags) {
+        unsafe { asm!("mov cr0, {}", in(reg) flags.bits(), options(nostack, preserves_flags)) }
+    }
+}
+
+pub struct Cr2;
+impl Cr2 {
+    /// Returns the address whose access caused the last page fault.
+    pub fn read() -> VirtualAddress {
+        let content: usize;
+        unsafe { asm!("mov {}, cr2", out(reg) content, options(nomem, nostack, preserves_flags)) }
+        VirtualAddress::from(content)
+    }
+}
 
 pub struct Cr3;
 impl Cr3 {
@@ -12,6 +37,185 @@ impl Cr3 {
         let flags = Cr3Flags::from_bits_truncate(content);
         (pdbr.into(), flags)
     }
+
+    /// Install the level 4 page table located at `frame`, which switches the active address
+    /// space and flushes the non-global TLB entries.
+    ///
+    /// SAFETY: The table must map the code, stack and data of the kernel at the same addresses as
+    /// the active one.
+    pub unsafe fn write(frame: PhysicalAddress, flags: Cr3Flags) {
+        let content = frame.value() | flags.bits();
+        unsafe { asm!("mov cr3, {}", in(reg) content, options(nostack, preserves_flags)) }
+    }
+}
+
+/// Returns the current stack pointer.
+#[inline(always)]
+pub fn read_rsp() -> VirtualAddress {
+    let content: usize;
+    unsafe { asm!("mov {}, rsp", out(reg) content, options(nomem, nostack, preserves_flags)) }
+    VirtualAddress::from(content)
+}
+
+/// Returns the current frame pointer, i.e. the start of the stack frame of the caller when it is
+/// inlined.
+#[inline(always)]
+pub fn read_rbp() -> VirtualAddress {
+    let content: usize;
+    unsafe { asm!("mov {}, rbp", out(reg) content, options(nomem, nostack, preserves_flags)) }
+    VirtualAddress::from(content)
+}
+
+/// Returns the number of bytes used on the stack growing down from `stack_top`, which must be
+/// the top of the current stack.
+#[inline(always)]
+pub fn stack_usage(stack_top: VirtualAddress) -> usize {
+    stack_top.value().saturating_sub(read_rsp().value())
+}
+
+/// A model-specific register, identified by its address.
+#[derive(Clone, Copy, Debug)]
+#[repr(transparent)]
+pub struct Msr(u32);
+
+impl Msr {
+    /// Physical base address of the local APIC registers and APIC state flags.
+    pub const IA32_APIC_BASE: Msr = Msr::new(0x1B);
+    /// Extended feature enable register.
+    pub const IA32_EFER: Msr = Msr::new(0xC000_0080);
+    /// Segment selectors loaded by `syscall` and `sysret`.
+    pub const IA32_STAR: Msr = Msr::new(0xC000_0081);
+    /// Entry point of `syscall` in 64-bit mode.
+    pub const IA32_LSTAR: Msr = Msr::new(0xC000_0082);
+    /// RFLAGS bits cleared by `syscall`.
+    pub const IA32_FMASK: Msr = Msr::new(0xC000_0084);
+    /// Base address of the GS segment.
+    pub const IA32_GS_BASE: Msr = Msr::new(0xC000_0101);
+    /// Base address of the GS segment swapped in by `swapgs`.
+    pub const IA32_KERNEL_GS_BASE: Msr = Msr::new(0xC000_0102);
+
+    pub const fn new(address: u32) -> Self {
+        Self(address)
+    }
+
+    /// SAFETY: Reading a register that is not supported by the processor raises a `#GP` fault.
+    #[inline]
+    pub unsafe fn read(&self) -> u64 {
+        let (high, low): (u32, u32);
+        unsafe {
+            asm!("rdmsr", in("ecx") self.0, out("eax") low, out("edx") high, options(nomem, nostack, preserves_flags));
+        }
+        ((high as u64) << 32) | low as u64
+    }
+
+    /// SAFETY: Writing to a register that is not supported by the processor, or writing reserved
+    /// bits, raises a `#GP` fault. Writing a register can also change the processor's behavior in
+    /// ways that break memory safety.
+    #[inline]
+    pub unsafe fn write(&self, value: u64) {
+        let low = value as u32;
+        let high = (value >> 32) as u32;
+        unsafe {
+            asm!("wrmsr", in("ecx") self.0, in("eax") low, in("edx") high, options(nostack, preserves_flags));
+        }
+    }
+}
+
+pub struct Cr4;
+impl Cr4 {
+    pub fn read() -> Cr4Flags {
+        let content: usize;
+        unsafe { asm!("mov {}, cr4", out(reg) content, options(nomem, nostack, preserves_flags)) }
+        Cr4Flags::from_bits_retain(content)
+    }
+
+    /// SAFETY: Enabling a feature that is not supported by the processor raises a `#GP` fault,
+    /// and changing the control flags can break memory safety.
+    pub unsafe fn write(flags: Cr4Flags) {
+        unsafe { asm!("mov cr4, {}", in(reg) flags.bits(), options(nostack, preserves_flags)) }
+    }
+}
+
+bitflags::bitflags! {
+    #[derive(PartialEq, Eq, Clone, Copy)]
+    #[repr(transparent)]
+    pub struct Cr0Flags: usize {
+        /// (PE) Protection enable.
+        const PROTECTION_ENABLE = 1 << 0;
+        /// (MP) Monitor coprocessor. Makes `wait`/`fwait` honor the TS flag.
+        const MONITOR_COPROCESSOR = 1 << 1;
+        /// (EM) Emulation. When set, x87 and SSE instructions raise `#NM` or `#UD`.
+        const EMULATION = 1 << 2;
+        /// (TS) Task switched. When set, x87 and SSE instructions raise `#NM`.
+        const TASK_SWITCHED = 1 << 3;
+        /// (ET) Extension type.
+        const EXTENSION_TYPE = 1 << 4;
+        /// (NE) Numeric error. Report x87 errors through `#MF` rather than the legacy IRQ 13.
+        const NUMERIC_ERROR = 1 << 5;
+        /// (WP) Write protect. Prevents supervisor writes to read-only pages.
+        const WRITE_PROTECT = 1 << 16;
+        /// (AM) Alignment mask. Enables alignment checking along with RFLAGS.AC.
+        const ALIGNMENT_MASK = 1 << 18;
+        /// (NW) Not write-through.
+        const NOT_WRITE_THROUGH = 1 << 29;
+        /// (CD) Cache disable.
+        const CACHE_DISABLE = 1 << 30;
+        /// (PG) Paging.
+        const PAGING = 1 << 31;
+    }
+}
+
+bitflags::bitflags! {
+    #[derive(PartialEq, Eq, Clone, Copy)]
+    #[repr(transparent)]
+    pub struct Cr4Flags: usize {
+        /// (VME) Virtual-8086 mode extensions.
+        const VIRTUAL_8086_EXTENSIONS = 1 << 0;
+        /// (PVI) Protected-mode virtual interrupts.
+        const PROTECTED_VIRTUAL_INTERRUPTS = 1 << 1;
+        /// (TSD) Time stamp disable. Restricts `rdtsc` to ring 0.
+        const TIME_STAMP_DISABLE = 1 << 2;
+        /// (DE) Debugging extensions.
+        const DEBUGGING_EXTENSIONS = 1 << 3;
+        /// (PSE) Page size extensions.
+        const PAGE_SIZE_EXTENSIONS = 1 << 4;
+        /// (PAE) Physical address extension.
+        const PHYSICAL_ADDRESS_EXTENSION = 1 << 5;
+        /// (MCE) Machine-check enable.
+        const MACHINE_CHECK = 1 << 6;
+        /// (PGE) Page global enable.
+        const PAGE_GLOBAL = 1 << 7;
+        /// (PCE) Performance-monitoring counter enable.
+        const PERFORMANCE_COUNTER = 1 << 8;
+        /// (OSFXSR) Operating system support for `fxsave` and `fxrstor`, enables SSE.
+        const OS_FXSR = 1 << 9;
+        /// (OSXMMEXCPT) Operating system support for unmasked SIMD floating-point exceptions.
+        const OS_XMM_EXCEPTIONS = 1 << 10;
+        /// (UMIP) User-mode instruction prevention.
+        const USER_MODE_INSTRUCTION_PREVENTION = 1 << 11;
+        /// (LA57) 57-bit linear addresses (5-level paging).
+        const LINEAR_ADDRESSES_57 = 1 << 12;
+        /// (VMXE) Virtual machine extensions enable.
+        const VMX = 1 << 13;
+        /// (SMXE) Safer mode extensions enable.
+        const SMX = 1 << 14;
+        /// (FSGSBASE) Enables the `rdfsbase`/`wrfsbase` family of instructions.
+        const FS_GS_BASE = 1 << 16;
+        /// (PCIDE) Process-context identifiers enable.
+        const PCID = 1 << 17;
+        /// (OSXSAVE) `xsave` and processor extended states enable.
+        const OS_XSAVE = 1 << 18;
+        /// (SMEP) Supervisor mode execution prevention.
+        const SUPERVISOR_EXECUTION_PREVENTION = 1 << 20;
+        /// (SMAP) Supervisor mode access prevention.
+        const SUPERVISOR_ACCESS_PREVENTION = 1 << 21;
+        /// (PKE) Protection keys for user-mode pages.
+        const PROTECTION_KEYS = 1 << 22;
+        /// (CET) Control-flow enforcement technology.
+        const CONTROL_FLOW_ENFORCEMENT = 1 << 23;
+        /// (PKS) Protection keys for supervisor-mode pages.
+        const SUPERVISOR_PROTECTION_KEYS = 1 << 24;
+    }
 }
 
 bitflags::bitflags! {
@@ -68,6 +272,23 @@ bitflags::bitflags! {
     }
 }
 
+impl RFlags {
+    /// Returns the current value of the flags register.
+    #[inline(always)]
+    pub fn read() -> RFlags {
+        let content: usize;
+        unsafe { asm!("pushfq; pop {}", out(reg) content, options(nomem, preserves_flags)) };
+        RFlags::from_bits_retain(content)
+    }
+
+    /// SAFETY: Changing the flags can break assumptions of the surrounding code, e.g. enabling
+    /// interrupts inside a critical section or setting the direction flag.
+    #[inline(always)]
+    pub unsafe fn write(flags: RFlags) {
+        unsafe { asm!("push {}; popfq", in(reg) flags.bits(), options(nomem)) };
+    }
+}
+
 impl fmt::Debug for RFlags {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         write!(f, "RFlags(")?;
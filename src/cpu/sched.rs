@@ -0,0 +1,301 @@
+//! Round-robin scheduling of kernel tasks on the bootstrap processor.
+//!
+//! Each task runs on its own stack. Switching to another task saves the callee-saved registers
+//! on the stack of the current one and records its stack pointer, which is all that is needed to
+//! resume it later as the switch looks like a regular function call to the compiler. Tasks give
+//! up the processor with [`yield_now`], or are preempted by the timer every
+//! [`TIME_SLICE_TICKS`] ticks once the interrupt has been acknowledged.
+//!
+//! The code that called [`init`] becomes the first task and keeps running on the boot stack.
+
+use core::arch::naked_asm;
+use core::error;
+use core::fmt;
+use core::sync::atomic::{AtomicBool, Ordering};
+
+use crate::cpu::{interrupts, percpu, registers, time};
+use crate::memory::{PhysicalAddress, frame_allocator};
+use crate::sync::IrqMutex;
+use crate::terminal::logger;
+use crate::terminal::tty::TerminalStdin;
+
+/// Maximum number of tasks, the initial one included.
+const MAX_TASKS: usize = 8;
+
+/// Size of the stack allocated for each task.
+const TASK_STACK_SIZE: usize = 16 * 1024;
+
+/// Number of timer ticks a task runs for before being preempted.
+const TIME_SLICE_TICKS: u64 = 2;
+
+/// Stack usage of a task above which a warning is logged when it yields.
+const STACK_WARNING_THRESHOLD: usize = TASK_STACK_SIZE / 4 * 3;
+
+/// Number of registers saved on the stack by [`switch_context`], below its return address.
+const SAVED_REGISTERS: usize = 6;
+
+static SCHEDULER: IrqMutex<Scheduler> = IrqMutex::new(Scheduler::new());
+static INITIALIZED: AtomicBool = AtomicBool::new(false);
+/// Set by the timer when the time slice of the running task is over.
+static PREEMPTION_PENDING: AtomicBool = AtomicBool::new(false);
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum TaskState {
+    Ready,
+    Running,
+    Finished,
+}
+
+struct Task {
+    name: &'static str,
+    state: TaskState,
+    /// Stack pointer saved when the task was switched out.
+    stack_pointer: usize,
+    /// Bottom of the stack allocated for the task, `None` for the initial task.
+    stack: Option<PhysicalAddress>,
+}
+
+/// Tasks by index, the index of the running one being kept in the per-CPU area.
+struct Scheduler {
+    tasks: [Option<Task>; MAX_TASKS],
+}
+
+impl Scheduler {
+    const fn new() -> Self {
+        Self {
+            tasks: [const { None }; MAX_TASKS],
+        }
+    }
+
+    /// Make the next ready task after the current one the running one. Returns where to save
+    /// the stack pointer of the current task and the stack pointer to resume the next one with,
+    /// or `None` if no other task is ready.
+    fn switch_to_next(&mut self) -> Option<(*mut usize, usize)> {
+        let current_index = percpu::current_task();
+        let next = (1..MAX_TASKS)
+            .map(|offset| (current_index + offset) % MAX_TASKS)
+            .find(|&index| {
+                self.tasks[index]
+                    .as_ref()
+                    .is_some_and(|task| task.state == TaskState::Ready)
+            })?;
+
+        let current = self.tasks[current_index].as_mut().unwrap();
+        if current.state == TaskState::Running {
+            current.state = TaskState::Ready;
+        }
+        let current_stack_pointer = &raw mut current.stack_pointer;
+
+        let next_task = self.tasks[next].as_mut().unwrap();
+        next_task.state = TaskState::Running;
+        percpu::set_current_task(next);
+        Some((current_stack_pointer, next_task.stack_pointer))
+    }
+}
+
+#[derive(Debug)]
+pub enum SpawnError {
+    TooManyTasks,
+}
+
+impl fmt::Display for SpawnError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::TooManyTasks => write!(f, "Cannot run more than {MAX_TASKS} tasks"),
+        }
+    }
+}
+
+impl error::Error for SpawnError {}
+
+/// Make the calling code the initial task and start preempting tasks on timer ticks.
+pub fn init() {
+    SCHEDULER.lock().tasks[0] = Some(Task {
+        name: "kernel",
+        state: TaskState::Running,
+        stack_pointer: 0,
+        stack: None,
+    });
+    time::on_tick(|ticks| {
+        if ticks % TIME_SLICE_TICKS == 0 {
+            PREEMPTION_PENDING.store(true, Ordering::Relaxed);
+        }
+    });
+    INITIALIZED.store(true, Ordering::Release);
+}
+
+/// Create a task named `name` running `entry` on a new stack. It starts with interrupts enabled
+/// the next time it is scheduled, and finishes when `entry` returns.
+///
+/// The stack of a finished task is only freed when its slot is reused by a new task.
+pub fn spawn(name: &'static str, entry: fn()) -> Result<(), SpawnError> {
+    let mut scheduler = SCHEDULER.lock();
+    let slot = scheduler
+        .tasks
+        .iter_mut()
+        .find(|slot| {
+            slot.as_ref()
+                .is_none_or(|task| task.state == TaskState::Finished)
+        })
+        .ok_or(SpawnError::TooManyTasks)?;
+    if let Some(stack) = slot.take().and_then(|task| task.stack) {
+        frame_allocator::free(stack);
+    }
+
+    let stack = frame_allocator::allocate_exact(TASK_STACK_SIZE);
+    let top = (stack + TASK_STACK_SIZE).to_virtual().to_ptr::<usize>();
+    // Registers popped by `switch_context` when first switching to the task, the entry point
+    // being passed in r12 to `task_start`, which is "returned" to.
+    let initial_frame: [usize; SAVED_REGISTERS + 1] = [
+        0,
+        0,
+        0,
+        entry as usize,
+        0,
+        0,
+        task_start as *const () as usize,
+    ];
+    let stack_pointer = unsafe {
+        let stack_pointer = top.sub(initial_frame.len());
+        stack_pointer.copy_from_nonoverlapping(initial_frame.as_ptr(), initial_frame.len());
+        stack_pointer as usize
+    };
+
+    *slot = Some(Task {
+        name,
+        state: TaskState::Ready,
+        stack_pointer,
+        stack: Some(stack),
+    });
+    Ok(())
+}
+
+/// Give the processor to the next ready task, if any. Returns once the calling task is
+/// scheduled again.
+pub fn yield_now() {
+    warn_if_stack_low();
+    interrupts::without_interrupts(switch);
+}
+
+/// Log a warning if the running task used most of its stack. Not done when preempting, as
+/// logging from interrupt context could deadlock with the interrupted code.
+fn warn_if_stack_low() {
+    let stack = SCHEDULER.lock().tasks[percpu::current_task()]
+        .as_ref()
+        .and_then(|task| task.stack);
+    let Some(stack) = stack else {
+        return;
+    };
+
+    let used = registers::stack_usage((stack + TASK_STACK_SIZE).to_virtual());
+    if used > STACK_WARNING_THRESHOLD {
+        logger::warning!(
+            "Task {} used {} of its {} bytes of stack",
+            current_name(),
+            used,
+            TASK_STACK_SIZE
+        );
+    }
+}
+
+/// Switch to the next task if the time slice of the running one is over. Called with interrupts
+/// disabled once an interrupt has been acknowledged, so that the next task can receive the
+/// following ones.
+pub(crate) fn preempt_if_pending() {
+    if !INITIALIZED.load(Ordering::Acquire) || percpu::index() != 0 {
+        return;
+    }
+    if PREEMPTION_PENDING.swap(false, Ordering::Relaxed) {
+        switch();
+    }
+}
+
+/// Returns the name of the running task.
+pub fn current_name() -> &'static str {
+    SCHEDULER.lock().tasks[percpu::current_task()]
+        .as_ref()
+        .map_or("kernel", |task| task.name)
+}
+
+/// Switch to the next ready task. Interrupts must be disabled.
+fn switch() {
+    // The lock must be released before switching, as the next task may lock it right away.
+    let next = SCHEDULER.lock().switch_to_next();
+    if let Some((current_stack_pointer, next_stack_pointer)) = next {
+        unsafe { switch_context(current_stack_pointer, next_stack_pointer) };
+    }
+}
+
+/// Mark the running task as finished and switch away from it for good.
+fn exit() -> ! {
+    interrupts::disable();
+    SCHEDULER.lock().tasks[percpu::current_task()]
+        .as_mut()
+        .unwrap()
+        .state = TaskState::Finished;
+    switch();
+    unreachable!("A finished task was scheduled again");
+}
+
+/// Save the callee-saved registers on the current stack and its pointer to `current`, then
+/// restore the registers from the stack at `next` and return to where that stack left off.
+#[unsafe(naked)]
+unsafe extern "C" fn switch_context(current: *mut usize, next: usize) {
+    naked_asm!(
+        "push rbx",
+        "push rbp",
+        "push r12",
+        "push r13",
+        "push r14",
+        "push r15",
+        "mov [rdi], rsp",
+        "mov rsp, rsi",
+        "pop r15",
+        "pop r14",
+        "pop r13",
+        "pop r12",
+        "pop rbp",
+        "pop rbx",
+        "ret",
+    )
+}
+
+/// First code run by a task, with the entry point in r12 and a 16-byte aligned stack.
+#[unsafe(naked)]
+unsafe extern "C" fn task_start() -> ! {
+    naked_asm!(
+        "mov rdi, r12",
+        "call {main}",
+        "ud2",
+        main = sym task_main,
+    )
+}
+
+extern "C" fn task_main(entry: *const ()) -> ! {
+    // Only ever called by `task_start` with the `fn()` given to `spawn`.
+    let entry = unsafe { core::mem::transmute::<*const (), fn()>(entry) };
+    interrupts::enable();
+    entry();
+    exit()
+}
+
+/// Spawn two tasks printing to the terminal in turns, to check that switching works.
+pub fn spawn_demo_tasks() {
+    fn demo() {
+        use core::fmt::Write;
+        for step in 0..3 {
+            let _ = writeln!(
+                TerminalStdin::new(),
+                "Hello from {} ({step})",
+                current_name()
+            );
+            yield_now();
+        }
+    }
+
+    for name in ["demo-a", "demo-b"] {
+        if let Err(error) = spawn(name, demo) {
+            logger::warning!("{}", error);
+        }
+    }
+}
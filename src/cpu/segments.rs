@@ -1,6 +1,48 @@
+use core::arch::asm;
 use core::fmt::Debug;
 
-use crate::cpu::PrivilegeLevel;
+use crate::cpu::smp::MAX_CPUS;
+use crate::cpu::{DescriptorTablePointer, PrivilegeLevel};
+use crate::memory::VirtualAddress;
+
+/// (Descriptor) The segment was accessed. Set beforehand so that the processor never writes to
+/// the code and data descriptors.
+const DESCRIPTOR_ACCESSED: u64 = 1 << 40;
+/// (Descriptor) Data segments are writable, code segments are readable.
+const DESCRIPTOR_READ_WRITE: u64 = 1 << 41;
+/// (Descriptor) The segment holds code.
+const DESCRIPTOR_EXECUTABLE: u64 = 1 << 43;
+/// (Descriptor) Code or data segment, as opposed to a system segment.
+const DESCRIPTOR_CODE_OR_DATA: u64 = 1 << 44;
+/// (Descriptor) Shift of the privilege level of the segment.
+const DESCRIPTOR_PRIVILEGE_SHIFT: u64 = 45;
+/// (Descriptor) The descriptor is valid.
+const DESCRIPTOR_PRESENT: u64 = 1 << 47;
+/// (Descriptor) The code segment runs in 64-bit mode.
+const DESCRIPTOR_LONG_MODE: u64 = 1 << 53;
+/// (System descriptor) Type of an available 64-bit TSS.
+const DESCRIPTOR_TSS_AVAILABLE: u64 = 0x9 << 40;
+
+const DATA_DESCRIPTOR: u64 =
+    DESCRIPTOR_PRESENT | DESCRIPTOR_CODE_OR_DATA | DESCRIPTOR_READ_WRITE | DESCRIPTOR_ACCESSED;
+const CODE_DESCRIPTOR: u64 = DATA_DESCRIPTOR | DESCRIPTOR_EXECUTABLE | DESCRIPTOR_LONG_MODE;
+const USER_PRIVILEGE: u64 = (PrivilegeLevel::Ring3 as u64) << DESCRIPTOR_PRIVILEGE_SHIFT;
+
+/// Index of the TSS descriptor of the first processor in the GDT, each one spans two entries.
+const FIRST_TSS_INDEX: usize = 9;
+const GDT_ENTRIES: usize = FIRST_TSS_INDEX + 2 * MAX_CPUS;
+
+/// Global Descriptor Table shared by every processor. The kernel segments keep the slots they
+/// have in the GDT set up by Limine, the legacy segments before them are left null. The TSS
+/// descriptors are filled in by [`set_tss`], and marked busy by the processor when loaded.
+static mut GDT: [u64; GDT_ENTRIES] = {
+    let mut gdt = [0; GDT_ENTRIES];
+    gdt[5] = CODE_DESCRIPTOR;
+    gdt[6] = DATA_DESCRIPTOR;
+    gdt[7] = DATA_DESCRIPTOR | USER_PRIVILEGE;
+    gdt[8] = CODE_DESCRIPTOR | USER_PRIVILEGE;
+    gdt
+};
 
 pub mod selectors {
     use super::*;
@@ -9,6 +51,104 @@ pub mod selectors {
         SegmentSelector::new(5, DescriptorTable::GDT, PrivilegeLevel::Ring0);
     pub const DATA: SegmentSelector =
         SegmentSelector::new(6, DescriptorTable::GDT, PrivilegeLevel::Ring0);
+
+    // `sysret` requires the user data segment to directly precede the user code segment. The GDT
+    // set up by Limine has no user segments, these are only valid once `load_gdt` was called.
+    pub const USER_DATA: SegmentSelector =
+        SegmentSelector::new(7, DescriptorTable::GDT, PrivilegeLevel::Ring3);
+    pub const USER_CODE: SegmentSelector =
+        SegmentSelector::new(8, DescriptorTable::GDT, PrivilegeLevel::Ring3);
+}
+
+/// Load the kernel GDT on the current processor and reload the segment registers from it. The GS
+/// register is left untouched, as loading it would reset the base of the per-CPU area.
+pub fn load_gdt() {
+    let pointer = DescriptorTablePointer {
+        limit: (size_of::<[u64; GDT_ENTRIES]>() - 1) as u16,
+        base: VirtualAddress::from_ptr(&raw const GDT),
+    };
+
+    unsafe {
+        asm!(
+            "lgdt [{pointer}]",
+            // Far return to the next instruction to reload CS.
+            "push {code}",
+            "lea {scratch}, [rip + 2f]",
+            "push {scratch}",
+            "retfq",
+            "2:",
+            "mov ds, {data:x}",
+            "mov es, {data:x}",
+            "mov ss, {data:x}",
+            pointer = in(reg) &pointer,
+            code = in(reg) selectors::CODE.as_u16() as u64,
+            data = in(reg) selectors::DATA.as_u16(),
+            scratch = out(reg) _,
+            options(preserves_flags),
+        );
+    }
+}
+
+/// Write the descriptor of the Task State Segment of processor `index`, located at `tss`, in the
+/// GDT. Must be done before the processor loads it with [`load_tss`].
+pub fn set_tss(index: usize, tss: VirtualAddress) {
+    assert!(index < MAX_CPUS, "Invalid processor index: {index}");
+    let base = tss.value() as u64;
+    let limit = (size_of::<TaskStateSegment>() - 1) as u64;
+    let low = limit & 0xFFFF
+        | (base & 0xFF_FFFF) << 16
+        | DESCRIPTOR_TSS_AVAILABLE
+        | DESCRIPTOR_PRESENT
+        | (limit >> 16 & 0xF) << 48
+        | (base >> 24 & 0xFF) << 56;
+
+    let entry = FIRST_TSS_INDEX + 2 * index;
+    unsafe {
+        let gdt = &raw mut GDT;
+        (*gdt)[entry] = low;
+        (*gdt)[entry + 1] = base >> 32;
+    }
+}
+
+/// Load the Task State Segment of processor `index`, set with [`set_tss`], on the current
+/// processor.
+pub fn load_tss(index: usize) {
+    let selector = SegmentSelector::new(
+        (FIRST_TSS_INDEX + 2 * index) as u16,
+        DescriptorTable::GDT,
+        PrivilegeLevel::Ring0,
+    );
+    unsafe { asm!("ltr {:x}", in(reg) selector.as_u16(), options(nostack, preserves_flags)) };
+}
+
+/// Task State Segment, holding the stacks the processor switches to on privilege level changes
+/// and for the interrupt vectors using an Interrupt Stack Table entry.
+#[repr(C, packed(4))]
+pub struct TaskStateSegment {
+    _reserved_0: u32,
+    /// Stack pointers loaded when entering privilege levels 0 to 2.
+    pub privilege_stack_table: [VirtualAddress; 3],
+    _reserved_1: u64,
+    /// Stack pointers loaded by the vectors using Interrupt Stack Table entries 1 to 7.
+    pub interrupt_stack_table: [VirtualAddress; 7],
+    _reserved_2: u64,
+    _reserved_3: u16,
+    /// Offset of the I/O permission bitmap, which is past the end of the segment as there is none.
+    io_map_base: u16,
+}
+
+impl TaskStateSegment {
+    pub const fn new() -> Self {
+        Self {
+            _reserved_0: 0,
+            privilege_stack_table: [VirtualAddress::null(); 3],
+            _reserved_1: 0,
+            interrupt_stack_table: [VirtualAddress::null(); 7],
+            _reserved_2: 0,
+            _reserved_3: 0,
+            io_map_base: size_of::<Self>() as u16,
+        }
+    }
 }
 
 #[derive(Clone, Copy, Debug)]
@@ -31,6 +171,11 @@ impl SegmentSelector {
         Self(index << 3 | ((descriptor_table as u16) << 2) | privilege_level as u16)
     }
 
+    /// Returns the raw value of the selector, as loaded in a segment register.
+    pub const fn as_u16(&self) -> u16 {
+        self.0
+    }
+
     fn index(&self) -> u16 {
         self.0 >> 3
     }
@@ -52,3 +197,26 @@ impl Debug for SegmentSelector {
             .finish()
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test_case]
+    fn tss_descriptor_covers_the_segment() {
+        let tss = TaskStateSegment::new();
+        let address = VirtualAddress::from_ptr(&tss);
+        // The last processor slot is not used by the tests.
+        set_tss(MAX_CPUS - 1, address);
+
+        let entry = FIRST_TSS_INDEX + 2 * (MAX_CPUS - 1);
+        let (low, high) = unsafe {
+            let gdt = &raw const GDT;
+            ((*gdt)[entry], (*gdt)[entry + 1])
+        };
+        let base = (low >> 16 & 0xFF_FFFF) | (low >> 56 & 0xFF) << 24 | high << 32;
+        assert_eq!(size_of::<TaskStateSegment>(), 104);
+        assert_eq!(low & 0xFFFF, 103);
+        assert_eq!(base as usize, address.value());
+    }
+}
@@ -0,0 +1,60 @@
+//! Bring-up of the application processors (APs) started by the bootloader.
+//!
+//! Limine starts every processor and parks the APs until an entry point is written to their
+//! `goto_address`. Each processor then sets up its own [`percpu`] area, which is how the code
+//! running on it identifies it.
+
+use core::sync::atomic::{AtomicUsize, Ordering};
+
+use crate::cpu::{self, apic, interrupts, percpu, segments, syscall};
+use crate::limine;
+use crate::terminal::logger;
+
+/// Maximum number of processors brought up, the others are left parked.
+pub const MAX_CPUS: usize = 64;
+
+static NEXT_CPU_INDEX: AtomicUsize = AtomicUsize::new(1);
+static ONLINE_CPUS: AtomicUsize = AtomicUsize::new(1);
+
+/// Set up the per-CPU area of the bootstrap processor and start every application processor
+/// reported by the bootloader, waiting until they are all online.
+pub fn boot_aps() {
+    let bsp_lapic_id = limine::bsp_lapic_id().unwrap_or(apic::id() as u32);
+    percpu::allocate(limine::cpus().len().clamp(1, MAX_CPUS));
+    percpu::init(0, bsp_lapic_id);
+
+    let aps = limine::cpus()
+        .iter()
+        .filter(|cpu| cpu.lapic_id != bsp_lapic_id);
+    let mut started = 0;
+    for cpu in aps.take(MAX_CPUS - 1) {
+        cpu.goto_address.write(ap_entry);
+        started += 1;
+    }
+
+    while ONLINE_CPUS.load(Ordering::Acquire) < started + 1 {
+        core::hint::spin_loop();
+    }
+    logger::info!("{} processors online", cpu_count());
+}
+
+/// Returns the number of processors that are online.
+pub fn cpu_count() -> usize {
+    ONLINE_CPUS.load(Ordering::Acquire)
+}
+
+/// Entry point of the application processors. They share the GDT and the IDT of the bootstrap
+/// processor.
+unsafe extern "C" fn ap_entry(info: &::limine::mp::Cpu) -> ! {
+    segments::load_gdt();
+    let index = NEXT_CPU_INDEX.fetch_add(1, Ordering::Relaxed);
+    percpu::init(index, info.lapic_id);
+
+    interrupts::load();
+    apic::init();
+    syscall::init();
+    logger::info!("Processor {} online", index);
+    ONLINE_CPUS.fetch_add(1, Ordering::Release);
+
+    cpu::idle();
+}
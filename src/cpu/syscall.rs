@@ -0,0 +1,173 @@
+//! Fast system calls through the `syscall` and `sysret` instructions.
+//!
+//! The system call number is passed in `rax` and up to five arguments in `rdi`, `rsi`, `rdx`,
+//! `r10` and `r8`. The result is returned in `rax`, negative values being errors. Every other
+//! register except `rcx` and `r11`, which are clobbered by the instructions, is preserved.
+
+use core::arch::naked_asm;
+use core::fmt::Write;
+use core::{slice, str};
+
+use crate::cpu::percpu;
+use crate::cpu::registers::{Msr, RFlags};
+use crate::cpu::segments::selectors;
+use crate::cpu::{power, time};
+use crate::terminal::logger;
+use crate::terminal::tty::TerminalStdin;
+
+/// (EFER) System call extensions, enables `syscall` and `sysret`.
+const EFER_SYSCALL_ENABLE: u64 = 1 << 0;
+
+/// Size of the stack the system calls run on, one per processor.
+pub const SYSCALL_STACK_SIZE: usize = 16 * 1024;
+
+/// End of the lower half of the address space, where user space buffers must lie.
+const USER_SPACE_END: usize = 0x0000_8000_0000_0000;
+
+/// Numbers identifying the system calls.
+#[derive(Clone, Copy, Debug)]
+#[repr(usize)]
+pub enum Syscall {
+    /// Write the UTF-8 string at `(pointer, length)` to the terminal, returns the length written.
+    Write = 0,
+    /// Returns the number of milliseconds elapsed since the timer was initialized.
+    Uptime = 1,
+    /// Power off the machine, does not return.
+    Shutdown = 2,
+    /// Reset the machine, does not return.
+    Reboot = 3,
+}
+
+impl TryFrom<usize> for Syscall {
+    type Error = SyscallError;
+
+    fn try_from(value: usize) -> Result<Self, Self::Error> {
+        match value {
+            0 => Ok(Self::Write),
+            1 => Ok(Self::Uptime),
+            2 => Ok(Self::Shutdown),
+            3 => Ok(Self::Reboot),
+            _ => Err(SyscallError::UnknownSyscall),
+        }
+    }
+}
+
+/// Errors returned by system calls, as their negated value.
+#[derive(Clone, Copy, Debug)]
+#[repr(isize)]
+pub enum SyscallError {
+    UnknownSyscall = 1,
+    InvalidArgument = 2,
+    IoError = 3,
+}
+
+/// Enable system call extensions and install the entry point of `syscall` on the current
+/// processor, whose per-CPU area must be initialized.
+pub fn init() {
+    // `sysret` loads SS from the base selector + 8 and CS from the base selector + 16.
+    let sysret_base = selectors::USER_DATA.as_u16() as u64 - 8;
+    let star = sysret_base << 48 | (selectors::CODE.as_u16() as u64) << 32;
+    let fmask = RFlags::INTERRUPT_ENABLE | RFlags::DIRECTION | RFlags::TRAP;
+
+    unsafe {
+        Msr::IA32_STAR.write(star);
+        Msr::IA32_LSTAR.write(syscall_entry as *const () as u64);
+        Msr::IA32_FMASK.write(fmask.bits() as u64);
+
+        let efer = Msr::IA32_EFER.read();
+        Msr::IA32_EFER.write(efer | EFER_SYSCALL_ENABLE);
+    }
+    logger::info!("System calls enabled");
+}
+
+/// Entry point of `syscall`: switch to the system call stack of the processor, forward the call
+/// to [`handle_syscall`] and return to the caller.
+///
+/// Interrupts are disabled by [`init`]'s flag mask for the whole call, the system call stack
+/// can't be re-entered.
+#[unsafe(naked)]
+unsafe extern "C" fn syscall_entry() -> ! {
+    naked_asm!(
+        // Switch to the per-CPU area of the kernel.
+        "swapgs",
+        "mov gs:[{caller_stack}], rsp",
+        "mov rsp, gs:[{stack_top}]",
+        "push qword ptr gs:[{caller_stack}]",
+        // Return address and flags of the caller, used by `sysret`.
+        "push rcx",
+        "push r11",
+        "push rdi",
+        "push rsi",
+        "push rdx",
+        "push r10",
+        "push r8",
+        "push r9",
+        // Realign the stack on 16 bytes after the 9 pushes.
+        "sub rsp, 8",
+        // Shift the arguments to the System V calling convention.
+        "mov r9, r8",
+        "mov r8, r10",
+        "mov rcx, rdx",
+        "mov rdx, rsi",
+        "mov rsi, rdi",
+        "mov rdi, rax",
+        "call {handler}",
+        "add rsp, 8",
+        "pop r9",
+        "pop r8",
+        "pop r10",
+        "pop rdx",
+        "pop rsi",
+        "pop rdi",
+        "pop r11",
+        "pop rcx",
+        "pop rsp",
+        "swapgs",
+        "sysretq",
+        caller_stack = const percpu::CALLER_STACK_POINTER_OFFSET,
+        stack_top = const percpu::SYSCALL_STACK_TOP_OFFSET,
+        handler = sym handle_syscall,
+    )
+}
+
+/// Run the system call `number` and returns its result, or the negated error.
+extern "sysv64" fn handle_syscall(
+    number: usize,
+    arg0: usize,
+    arg1: usize,
+    _arg2: usize,
+    _arg3: usize,
+    _arg4: usize,
+) -> isize {
+    let result = Syscall::try_from(number).and_then(|syscall| match syscall {
+        Syscall::Write => write(arg0 as *const u8, arg1),
+        Syscall::Uptime => Ok(time::uptime_ms() as isize),
+        Syscall::Shutdown => power::shutdown(),
+        Syscall::Reboot => power::reboot(),
+    });
+
+    match result {
+        Ok(value) => value,
+        Err(error) => -(error as isize),
+    }
+}
+
+fn write(pointer: *const u8, length: usize) -> Result<isize, SyscallError> {
+    // The buffer must lie in the lower half, so that user space can't make the kernel read its
+    // own memory.
+    let in_user_space = (pointer as usize)
+        .checked_add(length)
+        .is_some_and(|end| end <= USER_SPACE_END);
+    if pointer.is_null() || !in_user_space {
+        return Err(SyscallError::InvalidArgument);
+    }
+
+    // The mapping of the buffer is trusted until user space has page tables of its own.
+    let bytes = unsafe { slice::from_raw_parts(pointer, length) };
+    let text = str::from_utf8(bytes).map_err(|_| SyscallError::InvalidArgument)?;
+
+    TerminalStdin::new()
+        .write_str(text)
+        .map_err(|_| SyscallError::IoError)?;
+    Ok(length as isize)
+}
@@ -0,0 +1,150 @@
+//! Time keeping based on a periodic timer interrupt.
+//!
+//! Ticks are first driven by the legacy 8253/8254 Programmable Interval Timer (PIT), which is
+//! then used to calibrate the local APIC timer, taking over with [`switch_to_apic_timer`]. The
+//! HPET is used for the calibration instead of the PIT when the machine has one.
+
+use core::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+
+use crate::cpu::apic;
+use crate::cpu::interrupts::{self, InterruptStackFrame, ioapic, pic};
+use crate::cpu::port::outb;
+use crate::drivers::hpet;
+use crate::sync::IrqMutex;
+use crate::terminal::logger;
+
+const PIT_CHANNEL_0: u16 = 0x40;
+const PIT_COMMAND: u16 = 0x43;
+
+/// (Command) Select channel 0, access mode lobyte/hibyte, mode 3 (square wave generator), binary.
+const CHANNEL_0_SQUARE_WAVE: u8 = 0x36;
+
+/// Frequency of the PIT oscillator in Hz.
+const PIT_BASE_FREQUENCY: u64 = 1_193_182;
+
+/// Frequency of the timer interrupt in Hz.
+pub const TIMER_FREQUENCY: u64 = 100;
+
+/// IRQ line of the PIT.
+pub const TIMER_IRQ: u8 = 0;
+
+/// Number of ticks over which the local APIC timer is calibrated.
+const CALIBRATION_TICKS: u64 = 10;
+/// Duration of a tick of the timer, in nanoseconds.
+const NANOSECONDS_PER_TICK: u64 = 1_000_000_000 / TIMER_FREQUENCY;
+
+/// Maximum number of callbacks that can be registered with [`on_tick`].
+const MAX_TICK_CALLBACKS: usize = 4;
+
+static TICKS: AtomicU64 = AtomicU64::new(0);
+static INITIALIZED: AtomicBool = AtomicBool::new(false);
+/// Function called on every timer interrupt with the current tick count.
+type TickCallback = fn(u64);
+
+static TICK_CALLBACKS: IrqMutex<[Option<TickCallback>; MAX_TICK_CALLBACKS]> =
+    IrqMutex::new([None; MAX_TICK_CALLBACKS]);
+
+/// Configure channel 0 of the PIT to fire [`TIMER_FREQUENCY`] interrupts per second, register
+/// its handler and unmask its IRQ line.
+pub fn init() {
+    let divisor = (PIT_BASE_FREQUENCY / TIMER_FREQUENCY) as u16;
+    unsafe {
+        outb(PIT_COMMAND, CHANNEL_0_SQUARE_WAVE);
+        outb(PIT_CHANNEL_0, (divisor & 0xFF) as u8);
+        outb(PIT_CHANNEL_0, (divisor >> 8) as u8);
+    }
+    interrupts::register(pic::vector_for(TIMER_IRQ), timer_interrupt_handler);
+    interrupts::enable_legacy_irq(TIMER_IRQ);
+    INITIALIZED.store(true, Ordering::Release);
+}
+
+/// Calibrate the local APIC timer against the HPET, or the PIT if there is none, and use it to
+/// drive the ticks from now on, masking the PIT along with the rest of the PIC if the I/O APIC took
+/// over the legacy IRQs. Interrupts must be enabled, otherwise this never returns.
+pub fn switch_to_apic_timer() {
+    let (counts, source) = if hpet::is_available() {
+        let counts = apic::measure_timer(|| {
+            hpet::busy_sleep_ns(CALIBRATION_TICKS * NANOSECONDS_PER_TICK);
+        });
+        (counts, "HPET")
+    } else {
+        // Start measuring right after a tick, so that the whole first tick period is measured.
+        let start = ticks() + 1;
+        while ticks() < start {
+            core::hint::spin_loop();
+        }
+        let counts = apic::measure_timer(|| {
+            while ticks() < start + CALIBRATION_TICKS {
+                core::hint::spin_loop();
+            }
+        });
+        (counts, "PIT")
+    };
+    let counts_per_tick = counts / CALIBRATION_TICKS as u32;
+
+    interrupts::without_interrupts(|| {
+        interrupts::disable_legacy_irq(TIMER_IRQ);
+        // Without an I/O APIC, the PIC still delivers the other legacy IRQs.
+        if ioapic::is_initialized() {
+            pic::mask_all();
+        }
+        interrupts::unregister(pic::vector_for(TIMER_IRQ));
+        interrupts::register(apic::TIMER_VECTOR, timer_interrupt_handler);
+        apic::start_periodic_timer(apic::TIMER_VECTOR, counts_per_tick);
+    });
+    logger::info!(
+        "Local APIC timer calibrated to {} counts per tick against the {}",
+        counts_per_tick,
+        source
+    );
+}
+
+/// Returns whether the timer has been initialized, the tick counter is meaningless before that.
+#[inline]
+pub fn is_initialized() -> bool {
+    INITIALIZED.load(Ordering::Acquire)
+}
+
+/// Returns the number of timer interrupts received since the timer was initialized.
+#[inline]
+pub fn ticks() -> u64 {
+    TICKS.load(Ordering::Relaxed)
+}
+
+/// Returns the number of milliseconds elapsed since the timer was initialized.
+#[inline]
+pub fn uptime_ms() -> u64 {
+    ticks() * 1000 / TIMER_FREQUENCY
+}
+
+/// Register `callback` to be called with the current tick count on every timer interrupt.
+///
+/// Callbacks run in interrupt context: they must be short and must not wait on locks that may be
+/// held by the interrupted code.
+pub fn on_tick(callback: TickCallback) {
+    let mut callbacks = TICK_CALLBACKS.lock();
+    let slot = callbacks
+        .iter_mut()
+        .find(|slot| slot.is_none())
+        .expect("Cannot register more tick callbacks");
+    *slot = Some(callback);
+}
+
+/// Busy-wait for at least `ms` milliseconds. Interrupts must be enabled, otherwise this never
+/// returns.
+pub fn sleep_ms(ms: u64) {
+    // The current tick is already partly elapsed, so one more tick is waited for.
+    let target = ticks() + (ms * TIMER_FREQUENCY).div_ceil(1000) + 1;
+    while ticks() < target {
+        core::hint::spin_loop();
+    }
+}
+
+fn timer_interrupt_handler(_stack_frame: &InterruptStackFrame) {
+    let ticks = TICKS.fetch_add(1, Ordering::Relaxed) + 1;
+
+    let callbacks = *TICK_CALLBACKS.lock();
+    for callback in callbacks.iter().flatten() {
+        callback(ticks);
+    }
+}
@@ -0,0 +1,42 @@
+//! Invalidation of the translation lookaside buffer (TLB), which caches page translations and
+//! must be flushed when a mapping of the active address space changes.
+
+use core::arch::asm;
+
+use crate::memory::{PAGE_SIZE, VirtualAddress};
+
+/// Number of pages above which [`flush_range`] flushes the whole TLB rather than each page.
+const FLUSH_ALL_THRESHOLD: usize = 32;
+
+/// Invalidate the TLB entries of the page containing `address`.
+#[inline]
+pub fn flush(address: VirtualAddress) {
+    unsafe { asm!("invlpg [{}]", in(reg) address.value(), options(nostack, preserves_flags)) };
+}
+
+/// Invalidate every non-global TLB entry by reloading CR3.
+#[inline]
+pub fn flush_all() {
+    unsafe {
+        asm!(
+            "mov {0}, cr3",
+            "mov cr3, {0}",
+            out(reg) _,
+            options(nostack, preserves_flags),
+        )
+    };
+}
+
+/// Invalidate the TLB entries of the pages overlapping the `size` bytes starting at `start`.
+pub fn flush_range(start: VirtualAddress, size: usize) {
+    let first = start.align_down(PAGE_SIZE).value();
+    let pages = (start.value() + size - first).div_ceil(PAGE_SIZE);
+    if pages > FLUSH_ALL_THRESHOLD {
+        flush_all();
+        return;
+    }
+
+    for page in 0..pages {
+        flush(VirtualAddress::from(first + page * PAGE_SIZE));
+    }
+}
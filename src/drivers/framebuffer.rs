@@ -1,15 +1,23 @@
 use core::ops::{Bound, RangeBounds};
+use core::sync::atomic::{AtomicUsize, Ordering};
 use core::{fmt, slice};
 
 use spin::{Mutex, MutexGuard, Once};
 
 use crate::memory::{align_up, frame_allocator};
-use crate::terminal::logger;
 use crate::terminal::tty::TERMINAL;
+use crate::terminal::{font, logger};
 use crate::{LOGGER, limine};
 
 static DRIVER: Once<FramebufferDriver> = Once::new();
 
+/// Position of the next character drawn by [`early_print`], in characters.
+static EARLY_COLUMN: AtomicUsize = AtomicUsize::new(0);
+static EARLY_LINE: AtomicUsize = AtomicUsize::new(0);
+
+/// Log sink printing with [`early_print`] until the terminal is initialized.
+static EARLY_CONSOLE: Mutex<EarlyConsole> = Mutex::new(EarlyConsole);
+
 pub struct FramebufferDriver {
     info: FramebufferInfo,
     device: Mutex<Framebuffer<'static>>,
@@ -29,7 +37,7 @@ pub fn init() {
         slice::from_raw_parts_mut(back_ptr, buffer_size)
     };
 
-    let front_buffer = unsafe { slice::from_raw_parts_mut(front_ptr as *mut u32, buffer_size) };
+    let front_buffer = front_ptr as *mut u32;
 
     let primary_framebuffer = Framebuffer {
         info,
@@ -61,11 +69,80 @@ impl FramebufferDriver {
 unsafe impl Send for FramebufferDriver {}
 unsafe impl Sync for FramebufferDriver {}
 
+/// Print `s` in white on black straight to the screen provided by the bootloader, for use
+/// before the terminal exists. Requires neither [`init`] nor the frame allocator.
+///
+/// Text starts at the top-left corner and wraps around to the top once the bottom of the screen
+/// is reached. ANSI escape sequences are skipped.
+pub fn early_print(s: &str) {
+    let Some((front_buffer, info)) = limine::try_first_framebuffer() else {
+        return;
+    };
+    let front_buffer = front_buffer as *mut u32;
+    let columns = info.width / font::WIDTH;
+    let lines = info.height / font::HEIGHT;
+
+    let mut column = EARLY_COLUMN.load(Ordering::Relaxed);
+    let mut line = EARLY_LINE.load(Ordering::Relaxed);
+    let mut in_escape = false;
+    for ch in s.chars() {
+        if in_escape {
+            in_escape = !ch.is_ascii_alphabetic();
+            continue;
+        }
+        if ch == '\x1b' {
+            in_escape = true;
+            continue;
+        }
+        if ch == '\n' || column == columns {
+            column = 0;
+            line = (line + 1) % lines;
+            if ch == '\n' {
+                continue;
+            }
+        }
+
+        let raster = font::get_raster(ch).unwrap_or_default();
+        for y in 0..font::HEIGHT {
+            for x in 0..font::WIDTH {
+                let alpha = raster.get(y).and_then(|row| row.get(x)).copied();
+                let color = RGB::alpha_blend(RGB::WHITE, RGB::BLACK, alpha.unwrap_or(0));
+                let offset = (line * font::HEIGHT + y) * info.pitch + column * font::WIDTH + x;
+                unsafe { front_buffer.add(offset).write_volatile(info.pack(color)) };
+            }
+        }
+        column += 1;
+    }
+
+    EARLY_COLUMN.store(column, Ordering::Relaxed);
+    EARLY_LINE.store(line, Ordering::Relaxed);
+}
+
+/// Returns a log sink printing with [`early_print`], which stops printing once the terminal is
+/// initialized as the terminal registers its own sink.
+pub fn early_console() -> &'static Mutex<EarlyConsole> {
+    &EARLY_CONSOLE
+}
+
+pub struct EarlyConsole;
+
+impl fmt::Write for EarlyConsole {
+    fn write_str(&mut self, s: &str) -> fmt::Result {
+        if TERMINAL.get().is_none() {
+            early_print(s);
+        }
+        Ok(())
+    }
+}
+
 #[derive(Clone, Copy, Debug)]
 pub struct FramebufferInfo {
     pitch: usize,
     width: usize,
     height: usize,
+    red_shift: u8,
+    green_shift: u8,
+    blue_shift: u8,
 }
 
 impl FramebufferInfo {
@@ -89,12 +166,16 @@ impl FramebufferInfo {
             }
         }
 
-        // Ensure colors use the standard 0RGB layout
-        if buffer.red_mask_shift() != 16
-            || buffer.green_mask_shift() != 8
-            || buffer.blue_mask_shift() != 0
-        {
-            return None;
+        // Ensure each color is byte aligned and the colors don't overlap (e.g. 0RGB or 0BGR)
+        let shifts = [
+            buffer.red_mask_shift(),
+            buffer.green_mask_shift(),
+            buffer.blue_mask_shift(),
+        ];
+        for (i, shift) in shifts.iter().enumerate() {
+            if shift % 8 != 0 || *shift > 24 || shifts[..i].contains(shift) {
+                return None;
+            }
         }
 
         // Ensure potential padding is pixel sized
@@ -108,10 +189,21 @@ impl FramebufferInfo {
                 pitch: buffer.pitch() as usize / bytes_per_pixel,
                 width: buffer.width() as usize,
                 height: buffer.height() as usize,
+                red_shift: shifts[0],
+                green_shift: shifts[1],
+                blue_shift: shifts[2],
             },
         ))
     }
 
+    /// Convert `color` to the pixel layout of the framebuffer.
+    #[inline(always)]
+    pub fn pack(&self, color: RGB) -> u32 {
+        (color.red() as u32) << self.red_shift
+            | (color.green() as u32) << self.green_shift
+            | (color.blue() as u32) << self.blue_shift
+    }
+
     #[inline]
     pub fn width(&self) -> usize {
         self.width
@@ -137,25 +229,80 @@ pub struct Framebuffer<'a> {
     info: FramebufferInfo,
     back_buffer_cursor: usize,
     back_buffer: &'a mut [u32],
-    front_buffer: &'a mut [u32],
+    /// Start of the MMIO buffer displayed on screen, holds `buffer_len()` pixels.
+    front_buffer: *mut u32,
 }
 
 impl<'a> Framebuffer<'a> {
+    /// Set the pixel at (`x`, `y`) in the back buffer. Coordinates outside of the screen are
+    /// ignored.
     #[inline(always)]
     pub fn set_pixel(&mut self, x: usize, y: usize, color: RGB) {
+        if x >= self.info.width || y >= self.info.height {
+            return;
+        }
+
         self.back_buffer
             [(x + y * self.info.pitch + self.back_buffer_cursor) % self.back_buffer.len()] =
-            color.into();
+            self.info.pack(color);
     }
 
+    /// Returns the `width` pixels of the back buffer starting at (`x`, `y`), clipped to the screen.
+    /// Rows never wrap around the back buffer as it scrolls by whole rows.
     #[inline(always)]
-    pub fn update_from_slice(&mut self) -> &mut [u32] {
+    fn row_mut(&mut self, x: usize, y: usize, width: usize) -> &mut [u32] {
+        let width = width.min(self.info.width.saturating_sub(x));
+        let start = (y * self.info.pitch + self.back_buffer_cursor) % self.back_buffer.len() + x;
+        &mut self.back_buffer[start..start + width]
+    }
+
+    /// Fill the `width` by `height` rectangle with its top-left corner at (`x`, `y`) with `color`.
+    /// The parts of the rectangle outside of the screen are ignored.
+    pub fn fill_rect(&mut self, x: usize, y: usize, width: usize, height: usize, color: RGB) {
+        if x >= self.info.width {
+            return;
+        }
+
+        let pixel = self.info.pack(color);
+        for row in y..(y + height).min(self.info.height) {
+            self.row_mut(x, row, width).fill(pixel);
+        }
+    }
+
+    /// Copy the `width` by `height` pixels of `src`, whose rows are `src_pitch` pixels apart, to
+    /// the rectangle with its top-left corner at (`x`, `y`). The parts of the rectangle outside
+    /// of the screen are ignored. The pixels of `src` must use the framebuffer's layout, see
+    /// [`FramebufferInfo::pack`].
+    pub fn blit(
+        &mut self,
+        x: usize,
+        y: usize,
+        src: &[u32],
+        src_pitch: usize,
+        width: usize,
+        height: usize,
+    ) {
+        if x >= self.info.width {
+            return;
+        }
+
+        for (i, row) in (y..(y + height).min(self.info.height)).enumerate() {
+            let destination = self.row_mut(x, row, width);
+            let len = destination.len();
+            destination.copy_from_slice(&src[i * src_pitch..i * src_pitch + len]);
+        }
+    }
+
+    /// Returns the back buffer, which is only copied to the screen on [`Framebuffer::refresh`].
+    /// Note: The back buffer is used as a ring, the first on-screen pixel is at the scroll offset.
+    #[inline(always)]
+    pub fn get_back_buffer_mut(&mut self) -> &mut [u32] {
         self.back_buffer
     }
 
     #[inline(always)]
     pub fn fill(&mut self, color: RGB) {
-        self.back_buffer.fill(color.into())
+        self.back_buffer.fill(self.info.pack(color))
     }
 
     pub fn partial_fill(&mut self, range: impl RangeBounds<usize>, color: RGB) {
@@ -174,31 +321,69 @@ impl<'a> Framebuffer<'a> {
         assert!(start <= end);
         assert!(end <= self.back_buffer.len());
 
+        let pixel = self.info.pack(color);
         let (head, tail) = self.back_buffer.split_at_mut(self.back_buffer_cursor);
 
         if start < tail.len() {
             let tail_end = end.min(tail.len());
-            tail[start..tail_end].fill(color.into());
+            tail[start..tail_end].fill(pixel);
         }
 
         if end > tail.len() {
             let head_start = start.saturating_sub(tail.len());
             let head_end = end - tail.len();
-            head[head_start..head_end].fill(color.into());
+            head[head_start..head_end].fill(pixel);
         }
     }
 
+    /// Copy the back buffer to the screen.
     #[inline(always)]
     pub fn refresh(&mut self) {
         let (head, tail) = self.back_buffer.split_at(self.back_buffer_cursor);
-        self.front_buffer[..tail.len()].copy_from_slice(tail);
-        self.front_buffer[tail.len()..].copy_from_slice(head);
+        unsafe {
+            Self::copy_to_front(self.front_buffer, tail);
+            Self::copy_to_front(self.front_buffer.add(tail.len()), head);
+        }
+    }
+
+    /// Copy the `height` pixel rows of the back buffer starting at row `y` to the screen. Rows
+    /// outside of the screen are ignored.
+    pub fn refresh_rows(&mut self, y: usize, height: usize) {
+        let pitch = self.info.pitch;
+        for row in y..(y + height).min(self.info.height) {
+            let start = (row * pitch + self.back_buffer_cursor) % self.back_buffer.len();
+            unsafe {
+                Self::copy_to_front(
+                    self.front_buffer.add(row * pitch),
+                    &self.back_buffer[start..start + pitch],
+                )
+            };
+        }
+    }
+
+    /// Copy `pixels` to the front buffer at `destination`. The front buffer is MMIO, so each write
+    /// is volatile to prevent the compiler from eliding or merging them.
+    ///
+    /// SAFETY: `destination` must be valid for `pixels.len()` writes.
+    #[inline(always)]
+    unsafe fn copy_to_front(destination: *mut u32, pixels: &[u32]) {
+        for (i, &pixel) in pixels.iter().enumerate() {
+            unsafe { destination.add(i).write_volatile(pixel) };
+        }
     }
 
     pub fn scroll(&mut self, height: usize) {
         self.back_buffer_cursor = (self.back_buffer_cursor + self.info.pitch * height) % self.back_buffer.len();
     }
 
+    /// Shift the content of the back buffer up by `pixels` rows and fill the rows exposed at the
+    /// bottom with `fill`. Only the start of the ring is moved, no pixel is copied.
+    pub fn scroll_up(&mut self, pixels: usize, fill: RGB) {
+        let pixels = pixels.min(self.info.height);
+        self.scroll(pixels);
+        self.fill_rect(0, self.info.height - pixels, self.info.width, pixels, fill);
+    }
+
     #[inline]
     pub fn width(&self) -> usize {
         self.info.width
@@ -221,7 +406,7 @@ impl<'a> Framebuffer<'a> {
 }
 
 #[allow(clippy::upper_case_acronyms)]
-#[derive(Clone, Copy)]
+#[derive(Clone, Copy, PartialEq, Eq)]
 pub struct RGB(u32);
 
 impl RGB {
@@ -255,6 +440,27 @@ impl RGB {
         let blue = (fg.blue() as u64 * alpha + (255 - alpha) * bg.blue() as u64) / 255;
         RGB::new(red as u8, green as u8, blue as u8)
     }
+
+    /// Returns the color with the channels multiplied by `alpha`, i.e. blended over black.
+    pub const fn from_rgba(r: u8, g: u8, b: u8, alpha: u8) -> Self {
+        Self::new(r, g, b).scale(alpha)
+    }
+
+    /// Returns the color `t / 255` of the way from `a` to `b`.
+    pub const fn lerp(a: RGB, b: RGB, t: u8) -> RGB {
+        Self::alpha_blend(b, a, t)
+    }
+
+    /// Returns the color with each channel multiplied by `factor / 255`.
+    pub const fn scale(&self, factor: u8) -> RGB {
+        Self::alpha_blend(*self, Self::BLACK, factor)
+    }
+
+    /// Returns the perceived brightness of the color, using the Rec. 601 weights of the channels.
+    pub const fn luminance(&self) -> u8 {
+        let weighted = 77 * self.red() as u32 + 150 * self.green() as u32 + 29 * self.blue() as u32;
+        (weighted >> 8) as u8
+    }
 }
 
 impl RGB {
@@ -291,3 +497,16 @@ impl fmt::Debug for RGB {
             .finish()
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test_case]
+    fn color_helpers_handle_their_bounds() {
+        let (a, b) = (RGB::new(12, 34, 56), RGB::new(200, 100, 50));
+        assert_eq!(RGB::lerp(a, b, 0), a);
+        assert_eq!(RGB::WHITE.luminance(), 255);
+        assert_eq!(b.scale(0), RGB::BLACK);
+    }
+}
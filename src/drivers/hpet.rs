@@ -0,0 +1,106 @@
+//! High Precision Event Timer, used as a monotonic clock with a resolution of a few nanoseconds.
+//!
+//! Only the main counter is used: it is started once and counts up at the period advertised in
+//! the capabilities register, none of the comparators are programmed.
+
+use spin::Once;
+
+use crate::acpi;
+use crate::memory::mmio::{Mmio, ReadOnly, ReadWrite};
+use crate::terminal::logger;
+
+/// (Capabilities) The main counter is 64 bits wide. A 32-bit counter wraps around every few
+/// minutes, which [`now_ns`] does not account for.
+const CAPABILITIES_64_BIT_COUNTER: u64 = 1 << 13;
+/// (Capabilities) Shift of the period of the main counter, in femtoseconds.
+const CAPABILITIES_PERIOD_SHIFT: u64 = 32;
+/// (Configuration) Start the main counter.
+const CONFIGURATION_ENABLE: u64 = 1 << 0;
+
+/// Largest period allowed by the specification, in femtoseconds (100 ns).
+const MAX_PERIOD_FS: u64 = 100_000_000;
+const FEMTOSECONDS_PER_NANOSECOND: u64 = 1_000_000;
+
+static HPET: Once<Hpet> = Once::new();
+
+/// General registers of the timer block.
+#[repr(C)]
+struct Registers {
+    capabilities: ReadOnly<u64>,
+    _reserved_0: u64,
+    configuration: ReadWrite<u64>,
+    _reserved_1: u64,
+    interrupt_status: ReadWrite<u64>,
+    _reserved_2: [u64; 25],
+    main_counter: ReadWrite<u64>,
+}
+
+struct Hpet {
+    registers: Mmio<Registers>,
+    /// Period of the main counter, in femtoseconds.
+    period_fs: u64,
+}
+
+/// Locate the HPET through ACPI and start its main counter. Does nothing but log a warning if
+/// the machine has none.
+pub fn init() {
+    let Some(base_address) = acpi::hpet().and_then(|hpet| hpet.base_address()) else {
+        logger::warning!("No HPET found");
+        return;
+    };
+
+    let registers = unsafe { Mmio::<Registers>::map(base_address) };
+    let capabilities = registers.capabilities.read();
+    if capabilities & CAPABILITIES_64_BIT_COUNTER == 0 {
+        logger::warning!("Ignoring the HPET with a 32-bit main counter");
+        return;
+    }
+
+    let period_fs = capabilities >> CAPABILITIES_PERIOD_SHIFT;
+    if period_fs == 0 || period_fs > MAX_PERIOD_FS {
+        logger::warning!(
+            "Ignoring the HPET with an invalid period of {} fs",
+            period_fs
+        );
+        return;
+    }
+
+    registers
+        .configuration
+        .update(|configuration| configuration & !CONFIGURATION_ENABLE);
+    registers.main_counter.write(0);
+    registers
+        .configuration
+        .update(|configuration| configuration | CONFIGURATION_ENABLE);
+
+    HPET.call_once(|| Hpet {
+        registers,
+        period_fs,
+    });
+    logger::info!(
+        "HPET initialized at {:?} with a period of {} fs",
+        base_address,
+        period_fs
+    );
+}
+
+/// Returns whether the HPET was found and started by [`init`].
+pub fn is_available() -> bool {
+    HPET.get().is_some()
+}
+
+/// Returns the number of nanoseconds elapsed since [`init`].
+pub fn now_ns() -> u64 {
+    let hpet = HPET.get().expect("The HPET is not initialized");
+    let counter = hpet.registers.main_counter.read() as u128;
+    (counter * hpet.period_fs as u128 / FEMTOSECONDS_PER_NANOSECOND as u128) as u64
+}
+
+/// Busy-wait for at least `ns` nanoseconds. Works with interrupts disabled, unlike
+/// [`crate::cpu::time::sleep_ms`].
+pub fn busy_sleep_ns(ns: u64) {
+    let target = now_ns() + ns;
+    while now_ns() < target {
+        core::hint::spin_loop();
+    }
+}
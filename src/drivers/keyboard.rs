@@ -0,0 +1,353 @@
+//! Driver for the PS/2 keyboard, decoding scan code set 1.
+//!
+//! The interrupt handler decodes scan codes into [`KeyEvent`]s and pushes them into a ring
+//! buffer, which is consumed through [`poll`] and [`read_char`]. Replies of the terminal to
+//! queries, such as the cursor position, are queued with [`inject_input`] and read before the
+//! key events.
+
+use core::mem::MaybeUninit;
+
+use spin::Mutex;
+
+use crate::cpu::interrupts::{self, InterruptStackFrame, pic};
+use crate::cpu::port::inb;
+use crate::sync::IrqMutex;
+
+const DATA_PORT: u16 = 0x60;
+const STATUS_PORT: u16 = 0x64;
+
+/// (Status) Set when the output buffer contains data to be read from [`DATA_PORT`].
+const STATUS_OUTPUT_FULL: u8 = 1 << 0;
+
+/// Prefix announcing an extended scan code.
+const EXTENDED_PREFIX: u8 = 0xE0;
+/// Bit set in a scan code when the key is released.
+const RELEASE_BIT: u8 = 0x80;
+
+/// IRQ line of the PS/2 keyboard.
+pub const KEYBOARD_IRQ: u8 = 1;
+
+/// Capacity of the key event buffer. Events received while it is full are dropped.
+const EVENT_BUFFER_SIZE: usize = 128;
+/// Capacity of the injected input buffer. Characters injected while it is full are dropped.
+const INJECTED_BUFFER_SIZE: usize = 64;
+
+static EVENTS: IrqMutex<RingBuffer<KeyEvent, EVENT_BUFFER_SIZE>> = IrqMutex::new(RingBuffer::new());
+static INJECTED: IrqMutex<RingBuffer<char, INJECTED_BUFFER_SIZE>> =
+    IrqMutex::new(RingBuffer::new());
+static DECODER: Mutex<Decoder> = Mutex::new(Decoder::new());
+
+/// Discard any pending scan code, register the interrupt handler and unmask the keyboard IRQ line.
+pub fn init() {
+    unsafe {
+        while inb(STATUS_PORT) & STATUS_OUTPUT_FULL != 0 {
+            inb(DATA_PORT);
+        }
+    }
+    interrupts::register(pic::vector_for(KEYBOARD_IRQ), keyboard_interrupt_handler);
+    interrupts::enable_legacy_irq(KEYBOARD_IRQ);
+}
+
+/// Returns the oldest unread key event, if any.
+///
+/// Events are consumed by the first caller, so there should be a single consumer of the keyboard
+/// input at any time.
+pub fn poll() -> Option<KeyEvent> {
+    EVENTS.lock().pop()
+}
+
+/// Queue `text` as input, to be read by [`read_char`] before any pending key event.
+pub fn inject_input(text: &str) {
+    let mut injected = INJECTED.lock();
+    for ch in text.chars() {
+        injected.push(ch);
+    }
+}
+
+/// Wait for an injected character or a key press producing a character and returns it. Events
+/// that do not produce a character are discarded. Interrupts must be enabled, otherwise this
+/// never returns.
+pub fn read_char() -> char {
+    loop {
+        let injected = INJECTED.lock().pop();
+        match injected.or_else(|| poll().and_then(|event| event.to_char())) {
+            Some(ch) => return ch,
+            None => core::hint::spin_loop(),
+        }
+    }
+}
+
+fn keyboard_interrupt_handler(_stack_frame: &InterruptStackFrame) {
+    let scan_code = unsafe { inb(DATA_PORT) };
+
+    if let Some(event) = DECODER.lock().decode(scan_code) {
+        EVENTS.lock().push(event);
+    }
+}
+
+/// Physical key identified by a scan code.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum KeyCode {
+    Escape,
+    Backspace,
+    Tab,
+    Enter,
+    Space,
+    LeftCtrl,
+    RightCtrl,
+    LeftShift,
+    RightShift,
+    LeftAlt,
+    RightAlt,
+    CapsLock,
+    NumLock,
+    ScrollLock,
+    /// Function key `F1` to `F12`.
+    Function(u8),
+    Up,
+    Down,
+    Left,
+    Right,
+    Home,
+    End,
+    PageUp,
+    PageDown,
+    Insert,
+    Delete,
+    /// Key producing a printable character, identified by its unshifted character.
+    Printable(char),
+    /// Key of the numeric keypad producing a printable character.
+    Keypad(char),
+    Unknown(u8),
+}
+
+impl KeyCode {
+    fn from_scan_code(code: u8) -> Self {
+        const PRINTABLE: &[u8; 0x3A] =
+            b"\0\x001234567890-=\0\0qwertyuiop[]\0\0asdfghjkl;'`\0\\zxcvbnm,./\0*\0 ";
+
+        match code {
+            0x01 => Self::Escape,
+            0x0E => Self::Backspace,
+            0x0F => Self::Tab,
+            0x1C => Self::Enter,
+            0x1D => Self::LeftCtrl,
+            0x2A => Self::LeftShift,
+            0x36 => Self::RightShift,
+            0x37 => Self::Keypad('*'),
+            0x38 => Self::LeftAlt,
+            0x39 => Self::Space,
+            0x3A => Self::CapsLock,
+            0x3B..=0x44 => Self::Function(code - 0x3B + 1),
+            0x45 => Self::NumLock,
+            0x46 => Self::ScrollLock,
+            0x47..=0x53 => Self::Keypad(b"789-456+1230."[(code - 0x47) as usize] as char),
+            0x57 => Self::Function(11),
+            0x58 => Self::Function(12),
+            _ if (code as usize) < PRINTABLE.len() && PRINTABLE[code as usize] != 0 => {
+                Self::Printable(PRINTABLE[code as usize] as char)
+            }
+            _ => Self::Unknown(code),
+        }
+    }
+
+    fn from_extended_scan_code(code: u8) -> Self {
+        match code {
+            0x1C => Self::Keypad('\n'),
+            0x1D => Self::RightCtrl,
+            0x35 => Self::Keypad('/'),
+            0x38 => Self::RightAlt,
+            0x47 => Self::Home,
+            0x48 => Self::Up,
+            0x49 => Self::PageUp,
+            0x4B => Self::Left,
+            0x4D => Self::Right,
+            0x4F => Self::End,
+            0x50 => Self::Down,
+            0x51 => Self::PageDown,
+            0x52 => Self::Insert,
+            0x53 => Self::Delete,
+            _ => Self::Unknown(code),
+        }
+    }
+}
+
+bitflags::bitflags! {
+    /// State of the modifier keys.
+    #[derive(Clone, Copy, Debug, PartialEq, Eq)]
+    pub struct Modifiers: u8 {
+        const LEFT_SHIFT = 1 << 0;
+        const RIGHT_SHIFT = 1 << 1;
+        const LEFT_CTRL = 1 << 2;
+        const RIGHT_CTRL = 1 << 3;
+        const LEFT_ALT = 1 << 4;
+        const RIGHT_ALT = 1 << 5;
+        const CAPS_LOCK = 1 << 6;
+    }
+}
+
+impl Modifiers {
+    #[inline]
+    pub fn shift(&self) -> bool {
+        self.intersects(Self::LEFT_SHIFT | Self::RIGHT_SHIFT)
+    }
+
+    #[inline]
+    pub fn ctrl(&self) -> bool {
+        self.intersects(Self::LEFT_CTRL | Self::RIGHT_CTRL)
+    }
+
+    #[inline]
+    pub fn alt(&self) -> bool {
+        self.intersects(Self::LEFT_ALT | Self::RIGHT_ALT)
+    }
+
+    #[inline]
+    pub fn caps_lock(&self) -> bool {
+        self.contains(Self::CAPS_LOCK)
+    }
+}
+
+/// A key press or release, along with the state of the modifiers when it happened.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct KeyEvent {
+    pub key: KeyCode,
+    pub pressed: bool,
+    pub modifiers: Modifiers,
+}
+
+impl KeyEvent {
+    /// Returns the character produced by this event, if any. Only key presses produce
+    /// characters.
+    pub fn to_char(self) -> Option<char> {
+        if !self.pressed {
+            return None;
+        }
+
+        match self.key {
+            KeyCode::Printable(ch) if ch.is_ascii_alphabetic() => {
+                if self.modifiers.ctrl() {
+                    Some(((ch as u8) & 0x1F) as char)
+                } else if self.modifiers.shift() != self.modifiers.caps_lock() {
+                    Some(ch.to_ascii_uppercase())
+                } else {
+                    Some(ch)
+                }
+            }
+            KeyCode::Printable(ch) if self.modifiers.shift() => Some(shifted(ch)),
+            KeyCode::Printable(ch) | KeyCode::Keypad(ch) => Some(ch),
+            KeyCode::Space => Some(' '),
+            KeyCode::Enter => Some('\n'),
+            KeyCode::Tab => Some('\t'),
+            KeyCode::Backspace => Some('\x08'),
+            KeyCode::Escape => Some('\x1b'),
+            _ => None,
+        }
+    }
+}
+
+/// Returns the character produced by the key with the unshifted character `ch` while shift is
+/// held, on a US QWERTY layout.
+fn shifted(ch: char) -> char {
+    const UNSHIFTED: &[u8] = b"1234567890-=[]\\;',./`";
+    const SHIFTED: &[u8] = b"!@#$%^&*()_+{}|:\"<>?~";
+
+    match UNSHIFTED.iter().position(|&c| c as char == ch) {
+        Some(i) => SHIFTED[i] as char,
+        None => ch,
+    }
+}
+
+/// Scan code set 1 decoder, keeps track of extended sequences and modifiers.
+struct Decoder {
+    extended: bool,
+    modifiers: Modifiers,
+}
+
+impl Decoder {
+    const fn new() -> Self {
+        Self {
+            extended: false,
+            modifiers: Modifiers::empty(),
+        }
+    }
+
+    fn decode(&mut self, scan_code: u8) -> Option<KeyEvent> {
+        if scan_code == EXTENDED_PREFIX {
+            self.extended = true;
+            return None;
+        }
+
+        let pressed = scan_code & RELEASE_BIT == 0;
+        let code = scan_code & !RELEASE_BIT;
+        let key = if self.extended {
+            KeyCode::from_extended_scan_code(code)
+        } else {
+            KeyCode::from_scan_code(code)
+        };
+        self.extended = false;
+
+        let modifier = match key {
+            KeyCode::LeftShift => Modifiers::LEFT_SHIFT,
+            KeyCode::RightShift => Modifiers::RIGHT_SHIFT,
+            KeyCode::LeftCtrl => Modifiers::LEFT_CTRL,
+            KeyCode::RightCtrl => Modifiers::RIGHT_CTRL,
+            KeyCode::LeftAlt => Modifiers::LEFT_ALT,
+            KeyCode::RightAlt => Modifiers::RIGHT_ALT,
+            _ => Modifiers::empty(),
+        };
+        self.modifiers.set(modifier, pressed);
+
+        if key == KeyCode::CapsLock && pressed {
+            self.modifiers.toggle(Modifiers::CAPS_LOCK);
+        }
+
+        Some(KeyEvent {
+            key,
+            pressed,
+            modifiers: self.modifiers,
+        })
+    }
+}
+
+/// Fixed capacity FIFO ring buffer.
+struct RingBuffer<T, const N: usize> {
+    buffer: [MaybeUninit<T>; N],
+    /// Index of the next element to read.
+    head: usize,
+    /// Index of the next element to write.
+    tail: usize,
+}
+
+impl<T: Copy, const N: usize> RingBuffer<T, N> {
+    const fn new() -> Self {
+        Self {
+            buffer: [MaybeUninit::uninit(); N],
+            head: 0,
+            tail: 0,
+        }
+    }
+
+    /// Push `value` at the end of the buffer. Returns false if the buffer is full.
+    fn push(&mut self, value: T) -> bool {
+        let next = (self.tail + 1) % N;
+        if next == self.head {
+            return false;
+        }
+
+        self.buffer[self.tail].write(value);
+        self.tail = next;
+        true
+    }
+
+    /// Pop the value at the start of the buffer, if any.
+    fn pop(&mut self) -> Option<T> {
+        if self.head == self.tail {
+            return None;
+        }
+
+        let value = unsafe { self.buffer[self.head].assume_init() };
+        self.head = (self.head + 1) % N;
+        Some(value)
+    }
+}
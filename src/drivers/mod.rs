@@ -1 +1,6 @@
 pub mod framebuffer;
+pub mod hpet;
+pub mod keyboard;
+pub mod pci;
+pub mod rtc;
+pub mod serial;
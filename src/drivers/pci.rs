@@ -0,0 +1,163 @@
+//! Enumeration of the devices on the PCI buses.
+//!
+//! The configuration space of each function is accessed through the legacy configuration
+//! mechanism: the address of a register is written to [`CONFIG_ADDRESS`] and the register is
+//! then read from [`CONFIG_DATA`].
+
+use core::fmt;
+
+use spin::Mutex;
+
+use crate::cpu::port::Port;
+use crate::terminal::logger;
+
+/// I/O port selecting the configuration register accessed through [`CONFIG_DATA`].
+const CONFIG_ADDRESS: Port<u32> = Port::new(0xCF8);
+/// I/O port reading the selected configuration register.
+const CONFIG_DATA: Port<u32> = Port::new(0xCFC);
+
+/// (Address) Enables the access to the configuration space.
+const CONFIG_ENABLE: u32 = 1 << 31;
+
+const SLOTS_PER_BUS: u8 = 32;
+const FUNCTIONS_PER_SLOT: u8 = 8;
+
+/// (Header) Offset of the vendor and device ids.
+const OFFSET_ID: u8 = 0x00;
+/// (Header) Offset of the revision, programming interface, subclass and class.
+const OFFSET_CLASS: u8 = 0x08;
+/// (Header) Offset of the cache line size, latency timer, header type and BIST.
+const OFFSET_HEADER_TYPE: u8 = 0x0C;
+/// (Header) Offset of the first Base Address Register.
+const OFFSET_BARS: u8 = 0x10;
+
+/// (Header type) The device implements several functions.
+const MULTIFUNCTION: u8 = 1 << 7;
+/// (Header type) Mask of the layout of the rest of the header.
+const HEADER_LAYOUT_MASK: u8 = 0x7F;
+/// (Header type) Layout of a general device, with six BARs.
+const HEADER_LAYOUT_GENERAL: u8 = 0x00;
+/// (Header type) Layout of a PCI-to-PCI bridge, with two BARs.
+const HEADER_LAYOUT_BRIDGE: u8 = 0x01;
+
+/// Vendor id read from functions that are not present.
+const ABSENT_VENDOR: u16 = 0xFFFF;
+
+static CONFIG_SPACE: Mutex<ConfigSpace> = Mutex::new(ConfigSpace);
+
+/// A function of a device on a PCI bus.
+#[derive(Clone, Copy, Debug)]
+pub struct PciDevice {
+    pub bus: u8,
+    pub slot: u8,
+    pub func: u8,
+    pub vendor_id: u16,
+    pub device_id: u16,
+    pub class: u8,
+    pub subclass: u8,
+    /// Raw Base Address Registers, those absent from the header layout are 0.
+    pub bars: [u32; 6],
+}
+
+impl PciDevice {
+    /// Read the header of the function at `bus:slot.func`, if present.
+    fn read(bus: u8, slot: u8, func: u8) -> Option<Self> {
+        let mut config = CONFIG_SPACE.lock();
+        let id = config.read(bus, slot, func, OFFSET_ID);
+        let vendor_id = id as u16;
+        if vendor_id == ABSENT_VENDOR {
+            return None;
+        }
+
+        let class = config.read(bus, slot, func, OFFSET_CLASS);
+        let bar_count = match header_type(&mut config, bus, slot, func) & HEADER_LAYOUT_MASK {
+            HEADER_LAYOUT_GENERAL => 6,
+            HEADER_LAYOUT_BRIDGE => 2,
+            _ => 0,
+        };
+        let mut bars = [0; 6];
+        for (index, bar) in bars.iter_mut().enumerate().take(bar_count) {
+            *bar = config.read(bus, slot, func, OFFSET_BARS + 4 * index as u8);
+        }
+
+        Some(Self {
+            bus,
+            slot,
+            func,
+            vendor_id,
+            device_id: (id >> 16) as u16,
+            class: (class >> 24) as u8,
+            subclass: (class >> 16) as u8,
+            bars,
+        })
+    }
+}
+
+impl fmt::Display for PciDevice {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{:02x}:{:02x}.{} {:04x}:{:04x} class {:02x}:{:02x}",
+            self.bus,
+            self.slot,
+            self.func,
+            self.vendor_id,
+            self.device_id,
+            self.class,
+            self.subclass
+        )
+    }
+}
+
+/// Log every device found on the PCI buses.
+pub fn init() {
+    let mut count = 0;
+    for device in devices() {
+        logger::info!("PCI {}", device);
+        count += 1;
+    }
+    logger::info!("{} PCI functions found", count);
+}
+
+/// Returns every function present on the PCI buses, found by scanning every slot of every bus.
+pub fn devices() -> impl Iterator<Item = PciDevice> {
+    (0..=u8::MAX).flat_map(|bus| (0..SLOTS_PER_BUS).flat_map(move |slot| functions(bus, slot)))
+}
+
+/// Returns the functions of the device in `slot` of `bus`, none if the slot is empty.
+fn functions(bus: u8, slot: u8) -> impl Iterator<Item = PciDevice> {
+    let first = PciDevice::read(bus, slot, 0);
+    let count = match first {
+        Some(_) if header_type(&mut CONFIG_SPACE.lock(), bus, slot, 0) & MULTIFUNCTION != 0 => {
+            FUNCTIONS_PER_SLOT
+        }
+        Some(_) => 1,
+        None => 0,
+    };
+    first
+        .into_iter()
+        .chain((1..count).filter_map(move |func| PciDevice::read(bus, slot, func)))
+}
+
+fn header_type(config: &mut ConfigSpace, bus: u8, slot: u8, func: u8) -> u8 {
+    (config.read(bus, slot, func, OFFSET_HEADER_TYPE) >> 16) as u8
+}
+
+/// Access to the configuration space, which must not be interleaved as the address port is shared.
+struct ConfigSpace;
+
+impl ConfigSpace {
+    /// Read the 32-bit register at `offset`, rounded down to 4 bytes, in the header of
+    /// `bus:slot.func`.
+    fn read(&mut self, bus: u8, slot: u8, func: u8, offset: u8) -> u32 {
+        let address = CONFIG_ENABLE
+            | (bus as u32) << 16
+            | (slot as u32) << 11
+            | (func as u32) << 8
+            | (offset & 0xFC) as u32;
+        unsafe {
+            CONFIG_ADDRESS.write(address);
+            CONFIG_DATA.read()
+        }
+    }
+}
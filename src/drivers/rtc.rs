@@ -0,0 +1,155 @@
+//! Driver for the real-time clock (RTC) of the CMOS, providing the wall-clock time.
+//!
+//! The RTC keeps counting while the machine is off. Its registers are read through the CMOS
+//! index and data ports, and hold the date either in binary or in BCD depending on register B.
+
+use core::fmt;
+
+use spin::Mutex;
+
+use crate::acpi;
+use crate::cpu::port::{inb, outb};
+
+/// I/O port selecting the CMOS register accessed through [`CMOS_DATA`].
+const CMOS_INDEX: u16 = 0x70;
+/// I/O port reading or writing the selected CMOS register.
+const CMOS_DATA: u16 = 0x71;
+
+const REGISTER_SECONDS: u8 = 0x00;
+const REGISTER_MINUTES: u8 = 0x02;
+const REGISTER_HOURS: u8 = 0x04;
+const REGISTER_DAY: u8 = 0x07;
+const REGISTER_MONTH: u8 = 0x08;
+const REGISTER_YEAR: u8 = 0x09;
+const REGISTER_STATUS_A: u8 = 0x0A;
+const REGISTER_STATUS_B: u8 = 0x0B;
+
+/// (Status A) Set while the RTC updates its registers, which are inconsistent meanwhile.
+const UPDATE_IN_PROGRESS: u8 = 1 << 7;
+/// (Status B) The hours are in 24-hour format, otherwise in 12-hour format.
+const HOUR_FORMAT_24: u8 = 1 << 1;
+/// (Status B) The registers are in binary, otherwise in BCD.
+const BINARY_MODE: u8 = 1 << 2;
+/// (Hours) Set for PM hours in 12-hour format.
+const HOUR_PM: u8 = 1 << 7;
+
+/// Century assumed when the RTC has no century register.
+const DEFAULT_CENTURY: u16 = 20;
+
+static CMOS: Mutex<Cmos> = Mutex::new(Cmos);
+
+/// Date and time of the day, as kept by the RTC. Usually in UTC, but some firmwares keep the
+/// local time instead.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct DateTime {
+    pub year: u16,
+    pub month: u8,
+    pub day: u8,
+    pub hour: u8,
+    pub minute: u8,
+    pub second: u8,
+}
+
+impl fmt::Display for DateTime {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{:04}-{:02}-{:02} {:02}:{:02}:{:02}",
+            self.year, self.month, self.day, self.hour, self.minute, self.second
+        )
+    }
+}
+
+/// Returns the current date and time.
+pub fn now() -> DateTime {
+    let century_register = acpi::fadt().and_then(|fadt| fadt.century_register());
+    let mut cmos = CMOS.lock();
+
+    // The registers may change between two reads, so read them until they are stable.
+    let mut raw = cmos.read_raw(century_register);
+    loop {
+        let again = cmos.read_raw(century_register);
+        if again == raw {
+            break;
+        }
+        raw = again;
+    }
+
+    let status_b = cmos.read(REGISTER_STATUS_B);
+    raw.decode(status_b)
+}
+
+/// Values of the time registers, in the format of the RTC.
+#[derive(Clone, Copy, PartialEq, Eq)]
+struct RawDateTime {
+    second: u8,
+    minute: u8,
+    hour: u8,
+    day: u8,
+    month: u8,
+    year: u8,
+    century: Option<u8>,
+}
+
+impl RawDateTime {
+    /// Convert the registers according to the format given by status register B.
+    fn decode(self, status_b: u8) -> DateTime {
+        let convert = |value: u8| {
+            if status_b & BINARY_MODE != 0 {
+                value
+            } else {
+                (value >> 4) * 10 + (value & 0x0F)
+            }
+        };
+
+        let mut hour = convert(self.hour & !HOUR_PM);
+        if status_b & HOUR_FORMAT_24 == 0 {
+            // 12 AM is midnight and 12 PM is noon.
+            hour %= 12;
+            if self.hour & HOUR_PM != 0 {
+                hour += 12;
+            }
+        }
+
+        let century = self
+            .century
+            .map_or(DEFAULT_CENTURY, |century| convert(century) as u16);
+        DateTime {
+            year: century * 100 + convert(self.year) as u16,
+            month: convert(self.month),
+            day: convert(self.day),
+            hour,
+            minute: convert(self.minute),
+            second: convert(self.second),
+        }
+    }
+}
+
+/// Access to the CMOS registers, which must not be interleaved as they share the index port.
+struct Cmos;
+
+impl Cmos {
+    fn read(&mut self, register: u8) -> u8 {
+        unsafe {
+            outb(CMOS_INDEX, register);
+            inb(CMOS_DATA)
+        }
+    }
+
+    /// Wait for the end of any update in progress, then read the time registers.
+    fn read_raw(&mut self, century_register: Option<u8>) -> RawDateTime {
+        while self.read(REGISTER_STATUS_A) & UPDATE_IN_PROGRESS != 0 {
+            core::hint::spin_loop();
+        }
+
+        RawDateTime {
+            second: self.read(REGISTER_SECONDS),
+            minute: self.read(REGISTER_MINUTES),
+            hour: self.read(REGISTER_HOURS),
+            day: self.read(REGISTER_DAY),
+            month: self.read(REGISTER_MONTH),
+            year: self.read(REGISTER_YEAR),
+            century: century_register.map(|register| self.read(register)),
+        }
+    }
+}
@@ -0,0 +1,130 @@
+//! Driver for the 16550 UART, used on the first serial port (COM1) as an output for logs.
+//!
+//! Under QEMU, the output can be captured on the host with `-serial stdio`.
+
+use core::fmt;
+
+use spin::Mutex;
+
+use crate::cpu::port::{inb, outb};
+
+/// Base I/O port of COM1.
+const COM1: u16 = 0x3F8;
+
+/// (THR) Transmitter holding buffer, or divisor low byte when DLAB is set.
+const DATA: u16 = 0;
+/// (IER) Interrupt enable register, or divisor high byte when DLAB is set.
+const INTERRUPT_ENABLE: u16 = 1;
+/// (FCR) FIFO control register.
+const FIFO_CONTROL: u16 = 2;
+/// (LCR) Line control register.
+const LINE_CONTROL: u16 = 3;
+/// (MCR) Modem control register.
+const MODEM_CONTROL: u16 = 4;
+/// (LSR) Line status register.
+const LINE_STATUS: u16 = 5;
+
+/// (LCR) Divisor latch access bit.
+const LCR_DLAB: u8 = 0x80;
+/// (LCR) 8 data bits, no parity, one stop bit.
+const LCR_8N1: u8 = 0x03;
+/// (FCR) Enable and clear both FIFOs, with a 14 bytes interrupt threshold.
+const FCR_ENABLE_CLEAR_14: u8 = 0xC7;
+/// (MCR) Data terminal ready, request to send and auxiliary output 2.
+const MCR_DTR_RTS_OUT2: u8 = 0x0B;
+/// (LSR) Set when the transmitter holding register is empty.
+const LSR_TRANSMIT_EMPTY: u8 = 1 << 5;
+
+/// Divisor of the 115200 Hz UART clock, for a baud rate of 38400.
+const BAUD_DIVISOR: u16 = 3;
+
+static COM1_PORT: Mutex<SerialPort> = Mutex::new(SerialPort::new(COM1));
+
+/// Initialize COM1 for polled output.
+pub fn init() {
+    COM1_PORT.lock().init();
+}
+
+/// Returns the serial port COM1, for use as a log sink.
+pub fn com1() -> &'static Mutex<SerialPort> {
+    &COM1_PORT
+}
+
+/// Print the formatted `args` to COM1. Used by [`serial_print`] and [`serial_println`].
+pub fn print(args: fmt::Arguments) {
+    use fmt::Write;
+    let _ = COM1_PORT.lock().write_fmt(args);
+}
+
+/// Print the formatted `args` to COM1 without taking the port lock, for use when the lock may be
+/// held by the interrupted or panicking code. Output may interleave with other writers.
+pub fn emergency_print(args: fmt::Arguments) {
+    use fmt::Write;
+    let _ = SerialPort::new(COM1).write_fmt(args);
+}
+
+pub struct SerialPort {
+    base: u16,
+}
+
+impl SerialPort {
+    pub const fn new(base: u16) -> Self {
+        Self { base }
+    }
+
+    fn init(&mut self) {
+        unsafe {
+            outb(self.base + INTERRUPT_ENABLE, 0);
+
+            outb(self.base + LINE_CONTROL, LCR_DLAB);
+            outb(self.base + DATA, (BAUD_DIVISOR & 0xFF) as u8);
+            outb(self.base + INTERRUPT_ENABLE, (BAUD_DIVISOR >> 8) as u8);
+
+            outb(self.base + LINE_CONTROL, LCR_8N1);
+            outb(self.base + FIFO_CONTROL, FCR_ENABLE_CLEAR_14);
+            outb(self.base + MODEM_CONTROL, MCR_DTR_RTS_OUT2);
+        }
+    }
+
+    /// Wait for the transmitter to be ready and send `byte`.
+    pub fn send(&mut self, byte: u8) {
+        unsafe {
+            while inb(self.base + LINE_STATUS) & LSR_TRANSMIT_EMPTY == 0 {
+                core::hint::spin_loop();
+            }
+            outb(self.base + DATA, byte);
+        }
+    }
+}
+
+impl fmt::Write for SerialPort {
+    fn write_str(&mut self, s: &str) -> fmt::Result {
+        for byte in s.bytes() {
+            if byte == b'\n' {
+                self.send(b'\r');
+            }
+            self.send(byte);
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+macro_rules! serial_print {
+    ($($arg:tt)*) => {{
+        $crate::drivers::serial::print(format_args!($($arg)*));
+    }};
+}
+
+macro_rules! serial_println {
+    () => {{
+        $crate::drivers::serial::print(format_args!("\n"));
+    }};
+    ($($arg:tt)*) => {{
+        $crate::drivers::serial::print(format_args!("{}\n", format_args!($($arg)*)));
+    }};
+}
+
+#[cfg(test)]
+pub(crate) use serial_print;
+pub(crate) use serial_println;
@@ -1,9 +1,12 @@
 use crate::drivers::framebuffer::FramebufferInfo;
+use crate::memory::{PhysicalAddress, VirtualAddress};
 use core::sync::atomic::AtomicBool;
 use limine::request::{
-    FramebufferRequest, HhdmRequest, MemoryMapRequest, RequestsEndMarker, RequestsStartMarker,
+    ExecutableAddressRequest, ExecutableFileRequest, FramebufferRequest, HhdmRequest,
+    MemoryMapRequest, ModuleRequest, MpRequest, RequestsEndMarker, RequestsStartMarker,
+    RsdpRequest,
 };
-use limine::{BaseRevision, memory_map};
+use limine::{BaseRevision, file, memory_map, mp};
 
 /// Marks one or more static Limine bootloader request items to be placed in the
 /// `.limine_requests` section of the binary.
@@ -37,6 +40,11 @@ limine_request! {
     static HHDM_REQUEST: HhdmRequest = HhdmRequest::new();
     static FRAMEBUFFER_REQUEST: FramebufferRequest = FramebufferRequest::new();
     static MMAP_REQUEST: MemoryMapRequest = MemoryMapRequest::new();
+    static RSDP_REQUEST: RsdpRequest = RsdpRequest::new();
+    static MODULE_REQUEST: ModuleRequest = ModuleRequest::new();
+    static MP_REQUEST: MpRequest = MpRequest::new();
+    static EXECUTABLE_ADDRESS_REQUEST: ExecutableAddressRequest = ExecutableAddressRequest::new();
+    static EXECUTABLE_FILE_REQUEST: ExecutableFileRequest = ExecutableFileRequest::new();
 }
 
 static mut HHDM_OFFSET: usize = 0;
@@ -56,10 +64,41 @@ pub const fn hhdm_offset() -> usize {
     unsafe { HHDM_OFFSET }
 }
 
+/// Physical address the kernel image was loaded at.
+pub fn kernel_physical_base() -> PhysicalAddress {
+    PhysicalAddress::from_u64(executable_address().physical_base())
+}
+
+/// Virtual address the kernel image is mapped at, the start of its first segment.
+pub fn kernel_virtual_base() -> VirtualAddress {
+    VirtualAddress::from(executable_address().virtual_base() as usize)
+}
+
+/// Contents of the kernel ELF file as loaded from the disk, accessed through the higher half
+/// direct map.
+pub fn kernel_file() -> Option<&'static [u8]> {
+    EXECUTABLE_FILE_REQUEST.get_response().map(|response| {
+        let file = response.file();
+        unsafe { core::slice::from_raw_parts(file.addr(), file.size() as usize) }
+    })
+}
+
+fn executable_address() -> &'static limine::response::ExecutableAddressResponse {
+    EXECUTABLE_ADDRESS_REQUEST
+        .get_response()
+        .expect("The bootloader did not provide the kernel address")
+}
+
 #[derive(Copy, Clone)]
 pub struct MemoryMap(&'static [&'static memory_map::Entry]);
 
 impl MemoryMap {
+    /// Wrap `entries`, which must be sorted by base address like the map of the bootloader.
+    #[cfg(test)]
+    pub fn from_entries(entries: &'static [&'static memory_map::Entry]) -> Self {
+        Self(entries)
+    }
+
     pub fn entries(&self) -> &'static [&'static memory_map::Entry] {
         self.0
     }
@@ -69,6 +108,21 @@ impl MemoryMap {
             .iter()
             .filter(|e| e.entry_type == memory_map::EntryType::USABLE)
     }
+
+    /// Sum of the lengths of every entry, whatever its type.
+    pub fn total_bytes(&self) -> u64 {
+        self.0.iter().map(|e| e.length).sum()
+    }
+
+    /// Sum of the lengths of the usable entries.
+    pub fn total_usable_bytes(&self) -> u64 {
+        self.usable_entries().map(|e| e.length).sum()
+    }
+
+    /// Returns the largest usable entry, if any.
+    pub fn largest_usable_region(&self) -> Option<&'static memory_map::Entry> {
+        self.usable_entries().max_by_key(|e| e.length).copied()
+    }
 }
 
 /// Obtain the initial memory map provided by the bootloader.
@@ -100,3 +154,73 @@ pub fn framebuffer_information() -> impl Iterator<Item = (*mut u8, FramebufferIn
         .framebuffers()
         .flat_map(FramebufferInfo::from)
 }
+
+/// Returns the first framebuffer provided by the bootloader, or `None` if the bootloader did not
+/// answer the request. Unlike [`framebuffer_information`], this never panics, so it can be used
+/// while reporting a panic.
+pub fn try_first_framebuffer() -> Option<(*mut u8, FramebufferInfo)> {
+    FRAMEBUFFER_REQUEST
+        .get_response()?
+        .framebuffers()
+        .flat_map(FramebufferInfo::from)
+        .next()
+}
+
+/// Obtain the physical address of the ACPI RSDP, if the firmware provides one.
+pub fn acquire_rsdp() -> Option<PhysicalAddress> {
+    RSDP_REQUEST
+        .get_response()
+        .map(|response| PhysicalAddress::from(response.address()))
+}
+
+/// A file loaded by the bootloader alongside the kernel.
+#[derive(Copy, Clone)]
+pub struct Module(&'static file::File);
+
+impl Module {
+    /// Path of the module, as given in the bootloader configuration. Paths that are not valid
+    /// UTF-8 are reported as empty.
+    pub fn path(&self) -> &'static str {
+        self.0.path().to_str().unwrap_or_default()
+    }
+
+    /// Contents of the module, accessed through the higher half direct map.
+    pub fn contents(&self) -> &'static [u8] {
+        unsafe { core::slice::from_raw_parts(self.0.addr(), self.len()) }
+    }
+
+    /// Size of the module in bytes.
+    pub fn len(&self) -> usize {
+        self.0.size() as usize
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+}
+
+/// Obtain the modules loaded by the bootloader, which may be none.
+pub fn modules() -> impl Iterator<Item = Module> {
+    MODULE_REQUEST
+        .get_response()
+        .map(|response| response.modules())
+        .unwrap_or_default()
+        .iter()
+        .map(|module| Module(module))
+}
+
+/// Obtain the processors started by the bootloader, the bootstrap processor included. Empty if
+/// the bootloader did not start the other processors.
+pub fn cpus() -> &'static [&'static mp::Cpu] {
+    MP_REQUEST
+        .get_response()
+        .map(|response| response.cpus())
+        .unwrap_or_default()
+}
+
+/// Returns the local APIC id of the bootstrap processor, if the bootloader started the others.
+pub fn bsp_lapic_id() -> Option<u32> {
+    MP_REQUEST
+        .get_response()
+        .map(|response| response.bsp_lapic_id())
+}
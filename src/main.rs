@@ -1,58 +1,154 @@
 #![no_std]
 #![no_main]
 #![feature(abi_x86_interrupt)]
+#![feature(custom_test_frameworks)]
+#![test_runner(crate::testing::test_runner)]
+#![reexport_test_harness_main = "test_main"]
 
+mod acpi;
 mod cpu;
 mod drivers;
 mod limine;
 mod memory;
+mod sync;
 mod terminal;
+mod util;
+#[cfg(test)]
+mod testing;
 
+use core::fmt::{self, Write};
 use core::panic::PanicInfo;
-use core::arch::asm;
 
-use crate::drivers::framebuffer;
+use crate::cpu::backtrace::Backtrace;
+use crate::drivers::{framebuffer, keyboard, serial};
 use crate::terminal::logger::{self, LogLevel, Logger};
 use crate::terminal::tty;
 pub static LOGGER: Logger = Logger::new(LogLevel::Debug);
 
+#[cfg(not(test))]
 #[unsafe(no_mangle)]
 pub extern "C" fn _start() -> ! {
+    drivers::serial::init();
+    serial::serial_println!("Serial output initiated");
+    logger::add_sink(serial::com1(), true);
     limine::init();
+    logger::add_sink(framebuffer::early_console(), false);
+    cpu::segments::load_gdt();
     cpu::interrupts::init();
+    cpu::fpu::init();
     memory::frame_allocator::init();
+    memory::protect_kernel();
+    if let Err(error) = acpi::init() {
+        logger::warning!("{}", error);
+    }
+    logger::info!("Current time: {}", drivers::rtc::now());
+    for module in limine::modules() {
+        logger::info!("Module {} loaded ({} bytes)", module.path(), module.len());
+    }
     drivers::framebuffer::init();
     tty::init();
 
     logger::info!("Console initiated");
+    drivers::pci::init();
+    drivers::hpet::init();
+    cpu::apic::init();
+    cpu::interrupts::ioapic::init();
+    cpu::smp::boot_aps();
+    cpu::time::init();
+    keyboard::init();
+    cpu::syscall::init();
+    cpu::interrupts::enable();
+    cpu::time::switch_to_apic_timer();
+    cpu::sched::init();
+    cpu::sched::spawn_demo_tasks();
     logger::info!("Display info: {:?}", framebuffer::driver().info());
 
+    run();
+}
+
+#[cfg(test)]
+#[unsafe(no_mangle)]
+pub extern "C" fn _start() -> ! {
+    drivers::serial::init();
+    limine::init();
+    cpu::segments::load_gdt();
+    cpu::interrupts::init();
+    memory::frame_allocator::init();
+    drivers::framebuffer::init();
 
-    logger::warning!("Quitting!");
+    test_main();
+    cpu::halt();
+}
 
-    halt();
+/// Dispatch keyboard input to the terminal, sleeping between interrupts.
+fn run() -> ! {
+    loop {
+        while let Some(event) = keyboard::poll() {
+            tty::handle_key_event(event);
+        }
+        cpu::wait_for_interrupt();
+    }
 }
 
+#[cfg(not(test))]
 #[panic_handler]
 fn panic(info: &PanicInfo) -> ! {
-    if let Some(location) = info.location() {
-        logger::critical!(
-            "Panic at {}:{}: {} \n",
-            location.file(),
-            location.line(),
-            info.message()
-        );
+    cpu::interrupts::disable();
+    logger::enter_panic_mode();
+
+    let message = PanicMessage(info);
+    let backtrace = Backtrace::capture();
+    serial::emergency_print(format_args!(
+        "[{:#}]: {}\n{}",
+        LogLevel::Critical,
+        message,
+        backtrace
+    ));
+    if let Some(terminal) = tty::TERMINAL.get() {
+        if let Some(mut terminal) = terminal.try_lock() {
+            let _ = writeln!(
+                terminal,
+                "[{:#}]: {}\n{}",
+                LogLevel::Critical,
+                message,
+                backtrace
+            );
+        }
     } else {
-        logger::critical!("Panic: {} \n", info.message())
+        // Nothing is visible on screen before the terminal exists otherwise.
+        let _ = writeln!(
+            framebuffer::EarlyConsole,
+            "[{}]: {}\n{}",
+            LogLevel::Critical,
+            message,
+            backtrace
+        );
     }
-    halt()
+    cpu::halt()
 }
 
-fn halt() -> ! {
-    loop {
-        // loop over instruction in case CPU retakes control
-        unsafe {
-            asm!("hlt");
+#[cfg(test)]
+#[panic_handler]
+fn panic(info: &PanicInfo) -> ! {
+    cpu::interrupts::disable();
+    serial::emergency_print(format_args!("[failed]\n{}\n", PanicMessage(info)));
+    cpu::qemu::exit(cpu::qemu::QemuExitCode::Failed);
+}
+
+/// Location and message of a panic.
+struct PanicMessage<'a>(&'a PanicInfo<'a>);
+
+impl fmt::Display for PanicMessage<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.0.location() {
+            Some(location) => write!(
+                f,
+                "Panic at {}:{}: {}",
+                location.file(),
+                location.line(),
+                self.0.message()
+            ),
+            None => write!(f, "Panic: {}", self.0.message()),
         }
     }
 }
@@ -1,11 +1,21 @@
 use crate::limine;
+use crate::memory::{align_down, align_up, is_aligned};
 use core::fmt;
 use core::ops::{Add, AddAssign, Sub, SubAssign};
 
+/// Address in physical memory.
+///
+/// The arithmetic operators assume the result does not overflow, use [`Self::checked_add`] and
+/// friends otherwise.
 #[derive(PartialEq, Eq, Clone, Copy, PartialOrd, Ord)]
 #[repr(transparent)]
 pub struct PhysicalAddress(usize);
 
+/// Canonical address in the virtual address space.
+///
+/// The arithmetic operators assume the result does not overflow and is canonical, and panic
+/// otherwise. Use [`Self::checked_add`] and friends for arithmetic that may cross the
+/// non-canonical hole.
 #[derive(PartialEq, Eq, Clone, Copy, PartialOrd, Ord)]
 #[repr(transparent)]
 pub struct VirtualAddress(usize);
@@ -34,6 +44,54 @@ impl PhysicalAddress {
     pub const fn to_virtual(self) -> VirtualAddress {
         VirtualAddress(limine::hhdm_offset() + self.0)
     }
+
+    /// Returns the address `offset` bytes after this one, or `None` on overflow.
+    #[inline]
+    pub const fn checked_add(self, offset: usize) -> Option<Self> {
+        match self.0.checked_add(offset) {
+            Some(address) => Some(Self(address)),
+            None => None,
+        }
+    }
+
+    /// Returns the address `offset` bytes before this one, or `None` on underflow.
+    #[inline]
+    pub const fn checked_sub(self, offset: usize) -> Option<Self> {
+        match self.0.checked_sub(offset) {
+            Some(address) => Some(Self(address)),
+            None => None,
+        }
+    }
+
+    /// Returns the address `offset` bytes after this one, wrapping around the address space.
+    #[inline]
+    pub const fn wrapping_add(self, offset: usize) -> Self {
+        Self(self.0.wrapping_add(offset))
+    }
+
+    /// Returns the address `offset` bytes before this one, wrapping around the address space.
+    #[inline]
+    pub const fn wrapping_sub(self, offset: usize) -> Self {
+        Self(self.0.wrapping_sub(offset))
+    }
+
+    /// Returns whether the address is a multiple of `alignment`, a power of two.
+    #[inline]
+    pub fn is_aligned(self, alignment: usize) -> bool {
+        is_aligned(self.0, alignment)
+    }
+
+    /// Returns the address rounded up to the next multiple of `alignment`, a power of two.
+    #[inline]
+    pub fn align_up(self, alignment: usize) -> Self {
+        Self(align_up(self.0, alignment))
+    }
+
+    /// Returns the address rounded down to the previous multiple of `alignment`, a power of two.
+    #[inline]
+    pub fn align_down(self, alignment: usize) -> Self {
+        Self(align_down(self.0, alignment))
+    }
 }
 
 impl From<PhysicalAddress> for usize {
@@ -190,6 +248,70 @@ impl VirtualAddress {
     pub const unsafe fn to_physical(self) -> PhysicalAddress {
         PhysicalAddress(self.0 - limine::hhdm_offset())
     }
+
+    /// Returns the address `offset` bytes after this one, or `None` if the result overflows or
+    /// is not canonical.
+    #[inline]
+    pub const fn checked_add(self, offset: usize) -> Option<Self> {
+        match self.0.checked_add(offset) {
+            Some(address) => Self::checked_from(address),
+            None => None,
+        }
+    }
+
+    /// Returns the address `offset` bytes before this one, or `None` if the result underflows or
+    /// is not canonical.
+    #[inline]
+    pub const fn checked_sub(self, offset: usize) -> Option<Self> {
+        match self.0.checked_sub(offset) {
+            Some(address) => Self::checked_from(address),
+            None => None,
+        }
+    }
+
+    /// Returns the address `offset` bytes after this one, wrapping around the 48-bit address
+    /// space. The result is sign-extended to its canonical form.
+    #[inline]
+    pub const fn wrapping_add(self, offset: usize) -> Self {
+        Self(Self::sign_extend_value(self.0.wrapping_add(offset)))
+    }
+
+    /// Returns the address `offset` bytes before this one, wrapping around the 48-bit address
+    /// space. The result is sign-extended to its canonical form.
+    #[inline]
+    pub const fn wrapping_sub(self, offset: usize) -> Self {
+        Self(Self::sign_extend_value(self.0.wrapping_sub(offset)))
+    }
+
+    #[inline]
+    const fn checked_from(address: usize) -> Option<Self> {
+        let address = Self(address);
+        if address.is_canonical() {
+            Some(address)
+        } else {
+            None
+        }
+    }
+
+    /// Returns whether the address is a multiple of `alignment`, a power of two.
+    #[inline]
+    pub fn is_aligned(self, alignment: usize) -> bool {
+        is_aligned(self.0, alignment)
+    }
+
+    /// Returns the address rounded up to the next multiple of `alignment`, a power of two. The
+    /// rounding is done on the raw value, only the result must be canonical.
+    #[inline]
+    pub fn align_up(self, alignment: usize) -> Self {
+        Self::from(align_up(self.0, alignment))
+    }
+
+    /// Returns the address rounded down to the previous multiple of `alignment`, a power of two.
+    /// The rounding is done on the raw value, only the result must be canonical.
+    #[inline]
+    pub fn align_down(self, alignment: usize) -> Self {
+        Self::from(align_down(self.0, alignment))
+    }
 }
 
 impl From<VirtualAddress> for usize {
@@ -10,9 +10,11 @@ use crate::limine;
 use crate::memory::*;
 use crate::terminal::logger;
 
-static ALLOCATOR_PTR: Once<AllocatorPtr> = Once::new();
+/// Byte written over freed blocks in debug builds, so that a use after free reads data that
+/// stands out, such as `0xDEDEDEDEDEDEDEDE` pointers which are not canonical.
+const POISON_BYTE: u8 = 0xDE;
 
-const PAGE_SIZE: usize = 4096;
+static ALLOCATOR_PTR: Once<AllocatorPtr> = Once::new();
 
 struct AllocatorPtr(UnsafeCell<BuddyAllocator>);
 unsafe impl Send for AllocatorPtr {}
@@ -20,8 +22,14 @@ unsafe impl Sync for AllocatorPtr {}
 
 pub fn init() {
     ALLOCATOR_PTR.call_once(|| {
+        let memory_map = limine::acquire_memory_map().unwrap();
+        logger::info!(
+            "Detected {} of RAM, {} usable",
+            HumanBytes(memory_map.total_bytes() as usize),
+            HumanBytes(memory_map.total_usable_bytes() as usize)
+        );
         AllocatorPtr(UnsafeCell::new(
-            BuddyAllocator::new_embedded(limine::acquire_memory_map().unwrap()).unwrap(),
+            BuddyAllocator::new_embedded(memory_map).unwrap(),
         ))
     });
 }
@@ -31,11 +39,41 @@ pub fn allocate_exact(size: usize) -> PhysicalAddress {
     with_allocator(|a| a.allocate_exact(size))
 }
 
+#[inline(always)]
+pub fn try_allocate_exact(size: usize) -> Result<PhysicalAddress, AllocationError> {
+    with_allocator(|a| a.try_allocate_exact(size))
+}
+
 #[inline(always)]
 pub fn allocate(size: usize) -> PhysicalAddress {
     with_allocator(|a| a.allocate(size))
 }
 
+#[inline(always)]
+pub fn try_allocate(size: usize) -> Result<PhysicalAddress, AllocationError> {
+    with_allocator(|a| a.try_allocate(size))
+}
+
+/// Keep the pages overlapping `[start..end)` away from the allocations, such as device memory
+/// found at runtime. See [`BuddyAllocator::reserve`].
+#[inline(always)]
+pub fn reserve(start: PhysicalAddress, end: PhysicalAddress) -> Result<(), ReservationError> {
+    with_allocator(|a| a.reserve(start, end))
+}
+
+/// Returns the current and peak amount of allocated memory.
+#[inline(always)]
+pub fn stats() -> AllocatorStats {
+    with_allocator(|a| a.stats())
+}
+
+/// Restart tracking the peak of allocated memory from the current amount, to measure the peak of
+/// a specific phase.
+#[inline(always)]
+pub fn reset_peak() {
+    with_allocator(|a| a.reset_peak())
+}
+
 #[inline(always)]
 pub fn reallocate(address: PhysicalAddress, size: usize) -> PhysicalAddress {
     with_allocator(|a| a.reallocate(address, size))
@@ -49,7 +87,7 @@ pub fn free(address: PhysicalAddress) {
 #[inline(always)]
 pub fn with_allocator<F, R>(func: F) -> R
 where
-    F: Fn(&mut BuddyAllocator) -> R,
+    F: FnOnce(&mut BuddyAllocator) -> R,
 {
     let buddy = ALLOCATOR_PTR
         .get()
@@ -107,11 +145,93 @@ impl fmt::Display for InitializationError {
 
 impl error::Error for InitializationError {}
 
+#[derive(Debug)]
+pub enum AllocationError {
+    /// Allocations of zero bytes are not supported.
+    InvalidSize,
+    /// The size is larger than the largest block of the allocator.
+    SizeTooLarge(usize),
+    /// The size is not a power-of-two multiple of [`PAGE_SIZE`], as required by exact
+    /// allocations.
+    SizeNotExact(usize),
+}
+
+impl fmt::Display for AllocationError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::InvalidSize => write!(f, "Could not allocate 0 bytes"),
+            Self::SizeTooLarge(size) => write!(
+                f,
+                "Could not allocate {} because it is larger than the largest block",
+                HumanBytes(*size)
+            ),
+            Self::SizeNotExact(size) => write!(
+                f,
+                "Could not allocate exactly {size} bytes because it is not a power-of-two multiple of the page size"
+            ),
+        }
+    }
+}
+
+impl error::Error for AllocationError {}
+
+#[derive(Debug)]
+pub enum ReservationError {
+    /// The end of the range is not after its start.
+    BadRange(PhysicalAddress, PhysicalAddress),
+    /// The range does not overlap the memory managed by the allocator.
+    OutsideArena(PhysicalAddress, PhysicalAddress),
+    /// The page at this address is allocated.
+    InUse(PhysicalAddress),
+}
+
+impl fmt::Display for ReservationError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::BadRange(start, end) => write!(
+                f,
+                "Could not reserve memory for range [{start:?}..{end:?}] because it is an invalid range"
+            ),
+            Self::OutsideArena(start, end) => write!(
+                f,
+                "Could not reserve memory for range [{start:?}..{end:?}] because it is outside of the managed memory"
+            ),
+            Self::InUse(address) => write!(
+                f,
+                "Could not reserve the page at {address:?} because it is allocated"
+            ),
+        }
+    }
+}
+
+impl error::Error for ReservationError {}
+
+/// Memory usage of the allocator, in bytes. Reserved memory does not count as allocated.
+#[derive(Clone, Copy, Debug)]
+pub struct AllocatorStats {
+    pub allocated: usize,
+    /// Highest value of `allocated` since the allocator was created or the peak was reset.
+    pub allocated_peak: usize,
+}
+
+impl fmt::Display for AllocatorStats {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{} allocated, {} at peak",
+            HumanBytes(self.allocated),
+            HumanBytes(self.allocated_peak)
+        )
+    }
+}
+
 #[derive(Debug)]
 pub struct BuddyAllocator {
     region_start: PhysicalAddress,
     region_end: PhysicalAddress,
     max_order: u8,
+    allocated: usize,
+    allocated_peak: usize,
     markers: *mut [usize],
     state_tree: *mut [BlockState],
 }
@@ -134,9 +254,11 @@ impl BuddyAllocator {
         let markers = unsafe { Self::init_markers(markers_start, max_order as usize + 1) };
 
         let mut allocator = Self {
-            region_start: align_up(tree_start + tree_size, PAGE_SIZE),
+            region_start: (tree_start + tree_size).align_up(PAGE_SIZE),
             region_end: usable_end,
             max_order,
+            allocated: 0,
+            allocated_peak: 0,
             markers,
             state_tree,
         };
@@ -210,6 +332,8 @@ impl BuddyAllocator {
         address.clamp(self.region_start, self.region_end)
     }
 
+    /// Reserve the pages overlapping `[start..end)`. The range is clamped to the managed memory,
+    /// so nothing is reserved for the parts of it outside of `[region_start..region_end)`.
     #[inline]
     pub fn reserve_range(
         &mut self,
@@ -220,8 +344,8 @@ impl BuddyAllocator {
             return Err(InitializationError::BadRange(start, end));
         }
 
-        let first_block = self.page_block_from(self.clamp_addr(align_down(start, PAGE_SIZE)));
-        let last_block = self.page_block_from(self.clamp_addr(align_up(end, PAGE_SIZE)));
+        let first_block = self.page_block_from(self.clamp_addr(start.align_down(PAGE_SIZE)));
+        let last_block = self.page_block_from(self.clamp_addr(end.align_up(PAGE_SIZE)));
         let offset = Self::offset_for_order(self.max_order);
 
         for block in first_block + offset..last_block + offset {
@@ -231,9 +355,43 @@ impl BuddyAllocator {
         Ok(())
     }
 
+    /// Reserve the pages overlapping `[start..end)`, like [`Self::reserve_range`], but fail
+    /// instead of reserving nothing if the range is outside of the managed memory, and if one of
+    /// the pages is allocated. Reserving pages that are already reserved does nothing.
+    pub fn reserve(
+        &mut self,
+        start: PhysicalAddress,
+        end: PhysicalAddress,
+    ) -> Result<(), ReservationError> {
+        if end <= start {
+            return Err(ReservationError::BadRange(start, end));
+        }
+        if end <= self.region_start || start >= self.region_end {
+            return Err(ReservationError::OutsideArena(start, end));
+        }
+
+        let first_block = self.page_block_from(self.clamp_addr(start.align_down(PAGE_SIZE)));
+        let last_block = self.page_block_from(self.clamp_addr(end.align_up(PAGE_SIZE)));
+        let offset = Self::offset_for_order(self.max_order);
+        if let Some(block) = (first_block..last_block)
+            .find(|&block| self.state(block + offset) == BlockState::Allocated)
+        {
+            return Err(ReservationError::InUse(
+                self.address_for_block(block + offset, self.max_order),
+            ));
+        }
+
+        self.reserve_range(start, end)
+            .map_err(|_| ReservationError::BadRange(start, end))
+    }
+
+    /// Reserve every page from the one containing `address` to the end of the tree, i.e. every
+    /// page that is not entirely below `address`. Used with the end of the last usable entry of
+    /// the memory map to reserve what lies strictly after it.
     #[inline]
     pub fn reserve_all_after(&mut self, address: PhysicalAddress) {
-        let block = self.page_block_from(self.clamp_addr(align_down(address + 1, PAGE_SIZE)));
+        // Clamping first keeps the alignment from going past the end of the arena.
+        let block = self.page_block_from(self.clamp_addr(address).align_down(PAGE_SIZE));
         let offset = Self::offset_for_order(self.max_order);
 
         for block in block + offset..self.state_tree.len() {
@@ -244,7 +402,7 @@ impl BuddyAllocator {
 
     #[inline(always)]
     fn page_block_from(&self, address: PhysicalAddress) -> usize {
-        assert!(is_aligned(address, PAGE_SIZE));
+        assert!(address.is_aligned(PAGE_SIZE));
         (address - self.region_start).value() / PAGE_SIZE
     }
 
@@ -342,55 +500,104 @@ impl BuddyAllocator {
         self.state_tree_mut()[block] = state;
     }
 
+    /// Allocate a block of exactly `size` bytes, which must be a power-of-two multiple of
+    /// [`PAGE_SIZE`]. Panics otherwise, see [`Self::try_allocate_exact`].
     #[inline(always)]
     pub fn allocate_exact(&mut self, size: usize) -> PhysicalAddress {
-        self.allocate_order(self.order_for_size(size).unwrap())
+        self.try_allocate_exact(size)
+            .unwrap_or_else(|error| panic!("{error}"))
     }
 
+    /// Allocate a block of exactly `size` bytes, or fail if `size` is not a power-of-two multiple
+    /// of [`PAGE_SIZE`].
+    #[inline(always)]
+    pub fn try_allocate_exact(&mut self, size: usize) -> Result<PhysicalAddress, AllocationError> {
+        if size > self.size_for_order(0) {
+            return Err(AllocationError::SizeTooLarge(size));
+        }
+        let order = self
+            .order_for_size(size)
+            .ok_or(AllocationError::SizeNotExact(size))?;
+        Ok(self.allocate_order(order))
+    }
+
+    /// Allocate a block of at least `size` bytes. Panics if `size` is 0 or larger than the
+    /// largest block, see [`Self::try_allocate`].
     #[inline(always)]
     pub fn allocate(&mut self, size: usize) -> PhysicalAddress {
-        assert!(size != 0);
-        if is_aligned(size, PAGE_SIZE) && is_power_of_two(size) {
-            self.allocate_exact(size)
-        } else {
-            let mut reverse_order = 0;
-            while PAGE_SIZE << reverse_order < size {
-                reverse_order += 1;
-
-                if reverse_order > self.max_order {
-                    panic!(
-                        "Unsupported allocation for size {}, max supported size is {}",
-                        size,
-                        PAGE_SIZE << self.max_order
-                    );
-                }
-            }
+        self.try_allocate(size)
+            .unwrap_or_else(|error| panic!("{error}"))
+    }
 
-            self.allocate_order(self.max_order - reverse_order)
+    /// Allocate the smallest block holding `size` bytes, or fail if `size` is 0 or larger than
+    /// the largest block.
+    #[inline(always)]
+    pub fn try_allocate(&mut self, size: usize) -> Result<PhysicalAddress, AllocationError> {
+        if size == 0 {
+            return Err(AllocationError::InvalidSize);
+        }
+        if size > self.size_for_order(0) {
+            return Err(AllocationError::SizeTooLarge(size));
+        }
+
+        let pages = size.div_ceil(PAGE_SIZE).next_power_of_two();
+        Ok(self.allocate_order(self.max_order - pages.trailing_zeros() as u8))
+    }
+
+    pub fn stats(&self) -> AllocatorStats {
+        AllocatorStats {
+            allocated: self.allocated,
+            allocated_peak: self.allocated_peak,
         }
     }
 
+    pub fn reset_peak(&mut self) {
+        self.allocated_peak = self.allocated;
+    }
+
+    /// Add `size` bytes to the allocated memory, raising the peak if needed.
+    #[inline(always)]
+    fn track_allocation(&mut self, size: usize) {
+        self.allocated += size;
+        self.allocated_peak = self.allocated_peak.max(self.allocated);
+    }
+
     #[inline]
     fn allocate_block(&mut self, block: usize, order: u8) -> PhysicalAddress {
+        self.track_allocation(self.size_for_order(order));
         self.mark_subtree(block, BlockState::Allocated);
         self.update_ancestors(block);
         self.address_for_block(block, order)
     }
 
+    /// Allocate the first free block of `order`. If there is none, the first free block of the
+    /// closest larger order is split down to `order` and its first half is allocated, the other
+    /// halves staying free.
     #[inline]
     pub fn allocate_order(&mut self, order: u8) -> PhysicalAddress {
-        let first = self.marker_for(order);
-        let last = 2 << order;
+        if let Some(block) = self.find_free_block(order) {
+            self.markers_mut()[order as usize] = block + 1;
+            return self.allocate_block(block, order);
+        }
 
-        for block in first..last {
-            if self.state(block).is_free() {
-                self.markers_mut()[order as usize] = block + 1;
-                return self.allocate_block(block, order);
+        for larger in (0..order).rev() {
+            if let Some(block) = self.find_free_block(larger) {
+                for split in larger + 1..=order {
+                    // Right half of the block split at this order.
+                    self.set_marker_min(split, (block << (split - larger)) + 1);
+                }
+                return self.allocate_block(block << (order - larger), order);
             }
         }
         panic!("[FR0]: No free block for order size {order} in frame_allocator");
     }
 
+    /// Returns the first free block of `order` from its marker on.
+    #[inline]
+    fn find_free_block(&self, order: u8) -> Option<usize> {
+        (self.marker_for(order)..2 << order).find(|&block| self.state(block).is_free())
+    }
+
     #[inline]
     /// If an allocated block exists for the `address` provided, returns its number and order as
     /// a tuple of layout (number, order). Otherwise, panics.
@@ -450,8 +657,12 @@ impl BuddyAllocator {
                 new_order -= 1;
                 new_size >>= 1;
 
+                if cfg!(debug_assertions) {
+                    self.poison(right_child, new_order);
+                }
                 self.set_marker_min(new_order, right_child);
                 self.mark_subtree(right_child, BlockState::Free);
+                self.allocated -= new_size;
             }
 
             if new_block != block {
@@ -493,6 +704,7 @@ impl BuddyAllocator {
 
             self.update_ancestors(new_block);
             self.mark_subtree(new_block, BlockState::Allocated);
+            self.track_allocation(new_size - current_size);
             return new_address;
         }
 
@@ -520,9 +732,32 @@ impl BuddyAllocator {
     #[inline(always)]
     /// SAFETY: Caller must ensure that `block` is an allocated block of order `order`
     unsafe fn free_raw(&mut self, block: usize, order: u8) {
+        if cfg!(debug_assertions) {
+            self.poison(block, order);
+        }
+        self.allocated -= self.size_for_order(order);
         self.set_marker_min(order, block);
         self.mark_subtree(block, BlockState::Free);
         self.update_ancestors(block);
+
+        // Merging with free buddies frees blocks of larger orders.
+        let (mut block, mut order) = (block, order);
+        while let Some(parent) = Self::parent(block).filter(|&parent| self.state(parent).is_free())
+        {
+            block = parent;
+            order -= 1;
+            self.set_marker_min(order, block);
+        }
+    }
+
+    /// Fill `block` with [`POISON_BYTE`] through the higher half direct map.
+    fn poison(&self, block: usize, order: u8) {
+        let address = self.address_for_block(block, order).to_virtual();
+        unsafe {
+            address
+                .to_ptr::<u8>()
+                .write_bytes(POISON_BYTE, self.size_for_order(order))
+        };
     }
 
     #[inline(always)]
@@ -596,8 +831,8 @@ impl BuddyAllocator {
         }
 
         logger::debug!(
-            "Initial free {}KiB block count: {}",
-            PAGE_SIZE / 1024,
+            "Initial free {} block count: {}",
+            HumanBytes(PAGE_SIZE),
             count
         );
 
@@ -654,3 +889,120 @@ impl BuddyAllocator {
         logger::debug!("Freed all possible blocks")
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use ::limine::memory_map::{Entry, EntryType};
+
+    use super::*;
+
+    /// Size of the memory managed by the allocators under test.
+    const ARENA_SIZE: usize = 2 * 1024 * 1024;
+
+    /// Run `f` with an allocator managing a block taken from the global allocator, described by a
+    /// memory map whose usable entry is followed by a reserved one.
+    fn with_test_allocator(f: impl FnOnce(&mut BuddyAllocator)) {
+        let arena = allocate_exact(ARENA_SIZE);
+        let entries_page = allocate(PAGE_SIZE);
+        let base = arena.value() as u64;
+
+        let memory_map = unsafe {
+            let entries = entries_page.to_virtual().to_ptr::<Entry>();
+            entries.write(Entry {
+                base,
+                length: ARENA_SIZE as u64,
+                entry_type: EntryType::USABLE,
+            });
+            entries.add(1).write(Entry {
+                base: base + ARENA_SIZE as u64,
+                length: PAGE_SIZE as u64,
+                entry_type: EntryType::RESERVED,
+            });
+
+            let references = entries.add(2) as *mut &'static Entry;
+            references.write(&*entries);
+            references.add(1).write(&*entries.add(1));
+            limine::MemoryMap::from_entries(slice::from_raw_parts(references, 2))
+        };
+
+        f(&mut BuddyAllocator::new_embedded(memory_map).unwrap());
+        free(entries_page);
+        free(arena);
+    }
+
+    #[test_case]
+    fn large_allocation_succeeds_after_fragmentation() {
+        with_test_allocator(|allocator| {
+            let mut pages = [PhysicalAddress::null(); 64];
+            for page in &mut pages {
+                *page = allocator.allocate(PAGE_SIZE);
+            }
+            for page in pages.iter().step_by(2) {
+                allocator.free(*page);
+            }
+
+            // Two-page blocks don't fit in the holes left between the remaining pages.
+            let mut blocks = [PhysicalAddress::null(); 8];
+            for block in &mut blocks {
+                *block = allocator.allocate(2 * PAGE_SIZE);
+            }
+            for page in pages.iter().skip(1).step_by(2) {
+                allocator.free(*page);
+            }
+            for block in blocks {
+                allocator.free(block);
+            }
+
+            assert_eq!(allocator.stats().allocated, 0);
+            assert!(allocator.try_allocate(ARENA_SIZE / 2).is_ok());
+        });
+    }
+
+    #[test_case]
+    fn last_usable_page_is_not_reserved() {
+        with_test_allocator(|allocator| {
+            // The usable entry ends exactly at the end of the arena.
+            let offset = BuddyAllocator::offset_for_order(allocator.max_order);
+            let last_page = allocator.page_block_from(allocator.region_end - PAGE_SIZE) + offset;
+
+            assert_ne!(allocator.state(last_page), BlockState::Reserved);
+            assert_eq!(allocator.state(last_page + 1), BlockState::Reserved);
+        });
+    }
+
+    #[test_case]
+    fn shrinking_poisons_the_released_memory() {
+        with_test_allocator(|allocator| {
+            let block = allocator.allocate(4 * PAGE_SIZE);
+            let bytes = block.to_virtual().to_ptr::<u8>();
+            unsafe { bytes.write_bytes(0, 4 * PAGE_SIZE) };
+
+            assert_eq!(allocator.reallocate(block, PAGE_SIZE), block);
+            assert!(allocator.stats().allocated < 4 * PAGE_SIZE);
+            if cfg!(debug_assertions) {
+                // The right half is released whatever the final size of the block.
+                let released =
+                    unsafe { slice::from_raw_parts(bytes.add(2 * PAGE_SIZE), 2 * PAGE_SIZE) };
+                assert!(released.iter().all(|&byte| byte == POISON_BYTE));
+            }
+            allocator.free(block);
+        });
+    }
+
+    #[test_case]
+    fn exact_allocation_rejects_non_power_of_two_sizes() {
+        assert!(matches!(
+            try_allocate_exact(3 * PAGE_SIZE),
+            Err(AllocationError::SizeNotExact(_))
+        ));
+    }
+
+    #[test_case]
+    fn allocation_rejects_invalid_sizes() {
+        assert!(matches!(try_allocate(0), Err(AllocationError::InvalidSize)));
+        assert!(matches!(
+            try_allocate(1 << 60),
+            Err(AllocationError::SizeTooLarge(_))
+        ));
+    }
+}
@@ -0,0 +1,112 @@
+//! Typed access to memory-mapped device registers.
+//!
+//! A register block is described by a `#[repr(C)]` struct of [`ReadWrite`], [`ReadOnly`] and
+//! [`WriteOnly`] fields laid out like the device registers, and mapped with [`Mmio::map`]. Every
+//! access is volatile, so the compiler can neither elide nor merge them.
+
+use core::cell::UnsafeCell;
+use core::fmt;
+use core::marker::PhantomData;
+use core::ops::Deref;
+
+use crate::memory::{PhysicalAddress, paging};
+
+/// A register that can be read and written.
+#[repr(transparent)]
+pub struct Volatile<T: Copy>(UnsafeCell<T>);
+
+/// A register that can be read and written, see [`Volatile`].
+pub type ReadWrite<T> = Volatile<T>;
+
+impl<T: Copy> Volatile<T> {
+    #[inline(always)]
+    pub fn read(&self) -> T {
+        unsafe { self.0.get().read_volatile() }
+    }
+
+    #[inline(always)]
+    pub fn write(&self, value: T) {
+        unsafe { self.0.get().write_volatile(value) }
+    }
+
+    /// Read the register, apply `update` to its value and write the result back.
+    #[inline(always)]
+    pub fn update(&self, update: impl FnOnce(T) -> T) {
+        self.write(update(self.read()));
+    }
+}
+
+/// A register that can only be read.
+#[repr(transparent)]
+pub struct ReadOnly<T: Copy>(Volatile<T>);
+
+impl<T: Copy> ReadOnly<T> {
+    #[inline(always)]
+    pub fn read(&self) -> T {
+        self.0.read()
+    }
+}
+
+/// A register that can only be written, reading it may return garbage or have side effects.
+#[repr(transparent)]
+pub struct WriteOnly<T: Copy>(Volatile<T>);
+
+impl<T: Copy> WriteOnly<T> {
+    #[inline(always)]
+    pub fn write(&self, value: T) {
+        self.0.write(value)
+    }
+}
+
+impl<T: Copy + fmt::Debug> fmt::Debug for Volatile<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_tuple("Volatile").field(&self.read()).finish()
+    }
+}
+
+impl<T: Copy + fmt::Debug> fmt::Debug for ReadOnly<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_tuple("ReadOnly").field(&self.read()).finish()
+    }
+}
+
+impl<T: Copy> fmt::Debug for WriteOnly<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("WriteOnly(_)")
+    }
+}
+
+/// Registers of type `T` mapped uncached from a physical address, accessed through [`Deref`].
+pub struct Mmio<T> {
+    registers: *const T,
+    _registers: PhantomData<T>,
+}
+
+impl<T> Mmio<T> {
+    /// Map the registers at `physical` with caching disabled, see [`paging::map_mmio`].
+    ///
+    /// SAFETY: `physical` must be the address of device registers laid out like `T`, and aligned
+    /// for `T`.
+    pub unsafe fn map(physical: PhysicalAddress) -> Self {
+        Self {
+            registers: paging::map_mmio(physical, size_of::<T>()).to_ptr(),
+            _registers: PhantomData,
+        }
+    }
+}
+
+impl<T> Deref for Mmio<T> {
+    type Target = T;
+
+    fn deref(&self) -> &T {
+        unsafe { &*self.registers }
+    }
+}
+
+// Registers are only accessed through single volatile reads and writes, which can't tear for the
+// register widths used by devices. Keeping read-modify-write sequences atomic is up to drivers.
+unsafe impl<T: Copy + Send> Sync for Volatile<T> {}
+
+// The registers are device memory, accessed through volatile operations only.
+unsafe impl<T: Sync> Send for Mmio<T> {}
+unsafe impl<T: Sync> Sync for Mmio<T> {}
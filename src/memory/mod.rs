@@ -1,9 +1,61 @@
 mod addresses;
 pub mod frame_allocator;
+pub mod mmio;
 pub mod paging;
 
+use core::fmt;
+use core::ops::Range;
+
 pub use addresses::*;
 
+use crate::cpu::registers::{Cr0, Cr0Flags, Msr};
+use crate::limine;
+use crate::memory::paging::PageTableEntryFlags;
+use crate::terminal::logger;
+
+/// Size of a regular page and of a physical frame.
+pub const PAGE_SIZE: usize = 4096;
+
+/// (EFER) No-execute enable, makes the NX bit of page table entries effective.
+const EFER_NO_EXECUTE_ENABLE: u64 = 1 << 11;
+
+unsafe extern "C" {
+    // Bounds of the kernel sections, defined by the linker script.
+    static __text_start: u8;
+    static __text_end: u8;
+    static __rodata_start: u8;
+    static __rodata_end: u8;
+}
+
+/// Byte count displayed with the largest binary unit keeping its integer part under 1024, such
+/// as `1.50 MiB`.
+#[derive(Clone, Copy, Debug)]
+pub struct HumanBytes(pub usize);
+
+impl fmt::Display for HumanBytes {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        const UNITS: [&str; 6] = ["KiB", "MiB", "GiB", "TiB", "PiB", "EiB"];
+
+        let bytes = self.0 as u128;
+        if bytes < 1024 {
+            return write!(f, "{bytes} B");
+        }
+
+        let mut unit = 0;
+        while bytes >> (10 * (unit + 2)) != 0 && unit + 1 < UNITS.len() {
+            unit += 1;
+        }
+        let hundredths = (bytes * 100) >> (10 * (unit + 1));
+        write!(
+            f,
+            "{}.{:02} {}",
+            hundredths / 100,
+            hundredths % 100,
+            UNITS[unit]
+        )
+    }
+}
+
 #[inline]
 pub fn is_power_of_two(value: impl Into<usize>) -> bool {
     let value = value.into();
@@ -12,6 +64,7 @@ pub fn is_power_of_two(value: impl Into<usize>) -> bool {
 
 #[inline]
 pub fn is_aligned(value: impl Into<usize>, alignment: usize) -> bool {
+    debug_assert!(is_power_of_two(alignment), "Invalid alignment: {alignment}");
     (value.into() & (alignment - 1)) == 0
 }
 
@@ -20,6 +73,7 @@ pub fn align_up<T>(value: T, alignment: usize) -> T
 where
     T: Into<usize> + From<usize>,
 {
+    debug_assert!(is_power_of_two(alignment), "Invalid alignment: {alignment}");
     ((value.into() + alignment - 1) & !(alignment - 1)).into()
 }
 
@@ -28,5 +82,81 @@ pub fn align_down<T>(value: T, alignment: usize) -> T
 where
     T: Into<usize> + From<usize>,
 {
+    debug_assert!(is_power_of_two(alignment), "Invalid alignment: {alignment}");
     (value.into() & !(alignment - 1)).into()
 }
+
+/// Remap the kernel code as read-only and its read-only data as read-only and no-execute, and
+/// make ring 0 respect read-only pages.
+///
+/// The bootloader maps the whole kernel writable, so a stray write could otherwise overwrite its
+/// code. The NX bit is only used when the bootloader enabled it, as it is reserved otherwise.
+pub fn protect_kernel() {
+    let no_execute = unsafe { Msr::IA32_EFER.read() } & EFER_NO_EXECUTE_ENABLE != 0;
+    let text = VirtualAddress::from_ptr(&raw const __text_start)
+        ..VirtualAddress::from_ptr(&raw const __text_end);
+    let rodata = VirtualAddress::from_ptr(&raw const __rodata_start)
+        ..VirtualAddress::from_ptr(&raw const __rodata_end);
+
+    protect_range(&text, |flags| {
+        flags.difference(PageTableEntryFlags::WRITABLE | PageTableEntryFlags::NO_EXECUTE)
+    });
+    protect_range(&rodata, |flags| {
+        let flags = flags.difference(PageTableEntryFlags::WRITABLE);
+        if no_execute {
+            flags | PageTableEntryFlags::NO_EXECUTE
+        } else {
+            flags
+        }
+    });
+    unsafe { Cr0::write(Cr0::read() | Cr0Flags::WRITE_PROTECT) };
+
+    logger::debug!(
+        "Kernel loaded at {:?}, mapped at {:?}",
+        limine::kernel_physical_base(),
+        limine::kernel_virtual_base()
+    );
+    logger::info!(
+        "Write-protected {} of kernel code and {} of read-only data",
+        HumanBytes(text.end.value() - text.start.value()),
+        HumanBytes(rodata.end.value() - rodata.start.value())
+    );
+}
+
+/// Apply `update` to the flags of every page overlapping `range`.
+fn protect_range(
+    range: &Range<VirtualAddress>,
+    update: impl Fn(PageTableEntryFlags) -> PageTableEntryFlags,
+) {
+    let mut page = range.start.align_down(PAGE_SIZE);
+    while page < range.end {
+        if let Err(error) = paging::update_page_flags(page, &update) {
+            panic!("Could not protect the kernel: {error}");
+        }
+        page += PAGE_SIZE;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Immutable static, placed in `.rodata`.
+    static READ_ONLY: u64 = 0x1234_5678;
+
+    /// A write to the page would fault: it is not writable and ring 0 respects it.
+    #[test_case]
+    fn kernel_read_only_data_is_write_protected() {
+        protect_kernel();
+        let page = VirtualAddress::from_ptr(&raw const READ_ONLY).align_down(PAGE_SIZE);
+        let mut flags = PageTableEntryFlags::empty();
+        paging::update_page_flags(page, |current| {
+            flags = current;
+            current
+        })
+        .unwrap();
+
+        assert!(!flags.contains(PageTableEntryFlags::WRITABLE));
+        assert!(Cr0::read().contains(Cr0Flags::WRITE_PROTECT));
+    }
+}
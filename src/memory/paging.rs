@@ -4,8 +4,11 @@ use core::{
 };
 
 use crate::cpu;
+use crate::cpu::registers::Cr3Flags;
+use crate::memory::{HumanBytes, PAGE_SIZE, frame_allocator};
+use crate::terminal::logger;
 
-use super::PhysicalAddress;
+use super::{PhysicalAddress, VirtualAddress};
 
 /// A 64-bit page table.
 #[repr(C, align(4096))]
@@ -66,6 +69,14 @@ impl PageTableEntry {
         Self(0)
     }
 
+    /// Returns an entry pointing to `frame`, which must be 4KiB-aligned, with the given `flags`.
+    #[inline]
+    pub fn new_with(frame: PhysicalAddress, flags: PageTableEntryFlags) -> Self {
+        let mut entry = Self(flags.bits());
+        entry.set_address(frame);
+        entry
+    }
+
     #[inline]
     pub fn is_unused(&self) -> bool {
         self.0 == 0
@@ -85,6 +96,16 @@ impl PageTableEntry {
     pub fn set_flags(&mut self, flags: PageTableEntryFlags) {
         self.0 = self.address().value() | flags.bits();
     }
+
+    /// Point the entry to `frame`, which must be 4KiB-aligned, preserving its flags.
+    #[inline]
+    pub fn set_address(&mut self, frame: PhysicalAddress) {
+        assert!(
+            frame.is_aligned(PAGE_SIZE),
+            "Page table entries must point to 4KiB-aligned frames, got {frame:?}"
+        );
+        self.0 = (frame.value() & Self::ADDRESS_MASK) | self.flags().bits();
+    }
 }
 
 impl fmt::Debug for PageTableEntry {
@@ -164,9 +185,304 @@ bitflags::bitflags! {
     }
 }
 
+/// A hierarchy of page tables, identified by the frame of its level 4 table.
+///
+/// Every address space shares the higher half of the active one when it is created, so the
+/// kernel and the higher half direct map stay mapped when switching between them. Dropping it
+/// frees its level 4 table and its lower half tables, but not the frames they map.
+pub struct AddressSpace {
+    level_4_frame: PhysicalAddress,
+}
+
+impl AddressSpace {
+    /// Index of the first level 4 entry mapping the higher half.
+    const HIGHER_HALF_START: usize = PageTable::ENTRY_COUNT / 2;
+
+    /// Allocate an address space with an empty lower half, sharing the higher half tables of
+    /// the active address space.
+    pub fn new() -> Self {
+        let level_4_frame = frame_allocator::allocate_exact(PAGE_SIZE);
+        let table = unsafe { &mut *level_4_frame.to_virtual().to_ptr::<PageTable>() };
+        table.clear();
+
+        let active = get_active_level_4_table();
+        for index in Self::HIGHER_HALF_START..PageTable::ENTRY_COUNT {
+            table[index] = active[index];
+        }
+        Self { level_4_frame }
+    }
+
+    pub fn level_4_frame(&self) -> PhysicalAddress {
+        self.level_4_frame
+    }
+
+    /// Install this address space on the current processor.
+    pub fn switch(&self) {
+        // The kernel stays mapped as the higher half is shared with the boot address space.
+        unsafe { cpu::registers::Cr3::write(self.level_4_frame, Cr3Flags::empty()) };
+    }
+}
+
+impl Drop for AddressSpace {
+    fn drop(&mut self) {
+        debug_assert_ne!(
+            cpu::registers::Cr3::read().0,
+            self.level_4_frame,
+            "Cannot drop the active address space"
+        );
+        // The higher half tables are shared with the other address spaces.
+        free_tables(self.level_4_frame, 4, Self::HIGHER_HALF_START);
+    }
+}
+
+/// Free the table at `frame` of the specified `level` along with the lower level tables pointed
+/// to by its first `entry_count` entries. The frames mapped by the tables are not freed.
+fn free_tables(frame: PhysicalAddress, level: u8, entry_count: usize) {
+    if level > 1 {
+        let table = unsafe { &*frame.to_virtual().to_ptr::<PageTable>() };
+        for entry in table.entries().take(entry_count) {
+            let flags = entry.flags();
+            if flags.contains(PageTableEntryFlags::PRESENT)
+                && !flags.contains(PageTableEntryFlags::HUGE_PAGE)
+            {
+                free_tables(entry.address(), level - 1, PageTable::ENTRY_COUNT);
+            }
+        }
+    }
+    frame_allocator::free(frame);
+}
+
 pub fn get_active_level_4_table() -> &'static mut PageTable {
     let (physical, _) = cpu::registers::Cr3::read();
     let page_table_ptr = physical.to_virtual().to_ptr::<PageTable>();
 
     unsafe { &mut *page_table_ptr }
 }
+
+#[derive(Debug)]
+pub enum MappingError {
+    AlreadyMapped(VirtualAddress),
+    NotMapped(VirtualAddress),
+    HugePage(VirtualAddress),
+}
+
+impl fmt::Display for MappingError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::AlreadyMapped(address) => {
+                write!(f, "Could not map {address:?} because it is already mapped")
+            }
+            Self::NotMapped(address) => write!(f, "{address:?} is not mapped"),
+            Self::HugePage(address) => write!(
+                f,
+                "Could not map {address:?} because it is part of an existing huge page"
+            ),
+        }
+    }
+}
+
+impl core::error::Error for MappingError {}
+
+/// Returns the index of the entry translating `address` in a table of the specified `level`,
+/// `4` being the top-level table and `1` the table containing the page entries.
+#[inline(always)]
+fn table_index(address: VirtualAddress, level: u8) -> usize {
+    (address.value() >> (12 + 9 * (level as usize - 1))) & (PageTable::ENTRY_COUNT - 1)
+}
+
+/// Returns the size of the memory translated by an entry of a table of the specified `level`.
+#[inline(always)]
+fn entry_span(level: u8) -> usize {
+    PAGE_SIZE << (9 * (level as usize - 1))
+}
+
+/// How an address is translated by the active address space, from the level 4 table down to the
+/// page, stopping at the first entry that is not present. The tables are walked when formatted.
+pub struct Translation(pub VirtualAddress);
+
+impl fmt::Display for Translation {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let address = self.0;
+        write!(f, "Translation of {address:?}:")?;
+        let (mut table_frame, _) = cpu::registers::Cr3::read();
+        for level in (1..=4).rev() {
+            let table = unsafe { &*table_frame.to_virtual().to_ptr::<PageTable>() };
+            let index = table_index(address, level);
+            let entry = table[index];
+            let flags = entry.flags();
+            write!(
+                f,
+                "\n  L{level} table {table_frame:?}, entry {index}: {flags:?}"
+            )?;
+
+            if !flags.contains(PageTableEntryFlags::PRESENT) {
+                return write!(f, "\n  Not mapped");
+            }
+            if level == 1 || flags.contains(PageTableEntryFlags::HUGE_PAGE) {
+                let span = entry_span(level);
+                return write!(
+                    f,
+                    "\n  Mapped by a {} page to {:?}",
+                    HumanBytes(span),
+                    entry.address() + (address.value() & (span - 1))
+                );
+            }
+            table_frame = entry.address();
+        }
+        Ok(())
+    }
+}
+
+/// Log how `address` is translated by the active address space, see [`Translation`].
+pub fn dump_mapping(address: VirtualAddress) {
+    logger::debug!("{}", Translation(address));
+}
+
+/// Log the present entries of the active level 4 table along with the range of addresses each
+/// one translates, at most `max_entries` of them.
+pub fn dump_all_present(max_entries: usize) {
+    let table = get_active_level_4_table();
+    let mut present = table
+        .entries()
+        .enumerate()
+        .filter(|(_, entry)| entry.flags().contains(PageTableEntryFlags::PRESENT));
+
+    logger::debug!("Present level 4 entries:");
+    for (index, entry) in present.by_ref().take(max_entries) {
+        let start = VirtualAddress::sign_extend_value(index * entry_span(4));
+        logger::debug!(
+            "  {index:3}: {start:#018x}..{:#018x} -> {:?} {:?}",
+            start + (entry_span(4) - 1),
+            entry.address(),
+            entry.flags()
+        );
+    }
+
+    let remaining = present.count();
+    if remaining != 0 {
+        logger::debug!("  ... and {remaining} more");
+    }
+}
+
+/// Returns the table pointed to by `entry`, allocating and linking a new empty one if the entry
+/// is unused.
+fn next_table_or_create(
+    entry: &mut PageTableEntry,
+    address: VirtualAddress,
+) -> Result<&'static mut PageTable, MappingError> {
+    let flags = entry.flags();
+    if flags.contains(PageTableEntryFlags::PRESENT) {
+        if flags.contains(PageTableEntryFlags::HUGE_PAGE) {
+            return Err(MappingError::HugePage(address));
+        }
+        return Ok(unsafe { &mut *entry.address().to_virtual().to_ptr::<PageTable>() });
+    }
+
+    let frame = frame_allocator::allocate_exact(PAGE_SIZE);
+    let table = unsafe { &mut *frame.to_virtual().to_ptr::<PageTable>() };
+    table.clear();
+    *entry = PageTableEntry::new_with(
+        frame,
+        PageTableEntryFlags::PRESENT | PageTableEntryFlags::WRITABLE,
+    );
+    Ok(table)
+}
+
+/// Map the 4KiB page at `page` to the `frame` in the active address space using the provided
+/// `flags`. Intermediate tables are created when necessary.
+pub fn map_page(
+    page: VirtualAddress,
+    frame: PhysicalAddress,
+    flags: PageTableEntryFlags,
+) -> Result<(), MappingError> {
+    let mut table = get_active_level_4_table();
+    for level in (2..=4).rev() {
+        table = next_table_or_create(&mut table[table_index(page, level)], page)?;
+    }
+
+    let entry = &mut table[table_index(page, 1)];
+    if !entry.is_unused() {
+        return Err(MappingError::AlreadyMapped(page));
+    }
+    *entry = PageTableEntry::new_with(frame, flags | PageTableEntryFlags::PRESENT);
+    cpu::tlb::flush(page);
+    Ok(())
+}
+
+/// Replace the flags of the 4KiB page at `page` in the active address space by the result of
+/// `update`, which receives its current flags. The page keeps its frame and stays present.
+pub fn update_page_flags(
+    page: VirtualAddress,
+    update: impl FnOnce(PageTableEntryFlags) -> PageTableEntryFlags,
+) -> Result<(), MappingError> {
+    let mut table = get_active_level_4_table();
+    for level in (2..=4).rev() {
+        let entry = table[table_index(page, level)];
+        let flags = entry.flags();
+        if !flags.contains(PageTableEntryFlags::PRESENT) {
+            return Err(MappingError::NotMapped(page));
+        }
+        if flags.contains(PageTableEntryFlags::HUGE_PAGE) {
+            return Err(MappingError::HugePage(page));
+        }
+        table = unsafe { &mut *entry.address().to_virtual().to_ptr::<PageTable>() };
+    }
+
+    let entry = &mut table[table_index(page, 1)];
+    if !entry.flags().contains(PageTableEntryFlags::PRESENT) {
+        return Err(MappingError::NotMapped(page));
+    }
+    entry.set_flags(update(entry.flags()) | PageTableEntryFlags::PRESENT);
+    cpu::tlb::flush(page);
+    Ok(())
+}
+
+/// Map the memory-mapped I/O region of `size` bytes starting at `physical` in the higher half
+/// direct map with caching disabled, and returns its virtual address.
+///
+/// Since Limine does not map MMIO regions in the higher half direct map, this is required before
+/// accessing device registers through [`PhysicalAddress::to_virtual`]. Pages that are already
+/// mapped are left untouched.
+pub fn map_mmio(physical: PhysicalAddress, size: usize) -> VirtualAddress {
+    let start = physical.align_down(PAGE_SIZE);
+    let end = (physical + size).align_up(PAGE_SIZE);
+    let flags = PageTableEntryFlags::WRITABLE
+        | PageTableEntryFlags::CACHING_POLICY
+        | PageTableEntryFlags::DISABLE_CACHING;
+
+    let mut frame = start;
+    while frame < end {
+        // Pages already mapped, possibly by a huge page, are left untouched
+        let _ = map_page(frame.to_virtual(), frame, flags);
+        frame += PAGE_SIZE;
+    }
+
+    physical.to_virtual()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test_case]
+    fn dropping_an_address_space_frees_its_tables() {
+        let allocated = frame_allocator::stats().allocated;
+        let frame = frame_allocator::allocate_exact(PAGE_SIZE);
+        let (boot_level_4_frame, boot_flags) = cpu::registers::Cr3::read();
+
+        // Mapping a lower half page creates a table at each level below the level 4 one.
+        let address_space = AddressSpace::new();
+        address_space.switch();
+        let mapped = map_page(
+            VirtualAddress::from(0x4000_0000),
+            frame,
+            PageTableEntryFlags::WRITABLE,
+        );
+        unsafe { cpu::registers::Cr3::write(boot_level_4_frame, boot_flags) };
+        mapped.unwrap();
+
+        drop(address_space);
+        frame_allocator::free(frame);
+        assert_eq!(frame_allocator::stats().allocated, allocated);
+    }
+}
@@ -0,0 +1,65 @@
+//! Synchronization primitives that are safe to share with interrupt handlers.
+
+use core::ops::{Deref, DerefMut};
+
+use spin::{Mutex, MutexGuard};
+
+use crate::cpu::interrupts;
+
+/// Mutex disabling interrupts on the current processor while it is locked, so that an interrupt
+/// handler locking it can't deadlock with the code it interrupted.
+///
+/// Interrupts stay disabled for as long as the guard is held: it must be released quickly and
+/// must not be held across code that waits on interrupts, such as [`crate::cpu::time::sleep_ms`].
+pub struct IrqMutex<T> {
+    inner: Mutex<T>,
+}
+
+impl<T> IrqMutex<T> {
+    pub const fn new(value: T) -> Self {
+        Self {
+            inner: Mutex::new(value),
+        }
+    }
+
+    /// Disable interrupts and lock the mutex. The previous interrupt state is restored when the
+    /// returned guard is dropped.
+    pub fn lock(&self) -> IrqMutexGuard<'_, T> {
+        let interrupts_were_enabled = interrupts::are_enabled();
+        interrupts::disable();
+
+        IrqMutexGuard {
+            guard: Some(self.inner.lock()),
+            interrupts_were_enabled,
+        }
+    }
+}
+
+pub struct IrqMutexGuard<'a, T> {
+    /// Always `Some` until dropped, the lock must be released before interrupts are restored.
+    guard: Option<MutexGuard<'a, T>>,
+    interrupts_were_enabled: bool,
+}
+
+impl<T> Deref for IrqMutexGuard<'_, T> {
+    type Target = T;
+
+    fn deref(&self) -> &T {
+        self.guard.as_ref().unwrap()
+    }
+}
+
+impl<T> DerefMut for IrqMutexGuard<'_, T> {
+    fn deref_mut(&mut self) -> &mut T {
+        self.guard.as_mut().unwrap()
+    }
+}
+
+impl<T> Drop for IrqMutexGuard<'_, T> {
+    fn drop(&mut self) {
+        self.guard.take();
+        if self.interrupts_were_enabled {
+            interrupts::enable();
+        }
+    }
+}
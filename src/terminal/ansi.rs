@@ -2,12 +2,15 @@ use core::ops::Range;
 
 const ESC: char = '\x1b';
 const BRACKET: char = '\x5b';
+/// Final character of `ESC H`, setting a tab stop at the cursor (HTS).
+const TAB_SET: char = 'H';
 
 const PARAM_RANGE: Range<char> = '\x30'..'\x40';
 const INTERMEDIATE_RANGE: Range<char> = '\x20'..'\x30';
 const FINAL_RANGE: Range<char> = '\x40'..'\u{80}';
 
 const BUFFER_SIZE: usize = 20;
+const MAX_PARAMS: usize = 8;
 
 #[derive(PartialEq, Eq, Debug, Clone, Copy)]
 pub enum EraseMode {
@@ -28,6 +31,15 @@ pub enum AnsiColor {
     DefaultBackground,
 }
 
+/// Report requested by a Device Status Report (DSR) sequence.
+#[derive(PartialEq, Eq, Debug, Clone, Copy)]
+pub enum StatusReport {
+    /// `ESC[5n`, answered with `ESC[0n` when the terminal is working.
+    Status,
+    /// `ESC[6n`, answered with the 1-indexed cursor position as `ESC[<line>;<column>R`.
+    CursorPosition,
+}
+
 #[derive(PartialEq, Eq, Debug, Clone, Copy)]
 pub enum Direction {
     Up,
@@ -52,10 +64,75 @@ pub enum AnsiCommand {
         preserve_offscreen: bool,
     },
     EraseLine(EraseMode),
+    /// Set a tab stop at the cursor's column (HTS, `ESC H`).
+    SetTabStop,
+    /// Clear the tab stop at the cursor's column, or every tab stop if `all` is set (TBC).
+    ClearTabStop {
+        all: bool,
+    },
+    /// Move the cursor forward to the `n`th next tab stop (CHT).
+    CursorForwardTab(usize),
+    /// Insert blank cells at the cursor, shifting the rest of the line right (ICH).
+    InsertCharacters(usize),
+    /// Delete cells at the cursor, shifting the rest of the line left (DCH).
+    DeleteCharacters(usize),
+    /// Insert blank lines at the cursor, shifting the lines below down (IL).
+    InsertLines(usize),
+    /// Delete lines at the cursor, shifting the lines below up (DL).
+    DeleteLines(usize),
     ScrollRelative(isize),
-    SetBackground(AnsiColor),
+    /// Restrict scrolling to the screen rows from `top` to `bottom` included, or down to the last
+    /// row if `bottom` is `None` (DECSTBM).
+    SetScrollRegion {
+        top: usize,
+        bottom: Option<usize>,
+    },
+    SelectGraphicRendition(SgrSequence),
+    DeviceStatusReport(StatusReport),
+    /// DEC private mode set (`ESC[?<mode>h`) or reset (`ESC[?<mode>l`).
+    SetPrivateMode {
+        mode: u16,
+        enabled: bool,
+    },
+}
+
+/// Single effect of a Select Graphic Rendition (SGR) sequence.
+#[derive(PartialEq, Eq, Debug, Clone, Copy)]
+pub enum SgrEffect {
+    Reset,
     SetForeground(AnsiColor),
-    ResetGraphicRendition,
+    SetBackground(AnsiColor),
+    SetBold(bool),
+    SetDim(bool),
+    SetUnderline(bool),
+    SetReverse(bool),
+    /// Clear both bold and dim.
+    NormalIntensity,
+}
+
+/// Effects of a SGR sequence such as `ESC[1;31m`, in the order they must be applied.
+#[derive(PartialEq, Eq, Debug, Clone, Copy)]
+pub struct SgrSequence {
+    effects: [SgrEffect; MAX_PARAMS],
+    len: usize,
+}
+
+impl SgrSequence {
+    const fn new() -> Self {
+        Self {
+            effects: [SgrEffect::Reset; MAX_PARAMS],
+            len: 0,
+        }
+    }
+
+    fn push(&mut self, effect: SgrEffect) {
+        self.effects[self.len] = effect;
+        self.len += 1;
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = SgrEffect> + '_ {
+        self.effects[..self.len].iter().copied()
+    }
 }
 
 #[derive(PartialEq, Eq, Debug, Clone, Copy)]
@@ -114,7 +191,7 @@ pub struct AnsiHandler {
 impl AnsiHandler {
     pub fn new() -> Self {
         Self {
-            buffer: [0; 20],
+            buffer: [0; BUFFER_SIZE],
             ptr: 0,
             stage: AnsiStage::Escape,
         }
@@ -151,6 +228,8 @@ impl AnsiHandler {
                                 Err(error) => return ParserResult::Error(error),
                             }
                         }
+                    } else if self.stage == AnsiStage::CtrlSequenceIdentifier && ch == TAB_SET {
+                        return ParserResult::Valid(AnsiCommand::SetTabStop);
                     } else {
                         return ParserResult::Error(AnsiError::Unsupported);
                     }
@@ -160,11 +239,11 @@ impl AnsiHandler {
                         if ch.len_utf8() != 1 {
                             return ParserResult::Error(AnsiError::InvalidParameters);
                         }
-                        if self.ptr + ch.len_utf8() == BUFFER_SIZE {
+                        if self.ptr + ch.len_utf8() > BUFFER_SIZE {
                             return ParserResult::Error(AnsiError::BufferOverflow);
                         }
-                        let bytes = ch.encode_utf8(&mut self.buffer[self.ptr..]);
-                        self.ptr += 1;
+                        ch.encode_utf8(&mut self.buffer[self.ptr..]);
+                        self.ptr += ch.len_utf8();
                     } else {
                         self.stage = self.stage.next().unwrap();
                         continue;
@@ -189,10 +268,18 @@ impl AnsiHandler {
     /// that marks the end of the sequence.
     fn parse_final(&mut self, final_char: char) -> Result<AnsiCommand, AnsiError> {
         let s = str::from_utf8(&self.buffer).unwrap();
-        let mut params = [0; 5];
+        let mut params = [0; MAX_PARAMS];
         let mut n_params = 0;
 
         let s = s.trim_end_matches('\0');
+        let (private, s) = match s.strip_prefix('?') {
+            Some(s) => (true, s),
+            None => (false, s),
+        };
+        if s.starts_with(['<', '=', '>', '?']) {
+            return Err(AnsiError::Unsupported);
+        }
+
         if s.is_empty() {
             n_params = 0;
         } else {
@@ -203,12 +290,20 @@ impl AnsiHandler {
                 if part.is_empty() {
                     params[n_params] = 0;
                 } else {
-                    params[n_params] = part.parse().unwrap();
+                    params[n_params] = part.parse().map_err(|_| AnsiError::InvalidParameters)?;
                 }
                 n_params += 1;
             }
         }
 
+        if private {
+            return match final_char {
+                'h' => parse_private_mode(n_params, &params, true),
+                'l' => parse_private_mode(n_params, &params, false),
+                _ => Err(AnsiError::Unsupported),
+            };
+        }
+
         match final_char {
             'm' => parse_sgr(n_params, &params),
             'J' => parse_erase_display(n_params, &params),
@@ -221,6 +316,14 @@ impl AnsiHandler {
             'G' => parse_move_cursor_column(n_params, &params),
             'S' => parse_scroll(n_params, &params, Direction::Up),
             'T' => parse_scroll(n_params, &params, Direction::Down),
+            'r' => parse_scroll_region(n_params, &params),
+            'g' => parse_clear_tab_stop(n_params, &params),
+            'I' => parse_count(n_params, &params).map(AnsiCommand::CursorForwardTab),
+            'n' => parse_status_report(n_params, &params),
+            '@' => parse_count(n_params, &params).map(AnsiCommand::InsertCharacters),
+            'P' => parse_count(n_params, &params).map(AnsiCommand::DeleteCharacters),
+            'L' => parse_count(n_params, &params).map(AnsiCommand::InsertLines),
+            'M' => parse_count(n_params, &params).map(AnsiCommand::DeleteLines),
             _ => Err(AnsiError::Unsupported),
         }
     }
@@ -244,18 +347,18 @@ impl AnsiHandler {
     }
 }
 
-fn parse_rgb_sgr(zone: i32, r: i32, g: i32, b: i32) -> Result<AnsiCommand, AnsiError> {
+fn parse_rgb_sgr(zone: i32, r: i32, g: i32, b: i32) -> Result<SgrEffect, AnsiError> {
     let r: u8 = u8::try_from(r).map_err(|_| AnsiError::InvalidParameters)?;
     let g: u8 = u8::try_from(g).map_err(|_| AnsiError::InvalidParameters)?;
     let b: u8 = u8::try_from(b).map_err(|_| AnsiError::InvalidParameters)?;
 
     match zone {
-        38 => Ok(AnsiCommand::SetForeground(AnsiColor::Rgb(r, g, b))),
-        48 => Ok(AnsiCommand::SetBackground(AnsiColor::Rgb(r, g, b))),
+        38 => Ok(SgrEffect::SetForeground(AnsiColor::Rgb(r, g, b))),
+        48 => Ok(SgrEffect::SetBackground(AnsiColor::Rgb(r, g, b))),
         _ => Err(AnsiError::InvalidParameters),
     }
 }
-fn parse_256_sgr(zone: i32, color_code: i32) -> Result<AnsiCommand, AnsiError> {
+fn parse_256_sgr(zone: i32, color_code: i32) -> Result<SgrEffect, AnsiError> {
     let color_code = u8::try_from(color_code).map_err(|_| AnsiError::InvalidParameters)?;
     let color = match color_code {
         0..16 => AnsiColor::ColorCode(color_code),
@@ -275,40 +378,80 @@ fn parse_256_sgr(zone: i32, color_code: i32) -> Result<AnsiCommand, AnsiError> {
     };
 
     match zone {
-        38 => Ok(AnsiCommand::SetForeground(color)),
-        48 => Ok(AnsiCommand::SetBackground(color)),
+        38 => Ok(SgrEffect::SetForeground(color)),
+        48 => Ok(SgrEffect::SetBackground(color)),
         _ => Err(AnsiError::InvalidParameters),
     }
 }
 
-fn parse_16_sgr(color_code: i32) -> Result<AnsiCommand, AnsiError> {
+// 38 and 48 introduce extended colors, which are parsed before reaching this function.
+#[allow(non_contiguous_range_endpoints)]
+fn parse_16_sgr(color_code: i32) -> Result<SgrEffect, AnsiError> {
     let color_code = u8::try_from(color_code).map_err(|_| AnsiError::InvalidParameters)?;
     match color_code {
-        0 => Ok(AnsiCommand::ResetGraphicRendition),
-        30..38 => Ok(AnsiCommand::SetForeground(AnsiColor::ColorCode(
+        0 => Ok(SgrEffect::Reset),
+        1 => Ok(SgrEffect::SetBold(true)),
+        2 => Ok(SgrEffect::SetDim(true)),
+        4 => Ok(SgrEffect::SetUnderline(true)),
+        7 => Ok(SgrEffect::SetReverse(true)),
+        22 => Ok(SgrEffect::NormalIntensity),
+        24 => Ok(SgrEffect::SetUnderline(false)),
+        27 => Ok(SgrEffect::SetReverse(false)),
+        39 => Ok(SgrEffect::SetForeground(AnsiColor::DefaultForeground)),
+        49 => Ok(SgrEffect::SetBackground(AnsiColor::DefaultBackground)),
+        30..38 => Ok(SgrEffect::SetForeground(AnsiColor::ColorCode(
             color_code - 30,
         ))),
-        40..48 => Ok(AnsiCommand::SetBackground(AnsiColor::ColorCode(
+        40..48 => Ok(SgrEffect::SetBackground(AnsiColor::ColorCode(
             color_code - 40,
         ))),
-        90..98 => Ok(AnsiCommand::SetForeground(AnsiColor::ColorCode(
+        90..98 => Ok(SgrEffect::SetForeground(AnsiColor::ColorCode(
             color_code - 90 + 8,
         ))),
-        100..108 => Ok(AnsiCommand::SetBackground(AnsiColor::ColorCode(
+        100..108 => Ok(SgrEffect::SetBackground(AnsiColor::ColorCode(
             color_code - 100 + 8,
         ))),
         _ => Err(AnsiError::InvalidParameters),
     }
 }
 
+/// Parse each SGR code of `params` in order. Extended colors (`38`/`48`) consume the parameters
+/// following them.
 fn parse_sgr(n_params: usize, params: &[i32]) -> Result<AnsiCommand, AnsiError> {
-    match (params[1], n_params) {
-        (_, 0) => Ok(AnsiCommand::ResetGraphicRendition),
-        (2, 4 | 5) => parse_rgb_sgr(params[0], params[2], params[3], params[4]),
-        (5, 2 | 3) => parse_256_sgr(params[0], params[2]),
-        (_, 1) => parse_16_sgr(params[0]),
-        _ => Err(AnsiError::InvalidParameters),
+    let params = &params[..n_params];
+    let mut sequence = SgrSequence::new();
+
+    if params.is_empty() {
+        sequence.push(SgrEffect::Reset);
     }
+
+    let mut i = 0;
+    while i < params.len() {
+        let (effect, consumed) = match params[i..] {
+            [zone @ (38 | 48), 5, color_code, ..] => (parse_256_sgr(zone, color_code)?, 3),
+            [zone @ (38 | 48), 2, r, g, b, ..] => (parse_rgb_sgr(zone, r, g, b)?, 5),
+            [38 | 48, ..] => return Err(AnsiError::InvalidParameters),
+            [code, ..] => (parse_16_sgr(code)?, 1),
+            [] => unreachable!(),
+        };
+        sequence.push(effect);
+        i += consumed;
+    }
+
+    Ok(AnsiCommand::SelectGraphicRendition(sequence))
+}
+
+fn parse_private_mode(
+    n_params: usize,
+    params: &[i32],
+    enabled: bool,
+) -> Result<AnsiCommand, AnsiError> {
+    if n_params != 1 {
+        return Err(AnsiError::InvalidParameters);
+    }
+
+    let mode = u16::try_from(params[0]).map_err(|_| AnsiError::InvalidParameters)?;
+    Ok(AnsiCommand::SetPrivateMode { mode, enabled })
 }
 
 fn parse_erase_display(n_params: usize, params: &[i32]) -> Result<AnsiCommand, AnsiError> {
@@ -384,9 +527,13 @@ fn parse_move_cursor_absolute(n_params: usize, params: &[i32]) -> Result<AnsiCom
     if n_params > 2 {
         Err(AnsiError::Unsupported)
     } else {
+        // Positions are 1-based, a missing or zero position standing for the first one.
         let line = usize::try_from(params[0]).map_err(|_| AnsiError::InvalidParameters)?;
         let column = usize::try_from(params[1]).map_err(|_| AnsiError::InvalidParameters)?;
-        Ok(AnsiCommand::CursorMoveAbsolute { line, column })
+        Ok(AnsiCommand::CursorMoveAbsolute {
+            line: line.saturating_sub(1),
+            column: column.saturating_sub(1),
+        })
     }
 }
 
@@ -415,3 +562,74 @@ fn parse_scroll(
         _ => Err(AnsiError::InvalidParameters),
     }
 }
+
+/// Parse the 1-indexed `top` and `bottom` rows of a scroll region, missing or zero parameters
+/// selecting the edges of the screen.
+fn parse_scroll_region(n_params: usize, params: &[i32]) -> Result<AnsiCommand, AnsiError> {
+    if n_params > 2 {
+        return Err(AnsiError::InvalidParameters);
+    }
+
+    let top = usize::try_from(params[0]).map_err(|_| AnsiError::InvalidParameters)?;
+    let bottom = usize::try_from(params[1]).map_err(|_| AnsiError::InvalidParameters)?;
+    let bottom = (bottom != 0).then(|| bottom - 1);
+    let top = top.saturating_sub(1);
+    if bottom.is_some_and(|bottom| bottom <= top) {
+        return Err(AnsiError::InvalidParameters);
+    }
+
+    Ok(AnsiCommand::SetScrollRegion { top, bottom })
+}
+
+/// Parse the single repetition count of a sequence, a missing or zero count standing for 1.
+fn parse_count(n_params: usize, params: &[i32]) -> Result<usize, AnsiError> {
+    if n_params > 1 {
+        return Err(AnsiError::InvalidParameters);
+    }
+
+    let count = usize::try_from(params[0]).map_err(|_| AnsiError::InvalidParameters)?;
+    Ok(count.max(1))
+}
+
+fn parse_status_report(n_params: usize, params: &[i32]) -> Result<AnsiCommand, AnsiError> {
+    if n_params != 1 {
+        return Err(AnsiError::InvalidParameters);
+    }
+
+    match params[0] {
+        5 => Ok(AnsiCommand::DeviceStatusReport(StatusReport::Status)),
+        6 => Ok(AnsiCommand::DeviceStatusReport(
+            StatusReport::CursorPosition,
+        )),
+        _ => Err(AnsiError::Unsupported),
+    }
+}
+
+fn parse_clear_tab_stop(n_params: usize, params: &[i32]) -> Result<AnsiCommand, AnsiError> {
+    if n_params > 1 {
+        return Err(AnsiError::InvalidParameters);
+    }
+
+    match params[0] {
+        0 => Ok(AnsiCommand::ClearTabStop { all: false }),
+        3 => Ok(AnsiCommand::ClearTabStop { all: true }),
+        _ => Err(AnsiError::InvalidParameters),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test_case]
+    fn long_parameter_overflows_the_buffer() {
+        let mut handler = AnsiHandler::new();
+        handler.try_start();
+        let result = handler.continue_parse("[123456789012345678901m".chars());
+
+        assert!(matches!(
+            result,
+            ParserResult::Error(AnsiError::BufferOverflow)
+        ));
+    }
+}
@@ -1,4 +1,5 @@
-use noto_sans_mono_bitmap::{self as nsmb, FontWeight, RasterHeight, RasterizedChar};
+use noto_sans_mono_bitmap::{self as nsmb, FontWeight, RasterHeight};
+use spin::{Mutex, Once};
 
 pub const STYLE: FontWeight = noto_sans_mono_bitmap::FontWeight::Bold;
 pub const SIZE: RasterHeight = RasterHeight::Size20;
@@ -6,8 +7,49 @@ pub const SIZE: RasterHeight = RasterHeight::Size20;
 pub const HEIGHT: usize = SIZE.val();
 pub const WIDTH: usize = nsmb::get_raster_width(STYLE, SIZE);
 
+/// Number of non-ASCII glyphs kept in [`RECENT_GLYPHS`].
+const RECENT_GLYPHS_SIZE: usize = 8;
 
-pub fn get_raster(ch: char) -> Option<RasterizedChar> {
-    nsmb::get_raster(ch, STYLE, SIZE)
+/// Rows of alpha values of a rasterized glyph.
+pub type Raster = &'static [&'static [u8]];
+/// A character and its raster, if the font has a glyph for it.
+type CachedGlyph = (char, Option<Raster>);
+
+/// Rasters of the ASCII characters, looked up once on first use.
+static ASCII_GLYPHS: Once<[Option<Raster>; 128]> = Once::new();
+/// Most recently used non-ASCII glyphs, the most recent first.
+static RECENT_GLYPHS: Mutex<[Option<CachedGlyph>; RECENT_GLYPHS_SIZE]> =
+    Mutex::new([None; RECENT_GLYPHS_SIZE]);
+
+/// Returns the raster of `ch`, if the font has a glyph for it. Glyphs are cached to avoid looking
+/// them up in the font on every redraw.
+pub fn get_raster(ch: char) -> Option<Raster> {
+    if ch.is_ascii() {
+        let ascii = ASCII_GLYPHS.call_once(|| core::array::from_fn(|i| rasterize(i as u8 as char)));
+        return ascii[ch as usize];
+    }
+
+    // The cache may be held by the code this one interrupted, rasterize directly then.
+    let Some(mut recent) = RECENT_GLYPHS.try_lock() else {
+        return rasterize(ch);
+    };
+    let position = recent
+        .iter()
+        .position(|glyph| glyph.is_some_and(|(cached, _)| cached == ch));
+    match position {
+        Some(position) => {
+            recent[..=position].rotate_right(1);
+            recent[0]?.1
+        }
+        None => {
+            let raster = rasterize(ch);
+            recent.rotate_right(1);
+            recent[0] = Some((ch, raster));
+            raster
+        }
+    }
 }
 
+fn rasterize(ch: char) -> Option<Raster> {
+    nsmb::get_raster(ch, STYLE, SIZE).map(|raster| raster.raster())
+}
@@ -0,0 +1,232 @@
+//! Line editing on top of the keyboard driver, for interactive programs such as a shell.
+//!
+//! [`read_line`] echoes the keys it reads to the terminal and edits the line on screen with ANSI
+//! sequences: ICH and DCH to insert and delete characters, and relative cursor moves. Completed
+//! lines are kept in a history recalled with Up and Down.
+
+use core::fmt::{self, Write};
+
+use spin::Mutex;
+
+use crate::cpu;
+use crate::drivers::keyboard::{self, KeyCode, KeyEvent};
+use crate::terminal::tty::{self, TerminalStdin};
+
+/// Number of lines kept in the history, the oldest one is dropped when it is full.
+const HISTORY_SIZE: usize = 16;
+/// Maximum length of a line kept in the history, longer lines are truncated.
+const HISTORY_LINE_SIZE: usize = 256;
+
+static HISTORY: Mutex<History> = Mutex::new(History::new());
+
+#[derive(Clone, Copy)]
+struct HistoryLine {
+    bytes: [u8; HISTORY_LINE_SIZE],
+    len: usize,
+}
+
+/// Ring of the last [`HISTORY_SIZE`] lines read.
+struct History {
+    lines: [HistoryLine; HISTORY_SIZE],
+    /// Index of the next line to write.
+    next: usize,
+    count: usize,
+}
+
+impl History {
+    const fn new() -> Self {
+        Self {
+            lines: [HistoryLine {
+                bytes: [0; HISTORY_LINE_SIZE],
+                len: 0,
+            }; HISTORY_SIZE],
+            next: 0,
+            count: 0,
+        }
+    }
+
+    /// Add `line` to the history, unless it is empty.
+    fn push(&mut self, line: &[u8]) {
+        if line.is_empty() {
+            return;
+        }
+
+        let len = line.len().min(HISTORY_LINE_SIZE);
+        let entry = &mut self.lines[self.next];
+        entry.bytes[..len].copy_from_slice(&line[..len]);
+        entry.len = len;
+        self.next = (self.next + 1) % HISTORY_SIZE;
+        self.count = (self.count + 1).min(HISTORY_SIZE);
+    }
+
+    /// Returns the `age`th most recent line, 0 being the most recent one.
+    fn get(&self, age: usize) -> Option<HistoryLine> {
+        if age >= self.count {
+            return None;
+        }
+        Some(self.lines[(self.next + HISTORY_SIZE - 1 - age) % HISTORY_SIZE])
+    }
+}
+
+/// Read a line from the keyboard into `buffer` and returns its length, the final newline
+/// excluded. Keys typed while the buffer is full are ignored, and keys that do not edit the line,
+/// such as PageUp, are forwarded to the terminal.
+///
+/// Only ASCII characters are supported, and the cursor can't move across the lines of the screen
+/// so the line should fit on the current one.
+pub fn read_line(buffer: &mut [u8]) -> usize {
+    let mut editor = LineEditor::new(buffer);
+    loop {
+        while let Some(event) = keyboard::poll() {
+            if editor.handle_key_event(event) {
+                HISTORY.lock().push(editor.line());
+                return editor.len;
+            }
+        }
+        cpu::wait_for_interrupt();
+    }
+}
+
+/// Line being edited, along with the position of the cursor in it.
+struct LineEditor<'buf> {
+    buffer: &'buf mut [u8],
+    len: usize,
+    cursor: usize,
+    /// Age of the history line being shown, if any.
+    history_age: Option<usize>,
+}
+
+impl<'buf> LineEditor<'buf> {
+    fn new(buffer: &'buf mut [u8]) -> Self {
+        Self {
+            buffer,
+            len: 0,
+            cursor: 0,
+            history_age: None,
+        }
+    }
+
+    fn line(&self) -> &[u8] {
+        &self.buffer[..self.len]
+    }
+
+    /// Apply `event` to the line. Returns true once the line is completed.
+    fn handle_key_event(&mut self, event: KeyEvent) -> bool {
+        if tty::handle_debug_key(event) || !event.pressed {
+            return false;
+        }
+
+        match event.key {
+            KeyCode::Left => self.move_to(self.cursor.saturating_sub(1)),
+            KeyCode::Right => self.move_to((self.cursor + 1).min(self.len)),
+            KeyCode::Home => self.move_to(0),
+            KeyCode::End => self.move_to(self.len),
+            KeyCode::Delete => self.delete(),
+            KeyCode::Up if !event.modifiers.shift() => self.recall_older(),
+            KeyCode::Down if !event.modifiers.shift() => self.recall_newer(),
+            _ => match event.to_char() {
+                Some('\n') => {
+                    echo(format_args!("\n"));
+                    return true;
+                }
+                Some('\x08') => self.backspace(),
+                Some(ch) if ch.is_ascii() && !ch.is_ascii_control() => self.insert(ch as u8),
+                Some(_) => (),
+                None => tty::handle_key_event(event),
+            },
+        }
+        false
+    }
+
+    /// Insert `byte` at the cursor, shifting the rest of the line right.
+    fn insert(&mut self, byte: u8) {
+        if self.len == self.buffer.len() {
+            return;
+        }
+
+        if self.cursor < self.len {
+            self.buffer
+                .copy_within(self.cursor..self.len, self.cursor + 1);
+            echo(format_args!("\x1b[@"));
+        }
+        self.buffer[self.cursor] = byte;
+        self.len += 1;
+        self.cursor += 1;
+        echo(format_args!("{}", byte as char));
+    }
+
+    /// Delete the character before the cursor.
+    fn backspace(&mut self) {
+        if self.cursor == 0 {
+            return;
+        }
+        self.move_to(self.cursor - 1);
+        self.delete();
+    }
+
+    /// Delete the character under the cursor, shifting the rest of the line left.
+    fn delete(&mut self) {
+        if self.cursor == self.len {
+            return;
+        }
+
+        self.buffer
+            .copy_within(self.cursor + 1..self.len, self.cursor);
+        self.len -= 1;
+        echo(format_args!("\x1b[P"));
+    }
+
+    fn move_to(&mut self, position: usize) {
+        if position < self.cursor {
+            echo(format_args!("\x1b[{}D", self.cursor - position));
+        } else if position > self.cursor {
+            echo(format_args!("\x1b[{}C", position - self.cursor));
+        }
+        self.cursor = position;
+    }
+
+    /// Show the history line preceding the one shown, if any.
+    fn recall_older(&mut self) {
+        let age = self.history_age.map_or(0, |age| age + 1);
+        let Some(line) = HISTORY.lock().get(age) else {
+            return;
+        };
+        self.history_age = Some(age);
+        self.replace(&line.bytes[..line.len]);
+    }
+
+    /// Show the history line following the one shown, or an empty line past the most recent one.
+    fn recall_newer(&mut self) {
+        match self.history_age {
+            None => (),
+            Some(0) => {
+                self.history_age = None;
+                self.replace(&[]);
+            }
+            Some(age) => {
+                if let Some(line) = HISTORY.lock().get(age - 1) {
+                    self.history_age = Some(age - 1);
+                    self.replace(&line.bytes[..line.len]);
+                }
+            }
+        }
+    }
+
+    /// Replace the whole line with `text`, truncated to the buffer, and put the cursor at its end.
+    fn replace(&mut self, text: &[u8]) {
+        self.move_to(0);
+        echo(format_args!("\x1b[K"));
+
+        let len = text.len().min(self.buffer.len());
+        self.buffer[..len].copy_from_slice(&text[..len]);
+        self.len = len;
+        self.cursor = len;
+        for &byte in self.line() {
+            echo(format_args!("{}", byte as char));
+        }
+    }
+}
+
+fn echo(args: fmt::Arguments) {
+    let _ = TerminalStdin::new().write_fmt(args);
+}
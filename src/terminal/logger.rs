@@ -1,6 +1,24 @@
 use core::fmt;
+use core::sync::atomic::{AtomicBool, AtomicU8, AtomicUsize, Ordering};
 use fmt::Write;
-use crate::terminal::tty::{self, BufferWriter};
+use spin::{Mutex, Once};
+use crate::cpu::time;
+use crate::util::FmtBuf;
+
+/// Maximum number of sinks that can be registered on a [`Logger`].
+const MAX_SINKS: usize = 4;
+
+/// Size of the buffer a log line is formatted into, longer lines are truncated.
+const LINE_SIZE: usize = 1024;
+
+/// Destination of log messages.
+pub type SinkWriter = &'static Mutex<dyn fmt::Write + Send>;
+
+#[derive(Clone, Copy)]
+struct Sink {
+    writer: SinkWriter,
+    panic_safe: bool,
+}
 
 #[derive(Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
 pub enum LogLevel {
@@ -11,6 +29,28 @@ pub enum LogLevel {
     Critical = 4,
 }
 
+impl LogLevel {
+    const fn from_u8(value: u8) -> Self {
+        match value {
+            0 => Self::Debug,
+            1 => Self::Info,
+            2 => Self::Warn,
+            3 => Self::Error,
+            _ => Self::Critical,
+        }
+    }
+
+    /// Returns the level below this one, `Debug` being the lowest.
+    pub const fn lower(self) -> Self {
+        Self::from_u8((self as u8).saturating_sub(1))
+    }
+
+    /// Returns the level above this one, `Critical` being the highest.
+    pub const fn higher(self) -> Self {
+        Self::from_u8(self as u8 + 1)
+    }
+}
+
 impl fmt::Display for LogLevel {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         if f.alternate() {
@@ -33,37 +73,131 @@ impl fmt::Display for LogLevel {
     }
 }
 
+/// Uptime at which a message is logged, displayed as `seconds.milliseconds`, or `boot` when
+/// the timer is not initialized yet.
+struct Timestamp;
+
+impl fmt::Display for Timestamp {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if !time::is_initialized() {
+            return f.write_str("boot");
+        }
+
+        let uptime = time::uptime_ms();
+        write!(f, "{}.{:03}", uptime / 1000, uptime % 1000)
+    }
+}
+
+/// Register `writer` as a sink of the global logger. See [`Logger::add_sink`].
+pub fn add_sink(writer: SinkWriter, panic_safe: bool) {
+    crate::LOGGER.add_sink(writer, panic_safe);
+}
+
+/// Restrict the global logger to its panic-safe sinks. See [`Logger::enter_panic_mode`].
+pub fn enter_panic_mode() {
+    crate::LOGGER.enter_panic_mode();
+}
+
 pub struct Logger {
-    level: LogLevel,
+    level: AtomicU8,
+    sinks: [Once<Sink>; MAX_SINKS],
+    sink_count: AtomicUsize,
+    panicking: AtomicBool,
 }
 
 impl Logger {
     pub const fn new(level: LogLevel) -> Self {
-        Self { level }
+        Self {
+            level: AtomicU8::new(level as u8),
+            sinks: [const { Once::new() }; MAX_SINKS],
+            sink_count: AtomicUsize::new(0),
+            panicking: AtomicBool::new(false),
+        }
+    }
+
+    /// Returns the minimum level of the messages that are logged.
+    pub fn level(&self) -> LogLevel {
+        LogLevel::from_u8(self.level.load(Ordering::Relaxed))
+    }
+
+    /// Only log messages of `level` or above from now on.
+    pub fn set_level(&self, level: LogLevel) {
+        self.level.store(level as u8, Ordering::Relaxed);
+    }
+
+    /// Register `writer` as a destination for every subsequent message. Sinks that are
+    /// `panic_safe` keep receiving messages after [`Logger::enter_panic_mode`] is called, they
+    /// should not depend on state that could be left locked by a panicking context.
+    pub fn add_sink(&self, writer: SinkWriter, panic_safe: bool) {
+        let index = self.sink_count.fetch_add(1, Ordering::AcqRel);
+        assert!(index < MAX_SINKS, "Cannot register more than {MAX_SINKS} log sinks");
+        self.sinks[index].call_once(|| Sink { writer, panic_safe });
+    }
+
+    /// Only write to panic-safe sinks from now on, to avoid re-entering a sink that may have
+    /// been locked by the panicking context.
+    pub fn enter_panic_mode(&self) {
+        self.panicking.store(true, Ordering::Release);
+    }
+
+    /// Write the formatted message to each registered sink.
+    fn dispatch(&self, message: &str) {
+        let count = self.sink_count.load(Ordering::Acquire).min(MAX_SINKS);
+        let panicking = self.panicking.load(Ordering::Acquire);
+
+        for sink in self.sinks[..count].iter().filter_map(Once::get) {
+            if panicking && !sink.panic_safe {
+                continue;
+            }
+            let mut writer = match sink.writer.try_lock() {
+                Some(writer) => writer,
+                // The panicking context may hold the lock and never release it. Taking it over
+                // may interleave the output, but never loses the panic message.
+                None if panicking => unsafe {
+                    sink.writer.force_unlock();
+                    sink.writer.lock()
+                },
+                None => sink.writer.lock(),
+            };
+            let _ = writer.write_str(message);
+        }
     }
 
     pub fn log(&self, level: LogLevel, message: &str) {
-        if level < self.level {
+        self.log_args(level, format_args!("{}", message));
+    }
+
+    pub fn log_args(&self, level: LogLevel, message: fmt::Arguments) {
+        if level < self.level() {
             return;
         }
 
-        let mut buffer = [0; 1024];
-        let mut writer = BufferWriter::new(&mut buffer);
- 
-        let _ = writeln!(writer, "[{:#}]: {}", level, message);
-        tty::TERMINAL.get().unwrap().lock().write_str(writer.as_str());
+        let mut writer = FmtBuf::<LINE_SIZE>::new();
+
+        let _ = writeln!(writer, "[{}] [{:#}]: {}", Timestamp, level, message);
+        self.dispatch(writer.as_str());
+        if writer.truncated() {
+            self.dispatch("\n");
+        }
     }
 
-    pub fn log_args(&self, level: LogLevel, message: fmt::Arguments) {
-        if level < self.level {
+    /// Log `message` along with the source location it originates from.
+    pub fn log_args_at(&self, level: LogLevel, file: &str, line: u32, message: fmt::Arguments) {
+        if level < self.level() {
             return;
         }
 
-        let mut buffer = [0; 1024];
-        let mut writer = BufferWriter::new(&mut buffer);
- 
-        let _ = writeln!(writer, "[{:#}]: {}", level, message);
-        tty::TERMINAL.get().unwrap().lock().write_str(writer.as_str());
+        let mut writer = FmtBuf::<LINE_SIZE>::new();
+
+        let _ = writeln!(
+            writer,
+            "[{}] [{:#} {}:{}]: {}",
+            Timestamp, level, file, line, message
+        );
+        self.dispatch(writer.as_str());
+        if writer.truncated() {
+            self.dispatch("\n");
+        }
     }
 
     pub fn debug(&self, message: &str) {
@@ -109,31 +243,56 @@ impl Logger {
 
 macro_rules! debug {
     ($($arg:tt)*) => {{
-        $crate::LOGGER.debug_args(format_args!($($arg)*));
+        $crate::LOGGER.log_args_at(
+            $crate::terminal::logger::LogLevel::Debug,
+            file!(),
+            line!(),
+            format_args!($($arg)*),
+        );
     }};
 }
 
 macro_rules! info {
     ($($arg:tt)*) => {{
-        $crate::LOGGER.info_args(format_args!($($arg)*));
+        $crate::LOGGER.log_args_at(
+            $crate::terminal::logger::LogLevel::Info,
+            file!(),
+            line!(),
+            format_args!($($arg)*),
+        );
     }};
 }
 
 macro_rules! warning {
     ($($arg:tt)*) => {{
-        $crate::LOGGER.warn_args(format_args!($($arg)*));
+        $crate::LOGGER.log_args_at(
+            $crate::terminal::logger::LogLevel::Warn,
+            file!(),
+            line!(),
+            format_args!($($arg)*),
+        );
     }};
 }
 
 macro_rules! error {
     ($($arg:tt)*) => {{
-        $crate::LOGGER.error_args(format_args!($($arg)*));
+        $crate::LOGGER.log_args_at(
+            $crate::terminal::logger::LogLevel::Error,
+            file!(),
+            line!(),
+            format_args!($($arg)*),
+        );
     }};
 }
 
 macro_rules! critical {
     ($($arg:tt)*) => {{
-        $crate::LOGGER.critical_args(format_args!($($arg)*));
+        $crate::LOGGER.log_args_at(
+            $crate::terminal::logger::LogLevel::Critical,
+            file!(),
+            line!(),
+            format_args!($($arg)*),
+        );
     }};
 }
 
@@ -1,6 +1,7 @@
 mod themes;
 mod ansi;
-mod font;
+pub(crate) mod font;
 
 pub mod tty;
 pub mod logger;
+pub mod line_editor;
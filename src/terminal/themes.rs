@@ -1,23 +1,63 @@
 use crate::drivers::framebuffer::RGB;
 
+#[derive(Clone, Copy)]
 pub(super) struct Theme {
+    pub(super) name: &'static str,
     pub(super) foreground: RGB,
     pub(super) background: RGB,
     pub(super) selection_foreground: RGB,
     pub(super) selection_background: RGB,
     pub(super) cursor: RGB,
-    pub(super) cursor_text_color: RGB,
     pub(super) ansi_colors: [RGB; 16],
 }
 
 impl Theme {
+    /// Built-in themes, in the order they are cycled through.
+    pub(super) const BUILTIN: &[Theme] = &[
+        Self::GRUVBOX,
+        Self::CATPPUCCIN,
+        Self::NORD,
+        Self::DRACULA,
+        Self::SOLARIZED_DARK,
+    ];
+
+    /// Returns the built-in theme called `name`, ignoring case.
+    pub(super) fn by_name(name: &str) -> Option<Theme> {
+        Self::BUILTIN
+            .iter()
+            .find(|theme| theme.name.eq_ignore_ascii_case(name))
+            .copied()
+    }
+
+    /// Returns the color of the text under the cursor, whichever of the foreground and background
+    /// contrasts the most with the cursor.
+    pub(super) fn cursor_text_color(&self) -> RGB {
+        let cursor = self.cursor.luminance();
+        if cursor.abs_diff(self.foreground.luminance())
+            > cursor.abs_diff(self.background.luminance())
+        {
+            self.foreground
+        } else {
+            self.background
+        }
+    }
+
+    /// Returns the built-in theme following this one, wrapping around.
+    pub(super) fn next(&self) -> Theme {
+        let index = Self::BUILTIN
+            .iter()
+            .position(|theme| theme.name == self.name)
+            .map_or(0, |index| (index + 1) % Self::BUILTIN.len());
+        Self::BUILTIN[index]
+    }
+
     pub(super) const CATPPUCCIN: Theme = Self {
+        name: "catppuccin",
         foreground: RGB::from_hex(0xcdd6f4),
         background: RGB::from_hex(0x1e1e2e),
         selection_foreground: RGB::from_hex(0x1e1e2e),
         selection_background: RGB::from_hex(0xf5e0dc),
         cursor: RGB::from_hex(0xf5e0dc),
-        cursor_text_color: RGB::from_hex(0x1e1e2e),
         ansi_colors: [
             RGB::from_hex(0x45475a),
             RGB::from_hex(0xf38ba8),
@@ -39,12 +79,12 @@ impl Theme {
     };
 
     pub(super) const GRUVBOX: Theme = Self {
+        name: "gruvbox",
         foreground: RGB::from_hex(0xebdbb2),
         background: RGB::from_hex(0x282828),
         selection_foreground: RGB::from_hex(0x928374),
         selection_background: RGB::from_hex(0xebdbb2),
         cursor: RGB::from_hex(0x928374),
-        cursor_text_color: RGB::from_hex(0x282828),
         ansi_colors: [
             RGB::from_hex(0x665c54),
             RGB::from_hex(0xcc241d),
@@ -64,6 +104,87 @@ impl Theme {
             RGB::from_hex(0xbdae93),
         ],
     };
+
+    pub(super) const NORD: Theme = Self {
+        name: "nord",
+        foreground: RGB::from_hex(0xd8dee9),
+        background: RGB::from_hex(0x2e3440),
+        selection_foreground: RGB::from_hex(0xd8dee9),
+        selection_background: RGB::from_hex(0x434c5e),
+        cursor: RGB::from_hex(0xd8dee9),
+        ansi_colors: [
+            RGB::from_hex(0x3b4252),
+            RGB::from_hex(0xbf616a),
+            RGB::from_hex(0xa3be8c),
+            RGB::from_hex(0xebcb8b),
+            RGB::from_hex(0x81a1c1),
+            RGB::from_hex(0xb48ead),
+            RGB::from_hex(0x88c0d0),
+            RGB::from_hex(0xe5e9f0),
+            RGB::from_hex(0x4c566a),
+            RGB::from_hex(0xbf616a),
+            RGB::from_hex(0xa3be8c),
+            RGB::from_hex(0xebcb8b),
+            RGB::from_hex(0x81a1c1),
+            RGB::from_hex(0xb48ead),
+            RGB::from_hex(0x8fbcbb),
+            RGB::from_hex(0xeceff4),
+        ],
+    };
+
+    pub(super) const DRACULA: Theme = Self {
+        name: "dracula",
+        foreground: RGB::from_hex(0xf8f8f2),
+        background: RGB::from_hex(0x282a36),
+        selection_foreground: RGB::from_hex(0xffffff),
+        selection_background: RGB::from_hex(0x44475a),
+        cursor: RGB::from_hex(0xf8f8f2),
+        ansi_colors: [
+            RGB::from_hex(0x21222c),
+            RGB::from_hex(0xff5555),
+            RGB::from_hex(0x50fa7b),
+            RGB::from_hex(0xf1fa8c),
+            RGB::from_hex(0xbd93f9),
+            RGB::from_hex(0xff79c6),
+            RGB::from_hex(0x8be9fd),
+            RGB::from_hex(0xf8f8f2),
+            RGB::from_hex(0x6272a4),
+            RGB::from_hex(0xff6e6e),
+            RGB::from_hex(0x69ff94),
+            RGB::from_hex(0xffffa5),
+            RGB::from_hex(0xd6acff),
+            RGB::from_hex(0xff92df),
+            RGB::from_hex(0xa4ffff),
+            RGB::from_hex(0xffffff),
+        ],
+    };
+
+    pub(super) const SOLARIZED_DARK: Theme = Self {
+        name: "solarized-dark",
+        foreground: RGB::from_hex(0x839496),
+        background: RGB::from_hex(0x002b36),
+        selection_foreground: RGB::from_hex(0x93a1a1),
+        selection_background: RGB::from_hex(0x073642),
+        cursor: RGB::from_hex(0x93a1a1),
+        ansi_colors: [
+            RGB::from_hex(0x073642),
+            RGB::from_hex(0xdc322f),
+            RGB::from_hex(0x859900),
+            RGB::from_hex(0xb58900),
+            RGB::from_hex(0x268bd2),
+            RGB::from_hex(0xd33682),
+            RGB::from_hex(0x2aa198),
+            RGB::from_hex(0xeee8d5),
+            RGB::from_hex(0x002b36),
+            RGB::from_hex(0xcb4b16),
+            RGB::from_hex(0x586e75),
+            RGB::from_hex(0x657b83),
+            RGB::from_hex(0x839496),
+            RGB::from_hex(0x6c71c4),
+            RGB::from_hex(0x93a1a1),
+            RGB::from_hex(0xfdf6e3),
+        ],
+    };
 }
 
 impl Default for Theme {
@@ -71,3 +192,24 @@ impl Default for Theme {
         Self::GRUVBOX
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test_case]
+    fn builtin_themes_are_complete() {
+        for theme in Theme::BUILTIN {
+            assert_eq!(theme.ansi_colors.len(), 16);
+            assert!(
+                theme
+                    .ansi_colors
+                    .iter()
+                    .any(|color| *color != theme.ansi_colors[0]),
+                "The ANSI colors of {} are not populated",
+                theme.name
+            );
+            assert!(theme.foreground != theme.background);
+        }
+    }
+}
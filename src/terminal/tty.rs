@@ -1,48 +1,150 @@
-use core::{alloc::Layout, fmt, ptr, slice};
+use core::{
+    alloc::Layout,
+    fmt::{self, Write},
+    ptr, slice,
+    sync::atomic::{AtomicBool, Ordering},
+};
 
 use spin::{Mutex, Once};
 
 use crate::{
-    drivers::framebuffer::{self, RGB},
+    cpu::{interrupts, time},
+    drivers::{
+        framebuffer::{self, Framebuffer, RGB},
+        keyboard::{self, KeyCode, KeyEvent},
+        serial,
+    },
     memory::{VirtualAddress, frame_allocator},
-    terminal::{ansi::*, font, themes::Theme},
+    terminal::{ansi::*, font, logger, themes::Theme},
+    util::FmtBuf,
 };
 
 const HORIZONTAL_MARGIN: usize = 20;
 const VERTICAL_MARGIN: usize = 20;
+/// Distance of the underline from the bottom of a cell, in pixels.
+const UNDERLINE_OFFSET: usize = 2;
+
+/// DEC private mode controlling the visibility of the cursor (DECTCEM).
+const SHOW_CURSOR_MODE: u16 = 25;
+/// DEC private mode switching to the alternate screen, saving the cursor beforehand.
+const ALTERNATE_SCREEN_MODE: u16 = 1049;
+/// Number of timer ticks between two blinks of the cursor.
+const CURSOR_BLINK_TICKS: u64 = time::TIMER_FREQUENCY / 2;
+/// Columns between two tab stops when the terminal is created.
+const DEFAULT_TAB_WIDTH: usize = 8;
+/// Number of columns that can hold a tab stop, tabs past the last one go to the end of the line.
+const MAX_TAB_STOP_COLUMNS: usize = 512;
+/// Number of screen rows tracked individually by [`DirtyRows`], a terminal with more rows is
+/// fully redrawn whenever a row past this count changes.
+const MAX_DIRTY_ROWS: usize = 256;
+/// Function key cycling through the built-in themes.
+const THEME_CYCLE_KEY: u8 = 12;
+/// Function key lowering the level of the global logger.
+const LOG_LEVEL_DOWN_KEY: u8 = 1;
+/// Function key raising the level of the global logger.
+const LOG_LEVEL_UP_KEY: u8 = 2;
+/// Function key logging how many times each interrupt vector fired.
+const INTERRUPT_STATS_KEY: u8 = 3;
 
 pub static TERMINAL: Once<Mutex<Terminal>> = Once::new();
+/// Log sink writing to the [`TERMINAL`] through [`TerminalStdin`], so that the replies to the
+/// queries found in log messages reach the keyboard input too.
+static TERMINAL_SINK: Mutex<TerminalStdin> = Mutex::new(TerminalStdin);
+/// Whether [`LOG_LEVEL_DOWN_KEY`], [`LOG_LEVEL_UP_KEY`] and [`INTERRUPT_STATS_KEY`] are held down.
+static DEBUG_KEYS_HELD: [AtomicBool; 3] = [const { AtomicBool::new(false) }; 3];
 
 pub fn init() {
     TERMINAL.call_once(|| Mutex::new(Terminal::new()));
+    logger::add_sink(&TERMINAL_SINK, false);
+    time::on_tick(blink_cursor);
+}
+
+/// Switch the terminal to the built-in theme called `name`. Returns false if there is no such
+/// theme.
+pub fn set_theme(name: &str) -> bool {
+    let Some(theme) = Theme::by_name(name) else {
+        return false;
+    };
+    if let Some(terminal) = TERMINAL.get() {
+        terminal.lock().set_theme(theme);
+    }
+    true
+}
+
+/// Forward `event` to the terminal for scrollback navigation and theme switching, unless it is a
+/// debug key.
+pub fn handle_key_event(event: KeyEvent) {
+    if handle_debug_key(event) {
+        return;
+    }
+    if let Some(terminal) = TERMINAL.get() {
+        terminal.lock().handle_key_event(event);
+    }
+}
+
+/// Lower the level of the global logger with F1 and raise it with F2, printing the new level, and
+/// log the interrupt statistics with F3. Each press only acts once, even though held keys repeat.
+/// Returns whether `event` was handled.
+pub fn handle_debug_key(event: KeyEvent) -> bool {
+    let (held, action): (_, fn()) = match event.key {
+        KeyCode::Function(LOG_LEVEL_DOWN_KEY) => (&DEBUG_KEYS_HELD[0], || step_log_level(false)),
+        KeyCode::Function(LOG_LEVEL_UP_KEY) => (&DEBUG_KEYS_HELD[1], || step_log_level(true)),
+        KeyCode::Function(INTERRUPT_STATS_KEY) => (&DEBUG_KEYS_HELD[2], interrupts::log_stats),
+        _ => return false,
+    };
+    if !held.swap(event.pressed, Ordering::Relaxed) && event.pressed {
+        action();
+    }
+    true
 }
 
-pub struct BufferWriter<'buf> {
-    buffer: &'buf mut [u8],
-    cursor: usize,
+/// Raise or lower the level of the global logger by one, printing the new level.
+fn step_log_level(raise: bool) {
+    let level = crate::LOGGER.level();
+    let level = if raise { level.higher() } else { level.lower() };
+    crate::LOGGER.set_level(level);
+    let _ = writeln!(TerminalStdin::new(), "Log level set to {:#}", level);
 }
 
-impl<'buf> BufferWriter<'buf> {
-    pub fn new(buffer: &'buf mut [u8]) -> Self {
-        Self { buffer, cursor: 0 }
+/// Timer callback toggling the cursor every [`CURSOR_BLINK_TICKS`]. The blink is skipped if the
+/// terminal is in use, to avoid deadlocking with the interrupted code.
+fn blink_cursor(ticks: u64) {
+    if !ticks.is_multiple_of(CURSOR_BLINK_TICKS) {
+        return;
+    }
+
+    if let Some(mut terminal) = TERMINAL.get().and_then(Mutex::try_lock) {
+        terminal.toggle_cursor_blink();
     }
+}
+
+/// Writer forwarding text to the global [`TERMINAL`], then the replies to the queries found in
+/// the text to the keyboard input. Text written before the terminal is initialized is sent to
+/// the serial port instead.
+#[derive(Default)]
+pub struct TerminalStdin;
 
-    pub fn as_str(&self) -> &str {
-        unsafe { core::str::from_utf8_unchecked(&self.buffer[..self.cursor]) }
+impl TerminalStdin {
+    pub fn new() -> Self {
+        Self
     }
 }
 
-impl<'buf> fmt::Write for BufferWriter<'buf> {
+impl fmt::Write for TerminalStdin {
     fn write_str(&mut self, s: &str) -> fmt::Result {
-        let bytes = s.as_bytes();
-        let remaining_space = self.buffer.len() - self.cursor;
-        if remaining_space < bytes.len() {
-            self.buffer[self.cursor..].copy_from_slice(&bytes[..remaining_space]);
-            Ok(())
-        } else {
-            self.buffer[self.cursor..self.cursor + bytes.len()].copy_from_slice(bytes);
-            self.cursor += bytes.len();
-            Ok(())
+        match TERMINAL.get() {
+            Some(terminal) => {
+                let mut terminal = terminal.lock();
+                let result = terminal.write_str(s);
+                let reply = core::mem::take(&mut terminal.pending_reply);
+                // The keyboard input is locked by readers, don't hold the terminal meanwhile.
+                drop(terminal);
+                if !reply.as_str().is_empty() {
+                    keyboard::inject_input(reply.as_str());
+                }
+                result
+            }
+            None => serial::com1().lock().write_str(s),
         }
     }
 }
@@ -65,10 +167,14 @@ impl Default for Pos {
     }
 }
 
-#[derive(Debug, Clone, Copy)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
 struct Style {
     foreground: AnsiColor,
     background: AnsiColor,
+    bold: bool,
+    dim: bool,
+    underline: bool,
+    reverse: bool,
 }
 
 impl Default for Style {
@@ -76,7 +182,109 @@ impl Default for Style {
         Self {
             foreground: AnsiColor::DefaultForeground,
             background: AnsiColor::DefaultBackground,
+            bold: false,
+            dim: false,
+            underline: false,
+            reverse: false,
+        }
+    }
+}
+
+/// State of the primary screen, saved while the alternate screen is active.
+#[derive(Debug, Clone, Copy)]
+struct SavedScreen {
+    cursor: Pos,
+    style: Style,
+    scroll: usize,
+}
+
+/// Columns at which horizontal tabs stop.
+struct TabStops {
+    stops: [bool; MAX_TAB_STOP_COLUMNS],
+}
+
+impl TabStops {
+    /// Tab stops every `width` columns.
+    fn every(width: usize) -> Self {
+        let mut stops = [false; MAX_TAB_STOP_COLUMNS];
+        for column in (width..MAX_TAB_STOP_COLUMNS).step_by(width.max(1)) {
+            stops[column] = true;
+        }
+        Self { stops }
+    }
+
+    fn set(&mut self, column: usize, enabled: bool) {
+        if let Some(stop) = self.stops.get_mut(column) {
+            *stop = enabled;
+        }
+    }
+
+    fn clear_all(&mut self) {
+        self.stops.fill(false);
+    }
+
+    /// Returns the first tab stop after `column`, if any.
+    fn next(&self, column: usize) -> Option<usize> {
+        let start = (column + 1).min(MAX_TAB_STOP_COLUMNS);
+        self.stops[start..]
+            .iter()
+            .position(|&stop| stop)
+            .map(|offset| start + offset)
+    }
+}
+
+/// Set of screen rows to redraw on the next [`Terminal::flush_dirty`].
+struct DirtyRows {
+    rows: [u64; MAX_DIRTY_ROWS / u64::BITS as usize],
+    /// The whole screen must be redrawn.
+    all: bool,
+}
+
+impl DirtyRows {
+    const fn new() -> Self {
+        Self {
+            rows: [0; MAX_DIRTY_ROWS / u64::BITS as usize],
+            all: false,
+        }
+    }
+
+    fn mark(&mut self, row: usize) {
+        if row >= MAX_DIRTY_ROWS {
+            self.all = true;
+            return;
+        }
+        self.rows[row / u64::BITS as usize] |= 1 << (row % u64::BITS as usize);
+    }
+
+    fn mark_all(&mut self) {
+        self.all = true;
+    }
+
+    fn contains(&self, row: usize) -> bool {
+        self.rows[row / u64::BITS as usize] & (1 << (row % u64::BITS as usize)) != 0
+    }
+
+    fn is_empty(&self) -> bool {
+        !self.all && self.rows.iter().all(|&word| word == 0)
+    }
+
+    /// Returns the dirty rows in increasing order.
+    fn iter(&self) -> impl Iterator<Item = usize> + '_ {
+        (0..MAX_DIRTY_ROWS).filter(|&row| self.contains(row))
+    }
+
+    /// Move the dirty rows up by `delta`, following the content of the screen being scrolled.
+    fn scroll(&mut self, delta: usize) {
+        let mut scrolled = Self::new();
+        scrolled.all = self.all;
+        for row in self.iter().filter(|&row| row >= delta) {
+            scrolled.mark(row - delta);
         }
+        *self = scrolled;
+    }
+
+    fn clear(&mut self) {
+        *self = Self::new();
     }
 }
 
@@ -90,9 +298,24 @@ pub struct Terminal<'buf> {
     width: usize,
     height: usize,
     cursor: Pos,
+    cursor_visible: bool,
+    cursor_blink_on: bool,
     scroll: usize,
+    /// Scroll to restore once the user stops browsing the scrollback, if they are.
+    scrollback_origin: Option<usize>,
+    /// Screen rows, both included, to which scrolling is restricted if set.
+    scroll_region: Option<(usize, usize)>,
     selection: Option<Selection>,
+    /// Rows changed since the screen was last drawn.
+    dirty: DirtyRows,
+    tab_stops: TabStops,
     buffer: TerminalBuffer<'buf>,
+    /// Buffer swapped with `buffer` while the alternate screen is active.
+    alternate_buffer: TerminalBuffer<'buf>,
+    /// State of the primary screen, set while the alternate screen is active.
+    saved_screen: Option<SavedScreen>,
+    /// Replies to status reports, injected into the keyboard input once the terminal is unlocked.
+    pending_reply: FmtBuf<32>,
     ansi_handler: AnsiHandler,
     style: Style,
     theme: Theme,
@@ -108,9 +331,18 @@ impl<'buf> Terminal<'buf> {
             width,
             height,
             cursor: Pos::origin(),
+            cursor_visible: true,
+            cursor_blink_on: true,
             scroll: 0,
+            scrollback_origin: None,
+            scroll_region: None,
             selection: None,
+            dirty: DirtyRows::new(),
+            tab_stops: TabStops::every(DEFAULT_TAB_WIDTH),
             buffer: TerminalBuffer::new(height * 30, width),
+            alternate_buffer: TerminalBuffer::new(height, width),
+            saved_screen: None,
+            pending_reply: FmtBuf::new(),
             ansi_handler: AnsiHandler::new(),
             style: Style::default(),
             theme: Theme::default(),
@@ -124,6 +356,7 @@ impl<'buf> Terminal<'buf> {
     /// Manages the handling of ANSI sequences inside the input and adds regular text to the [`TerminalBuffer`]
     fn push_input(&mut self, input: impl IntoIterator<Item = char>) {
         let mut iterator = input.into_iter();
+        self.leave_scrollback();
 
         if self.ansi_handler.is_active() {
             self.parse_ansi(iterator.by_ref());
@@ -133,7 +366,9 @@ impl<'buf> Terminal<'buf> {
         while let Some(ch) = element {
             match ch {
                 '\n' => self.jump_line(),
-                '\t' => self.send_to_buffer("    ".chars()),
+                '\r' => self.carriage_return(),
+                '\x08' => self.backspace(),
+                '\t' => self.horizontal_tab(1),
                 '\x1b' => self.parse_ansi(iterator.by_ref()),
                 _ => self.send_char_to_buffer(ch),
             }
@@ -141,7 +376,8 @@ impl<'buf> Terminal<'buf> {
             element = iterator.next();
         }
 
-        self.line_draw(self.cursor.line);
+        self.mark_dirty(self.cursor.line);
+        self.flush_dirty();
     }
 
     /// Start or continue parsing of an ANSI sequence using the ANSI handler.
@@ -182,35 +418,124 @@ impl<'buf> Terminal<'buf> {
     /// for the current line is reached. Ensures the cursor is always in view by adjusting the
     /// scroll.
     fn advance_cursor_wrapping(&mut self, len: usize) {
+        let old_line = self.cursor.line;
+        self.mark_dirty(old_line);
         let new_col_with_overflow = self.cursor.column + len;
         self.cursor.column = (new_col_with_overflow) % self.buffer.max_columns;
         let cursor_delta = (new_col_with_overflow) / self.buffer.max_columns;
+        if let Some(region) = self.scroll_region {
+            for _ in 0..cursor_delta {
+                self.region_line_feed(region);
+            }
+            return;
+        }
         self.cursor.line += cursor_delta;
 
-        if self.cursor.line > self.scroll + self.height {
-            let old_scroll = self.scroll;
-            self.scroll = self.cursor.line - self.height;
-            let scroll_delta = self.scroll - old_scroll;
-            if scroll_delta != 0 {
-                self.scroll_framebuffer(scroll_delta, false);
-                framebuffer::driver().device().refresh();
-            }
+        self.scroll_to_cursor();
+
+        for line in old_line..=self.cursor.line {
+            self.mark_dirty(line);
+        }
+    }
+
+    /// Scroll the view down just enough for the cursor to be on its last visible line, if it is
+    /// below it.
+    fn scroll_to_cursor(&mut self) {
+        let last_visible_line = self.last_visible_line();
+        if self.cursor.line > last_visible_line {
+            self.scroll_view_down(self.cursor.line - last_visible_line);
+        }
+    }
+
+    /// Returns the index of the bottom line of the view.
+    fn last_visible_line(&self) -> usize {
+        self.scroll + self.height - 1
+    }
+
+    /// Scroll the view down by `delta` lines. The pixels of the lines that stay visible are moved
+    /// instead of redrawing the lines, only the lines scrolled into view are marked dirty.
+    fn scroll_view_down(&mut self, delta: usize) {
+        self.scroll += delta;
+        if delta > self.height {
+            self.dirty.mark_all();
+            return;
         }
 
-        if cursor_delta != 0 {
-            self.line_draw(self.cursor.line - cursor_delta);
+        self.scroll_framebuffer(delta);
+        self.dirty.scroll(delta);
+        for line in self.last_visible_line() + 1 - delta..=self.last_visible_line() {
+            self.mark_dirty(line);
         }
     }
 
     /// Skips a line. Corresponds to the typical `'\n'` behavior.
     fn jump_line(&mut self) {
+        self.mark_dirty(self.cursor.line);
         self.cursor.column = 0;
+        if let Some(region) = self.scroll_region {
+            self.region_line_feed(region);
+            return;
+        }
+
         self.cursor.line += 1;
-        if self.cursor.line - self.scroll > self.height {
-            self.scroll += 1;
-            self.scroll_framebuffer(1, false);
+        self.scroll_to_cursor();
+        self.mark_dirty(self.cursor.line);
+    }
+
+    /// Moves the cursor to the next line while a scroll region is set. At the bottom of the
+    /// region, the lines of the region scroll up instead and the lines outside of it stay put.
+    fn region_line_feed(&mut self, (top, bottom): (usize, usize)) {
+        let row = self.cursor.line.saturating_sub(self.scroll);
+        if row == bottom {
+            let (first, last) = (self.scroll + top, self.scroll + bottom);
+            self.buffer.delete_lines(first, last, 1);
+            for line in first..=last {
+                self.mark_dirty(line);
+            }
+        } else if row + 1 < self.height {
+            self.mark_dirty(self.cursor.line);
+            self.cursor.line += 1;
+            self.mark_dirty(self.cursor.line);
+        }
+    }
+
+    /// Moves the cursor to the start of the current line. Corresponds to the typical `'\r'`
+    /// behavior.
+    fn carriage_return(&mut self) {
+        self.cursor.column = 0;
+        self.mark_dirty(self.cursor.line);
+    }
+
+    /// Moves the cursor to the `count`th next tab stop without writing any cell, or to the end of
+    /// the line if there are not enough tab stops. Corresponds to the typical `'\t'` behavior.
+    fn horizontal_tab(&mut self, count: usize) {
+        let last_column = self.buffer.max_columns - 1;
+        for _ in 0..count {
+            self.cursor.column = self
+                .tab_stops
+                .next(self.cursor.column)
+                .map_or(last_column, |stop| stop.min(last_column));
         }
-        self.line_draw(self.cursor.line - 1);
+        self.mark_dirty(self.cursor.line);
+    }
+
+    /// Replace the tab stops with stops every `width` columns.
+    pub fn set_tab_width(&mut self, width: usize) {
+        self.tab_stops = TabStops::every(width);
+    }
+
+    /// Moves the cursor back by one column and clears the cell it lands on, if any.
+    /// Corresponds to a destructive `'\x08'`.
+    fn backspace(&mut self) {
+        if self.cursor.column == 0 {
+            return;
+        }
+
+        self.cursor.column -= 1;
+        let cell = self.cursor.line * self.buffer.max_columns + self.cursor.column;
+        self.buffer.reserve_cells(cell + 1);
+        self.buffer.clear_range(cell, 1);
+        self.mark_dirty(self.cursor.line);
     }
 
     /// Executes the provided ANSI `command`
@@ -228,12 +553,44 @@ impl<'buf> Terminal<'buf> {
             AnsiCommand::EraseDisplay {
                 mode,
                 preserve_offscreen,
-            } => todo!(),
-            AnsiCommand::EraseLine(erase_mode) => todo!(),
+            } => self.erase_display(mode, preserve_offscreen),
+            AnsiCommand::EraseLine(erase_mode) => self.erase_line(erase_mode),
+            AnsiCommand::SetTabStop => self.tab_stops.set(self.cursor.column, true),
+            AnsiCommand::ClearTabStop { all: true } => self.tab_stops.clear_all(),
+            AnsiCommand::ClearTabStop { all: false } => {
+                self.tab_stops.set(self.cursor.column, false)
+            }
+            AnsiCommand::CursorForwardTab(count) => self.horizontal_tab(count),
+            AnsiCommand::InsertCharacters(count) => {
+                let (line, column) = (self.cursor.line, self.cursor.column);
+                self.buffer.insert_cells(line, column, count);
+                self.mark_dirty(line);
+            }
+            AnsiCommand::DeleteCharacters(count) => {
+                let (line, column) = (self.cursor.line, self.cursor.column);
+                self.buffer.delete_cells(line, column, count);
+                self.mark_dirty(line);
+            }
+            AnsiCommand::InsertLines(count) => self.shift_lines(count, true),
+            AnsiCommand::DeleteLines(count) => self.shift_lines(count, false),
             AnsiCommand::ScrollRelative(delta) => self.scroll_relative(delta),
-            AnsiCommand::SetBackground(ansi_color) => self.set_background(ansi_color),
-            AnsiCommand::SetForeground(ansi_color) => self.set_foreground(ansi_color),
-            AnsiCommand::ResetGraphicRendition => self.reset_style(),
+            AnsiCommand::SetScrollRegion { top, bottom } => self.set_scroll_region(top, bottom),
+            AnsiCommand::SelectGraphicRendition(sequence) => {
+                for effect in sequence.iter() {
+                    self.apply_sgr_effect(effect);
+                }
+            }
+            AnsiCommand::DeviceStatusReport(report) => self.report_status(report),
+            AnsiCommand::SetPrivateMode {
+                mode: SHOW_CURSOR_MODE,
+                enabled,
+            } => self.set_cursor_visible(enabled),
+            AnsiCommand::SetPrivateMode {
+                mode: ALTERNATE_SCREEN_MODE,
+                enabled,
+            } => self.set_alternate_screen(enabled),
+            // Other private modes are not supported.
+            AnsiCommand::SetPrivateMode { .. } => (),
         }
     }
 
@@ -243,11 +600,11 @@ impl<'buf> Terminal<'buf> {
     fn move_cursor_absolute(&mut self, line: usize, column: usize) {
         let old_line = self.cursor.line;
         let line = self.scroll + line;
-        self.cursor.line = line.clamp(self.scroll, self.scroll + self.height);
+        self.cursor.line = line.clamp(self.scroll, self.last_visible_line());
         self.cursor.column = column.clamp(0, self.buffer.get_line_length(self.cursor.line));
 
-        self.line_draw(old_line);
-        self.line_draw(self.cursor.line);
+        self.mark_dirty(old_line);
+        self.mark_dirty(self.cursor.line);
     }
 
     /// Moves the cursor according to the provided deltas.
@@ -266,18 +623,272 @@ impl<'buf> Terminal<'buf> {
             .saturating_add_signed(column_delta)
             .min(self.buffer.get_line_length(self.cursor.line));
 
-        self.line_draw(old_line);
-        self.line_draw(self.cursor.line);
+        self.mark_dirty(old_line);
+        self.mark_dirty(self.cursor.line);
+    }
+
+    /// Answer a Device Status Report by queuing the reply, which [`TerminalStdin`] injects into
+    /// the keyboard input where the program that sent the query reads it. Positions are 1-based,
+    /// like the ones of cursor movements.
+    fn report_status(&mut self, report: StatusReport) {
+        let reply = &mut self.pending_reply;
+        let _ = match report {
+            StatusReport::Status => write!(reply, "\x1b[0n"),
+            StatusReport::CursorPosition => write!(
+                reply,
+                "\x1b[{};{}R",
+                self.cursor.line.saturating_sub(self.scroll) + 1,
+                self.cursor.column + 1
+            ),
+        };
+    }
+
+    /// Show or hide the cursor.
+    fn set_cursor_visible(&mut self, visible: bool) {
+        self.cursor_visible = visible;
+        self.mark_dirty(self.cursor.line);
+    }
+
+    /// Switch to a cleared alternate screen, or back to the primary screen, restoring its
+    /// cursor, style and scrollback as they were before the switch.
+    fn set_alternate_screen(&mut self, enabled: bool) {
+        if enabled == self.saved_screen.is_some() {
+            return;
+        }
+
+        if enabled {
+            self.saved_screen = Some(SavedScreen {
+                cursor: self.cursor,
+                style: self.style,
+                scroll: self.scroll,
+            });
+            core::mem::swap(&mut self.buffer, &mut self.alternate_buffer);
+            self.buffer.clear();
+            self.cursor = Pos::origin();
+            self.scroll = 0;
+        } else if let Some(saved) = self.saved_screen.take() {
+            core::mem::swap(&mut self.buffer, &mut self.alternate_buffer);
+            self.cursor = saved.cursor;
+            self.style = saved.style;
+            self.scroll = saved.scroll;
+        }
+        self.selection = None;
+        self.dirty.mark_all();
+    }
+
+    /// Alternate the cursor between its shown and hidden blink phases.
+    pub fn toggle_cursor_blink(&mut self) {
+        self.cursor_blink_on = !self.cursor_blink_on;
+        self.cursor_draw();
+    }
+
+    /// Returns whether the cursor is currently drawn over its cell.
+    fn cursor_shown(&self) -> bool {
+        self.cursor_visible && self.cursor_blink_on
+    }
+
+    /// Insert `count` blank lines at the cursor if `insert` is set, delete `count` lines at the
+    /// cursor otherwise. Only the lines down to the bottom of the scroll region, or of the screen
+    /// if there is none, are shifted. Nothing happens if the cursor is outside of the region.
+    fn shift_lines(&mut self, count: usize, insert: bool) {
+        let row = self.cursor.line.saturating_sub(self.scroll);
+        let (top, bottom) = self.scroll_region.unwrap_or((0, self.height - 1));
+        if !(top..=bottom).contains(&row) {
+            return;
+        }
+
+        let (first, last) = (self.cursor.line, self.scroll + bottom);
+        if insert {
+            self.buffer.insert_lines(first, last, count);
+        } else {
+            self.buffer.delete_lines(first, last, count);
+        }
+        self.cursor.column = 0;
+        for line in first..=last {
+            self.mark_dirty(line);
+        }
+    }
+
+    /// Clears the part of the scroll view selected by `mode`, the cell under the cursor is part of
+    /// both halves. Unless `preserve_offscreen` is set, the lines above the view are cleared too.
+    fn erase_display(&mut self, mode: EraseMode, preserve_offscreen: bool) {
+        let columns = self.buffer.max_columns;
+        let view_start = self.scroll * columns;
+        let view_end = (self.scroll + self.height) * columns;
+        // The cursor may sit past the view, e.g. on a pending wrap, keep the range inside it.
+        let cursor =
+            (self.cursor.line * columns + self.cursor.column).clamp(view_start, view_end - 1);
+        self.buffer.reserve_cells(view_end);
+
+        let (start, end) = match mode {
+            EraseMode::AfterCursor => (cursor, view_end),
+            EraseMode::BeforeCursor => (view_start, cursor + 1),
+            EraseMode::All => (view_start, view_end),
+        };
+        self.buffer.clear_range(start, end - start);
+
+        if !preserve_offscreen {
+            self.buffer.clear_range(0, view_start);
+        }
+
+        self.dirty.mark_all();
+    }
+
+    /// Clears the part of the cursor's line selected by `mode`, the cell under the cursor is part
+    /// of both halves.
+    fn erase_line(&mut self, mode: EraseMode) {
+        let columns = self.buffer.max_columns;
+        let line_start = self.cursor.line * columns;
+        self.buffer.reserve_cells(line_start + columns);
+
+        let (start, end) = match mode {
+            EraseMode::AfterCursor => (self.cursor.column, columns),
+            EraseMode::BeforeCursor => (0, (self.cursor.column + 1).min(columns)),
+            EraseMode::All => (0, columns),
+        };
+        self.buffer.clear_range(line_start + start, end - start);
+
+        self.mark_dirty(self.cursor.line);
     }
 
     /// Scrolls downwards by delta if it's positive and upwards by -delta otherwise.
-    /// Ensures the result is within the range of valid lines.
+    /// Ensures the result is within the range of valid lines. With a scroll region, only the
+    /// lines of the region move, blank lines coming in from the edge they moved away from.
     fn scroll_relative(&mut self, delta: isize) {
+        if let Some((top, bottom)) = self.scroll_region {
+            let (first, last) = (self.scroll + top, self.scroll + bottom);
+            if delta > 0 {
+                self.buffer.delete_lines(first, last, delta.unsigned_abs());
+            } else {
+                self.buffer.insert_lines(first, last, delta.unsigned_abs());
+            }
+            for line in first..=last {
+                self.mark_dirty(line);
+            }
+            return;
+        }
+
         self.scroll = self
             .scroll
             .saturating_add_signed(delta)
-            .min(self.buffer.max_lines);
-        self.full_draw();
+            .min(self.buffer.max_lines - self.height);
+        self.dirty.mark_all();
+    }
+
+    /// Restrict scrolling to the screen rows from `top` to `bottom`, defaulting to the last row.
+    /// A region covering the whole screen, or less than two rows once clipped to the screen,
+    /// removes the restriction. Like DECSTBM, this moves the cursor to the top-left corner.
+    fn set_scroll_region(&mut self, top: usize, bottom: Option<usize>) {
+        let last_row = self.height - 1;
+        let bottom = bottom.map_or(last_row, |bottom| bottom.min(last_row));
+
+        self.scroll_region =
+            (top < bottom && (top != 0 || bottom != last_row)).then_some((top, bottom));
+        self.move_cursor_absolute(0, 0);
+    }
+
+    /// Scroll the view with PageUp/PageDown and Shift+Up/Down, and cycle through the built-in
+    /// themes with F12. Any other key producing a character brings the view back to where it was
+    /// before browsing the scrollback.
+    pub fn handle_key_event(&mut self, event: KeyEvent) {
+        if !event.pressed {
+            return;
+        }
+        if event.key == KeyCode::Function(THEME_CYCLE_KEY) {
+            self.set_theme(self.theme.next());
+            return;
+        }
+
+        let page = self.height as isize;
+        let delta = match event.key {
+            KeyCode::PageUp => -page,
+            KeyCode::PageDown => page,
+            KeyCode::Up if event.modifiers.shift() => -1,
+            KeyCode::Down if event.modifiers.shift() => 1,
+            _ => {
+                if event.to_char().is_some() {
+                    self.leave_scrollback();
+                    self.flush_dirty();
+                }
+                return;
+            }
+        };
+
+        self.scrollback_origin.get_or_insert(self.scroll);
+        self.scroll = self
+            .scroll
+            .saturating_add_signed(delta)
+            .min(self.buffer.max_lines - self.height);
+        self.dirty.mark_all();
+        self.flush_dirty();
+    }
+
+    /// Resize the terminal to `width` columns and `height` lines and redraw it. The text of both
+    /// screens is rewrapped to the new width, so lines split when shrinking and wrapped lines
+    /// merge back when growing.
+    pub fn resize(&mut self, width: usize, height: usize) {
+        assert!(
+            width > 0 && height > 0,
+            "The terminal must have at least one cell"
+        );
+
+        let saved_cursor = self
+            .saved_screen
+            .map_or(Pos::origin(), |saved| saved.cursor);
+        let (buffer, cursor) = self
+            .buffer
+            .reflow(width, self.buffer.max_lines, self.cursor);
+        let (alternate_buffer, saved_cursor) =
+            self.alternate_buffer
+                .reflow(width, self.alternate_buffer.max_lines, saved_cursor);
+        self.buffer = buffer;
+        self.alternate_buffer = alternate_buffer;
+
+        self.width = width;
+        self.height = height;
+        self.cursor = cursor;
+        self.scroll = (cursor.line + 1).saturating_sub(height);
+        if let Some(saved) = &mut self.saved_screen {
+            saved.cursor = saved_cursor;
+            saved.scroll = (saved_cursor.line + 1).saturating_sub(height);
+        }
+        self.scroll_region = None;
+        self.scrollback_origin = None;
+        self.selection = None;
+
+        self.dirty.mark_all();
+        self.flush_dirty();
+    }
+
+    /// Use `theme` from now on and redraw the screen with it.
+    pub(super) fn set_theme(&mut self, theme: Theme) {
+        self.theme = theme;
+        self.dirty.mark_all();
+        self.flush_dirty();
+    }
+
+    /// Restore the scroll the view had before browsing the scrollback.
+    fn leave_scrollback(&mut self) {
+        if let Some(scroll) = self.scrollback_origin.take() {
+            self.scroll = scroll;
+            self.dirty.mark_all();
+        }
+    }
+
+    fn apply_sgr_effect(&mut self, effect: SgrEffect) {
+        match effect {
+            SgrEffect::Reset => self.reset_style(),
+            SgrEffect::SetForeground(ansi_color) => self.set_foreground(ansi_color),
+            SgrEffect::SetBackground(ansi_color) => self.set_background(ansi_color),
+            SgrEffect::SetBold(bold) => self.style.bold = bold,
+            SgrEffect::SetDim(dim) => self.style.dim = dim,
+            SgrEffect::SetUnderline(underline) => self.style.underline = underline,
+            SgrEffect::SetReverse(reverse) => self.style.reverse = reverse,
+            SgrEffect::NormalIntensity => {
+                self.style.bold = false;
+                self.style.dim = false;
+            }
+        }
     }
 
     fn set_background(&mut self, color: AnsiColor) {
@@ -288,10 +899,10 @@ impl<'buf> Terminal<'buf> {
         self.style.foreground = color;
     }
 
-    /// Reset the style to the one set in `self.theme`
+    /// Reset the colors, including 256-color and truecolor ones, to the defaults of `self.theme`
+    /// and clear every attribute.
     fn reset_style(&mut self) {
-        self.style.foreground = AnsiColor::DefaultForeground;
-        self.style.background = AnsiColor::DefaultBackground;
+        self.style = Style::default();
     }
 
     /// Convert `ansi_color` to RGB according to the current theme
@@ -304,6 +915,89 @@ impl<'buf> Terminal<'buf> {
         }
     }
 
+    /// Returns the foreground and background colors of a cell drawn with `style`.
+    /// Bold brightens the 8 base colors, dim darkens the foreground by half and reverse swaps both
+    /// colors.
+    fn style_to_rgb(&self, style: Style) -> (RGB, RGB) {
+        let foreground = match style.foreground {
+            AnsiColor::ColorCode(code) if style.bold && code < 8 => AnsiColor::ColorCode(code + 8),
+            color => color,
+        };
+
+        let mut fg_color = self.ansi_to_rgb(foreground);
+        let bg_color = self.ansi_to_rgb(style.background);
+        if style.dim {
+            fg_color = fg_color.scale(0x80);
+        }
+
+        if style.reverse {
+            (bg_color, fg_color)
+        } else {
+            (fg_color, bg_color)
+        }
+    }
+
+    /// Draw `cell` with the provided `(foreground, background)` colors and its top-left corner at
+    /// (`x`, `y`), does not refresh the screen.
+    fn draw_cell(
+        &self,
+        fb: &mut Framebuffer,
+        cell: &TextCell,
+        (fg_color, bg_color): (RGB, RGB),
+        x: usize,
+        y: usize,
+    ) {
+        let raster = font::get_raster(cell.content).unwrap();
+        fb.fill_rect(x, y, font::WIDTH, font::HEIGHT, bg_color);
+
+        for (char_y, char_row) in raster.iter().enumerate() {
+            for (char_x, &alpha) in char_row.iter().enumerate() {
+                if alpha != 0 {
+                    let color = RGB::alpha_blend(fg_color, bg_color, alpha);
+                    fb.set_pixel(char_x + x, char_y + y, color);
+                }
+            }
+        }
+
+        if cell.style.underline {
+            let underline_y = y + font::HEIGHT - UNDERLINE_OFFSET;
+            fb.fill_rect(x, underline_y, font::WIDTH, 1, fg_color);
+        }
+    }
+
+    /// Serialize the visible cells to `out` as UTF-8 text and returns the number of bytes written.
+    ///
+    /// Rows are separated by `'\n'` and their trailing whitespace is trimmed, empty cells before
+    /// the end of a row are written as spaces. The output stops at the last character that fits
+    /// entirely in `out`.
+    pub fn screen_text(&self, out: &mut [u8]) -> usize {
+        let rows = self
+            .buffer
+            .get_view(self.scroll, self.height)
+            .chunks(self.buffer.max_columns);
+
+        let mut written = 0;
+        for (i, row) in rows.enumerate() {
+            let length = row
+                .iter()
+                .rposition(|cell| cell.is_some_and(|cell| !cell.content.is_whitespace()))
+                .map_or(0, |last| last + 1);
+
+            let separator = (i != 0).then_some('\n');
+            let text = row[..length]
+                .iter()
+                .map(|cell| cell.map_or(' ', |cell| cell.content));
+
+            for ch in separator.into_iter().chain(text) {
+                if written + ch.len_utf8() > out.len() {
+                    return written;
+                }
+                written += ch.encode_utf8(&mut out[written..]).len();
+            }
+        }
+        written
+    }
+
     /// Draw the entire scroll view in the framebuffer;
     pub fn full_draw(&self) {
         let rows = self
@@ -315,75 +1009,120 @@ impl<'buf> Terminal<'buf> {
         fb.fill(self.theme.background);
 
         for (logical_y, row) in rows.enumerate() {
-            for (logical_x, cell) in row.iter().flatten().enumerate() {
-                let raster = font::get_raster(cell.content).unwrap();
+            for (logical_x, cell) in row.iter().enumerate() {
+                let Some(cell) = cell else { continue };
                 let visual_x = HORIZONTAL_MARGIN + logical_x * font::WIDTH;
                 let visual_y = VERTICAL_MARGIN + logical_y * font::HEIGHT;
-
-                for (char_y, char_row) in raster.raster().iter().enumerate() {
-                    for (char_x, alpha) in char_row.iter().enumerate() {
-                        let fg_color = self.ansi_to_rgb(cell.style.foreground);
-                        let bg_color = self.ansi_to_rgb(cell.style.background);
-                        let color = RGB::alpha_blend(fg_color, bg_color, *alpha);
-
-                        fb.set_pixel(char_x + visual_x, char_y + visual_y, color);
-                    }
-                }
+                let colors = self.style_to_rgb(cell.style);
+                self.draw_cell(&mut fb, cell, colors, visual_x, visual_y);
             }
         }
+        self.draw_cursor_cell(&mut fb);
         fb.refresh();
     }
 
-    /// Scroll the framebuffer by `scroll_delta` rows and preserves the top margin.
-    /// If `clear_scroll` is set, this also clears the rows that have been scrolled up
-    /// to avoid them being duplicated.
-    pub fn scroll_framebuffer(&self, scroll_delta: usize, clear_scroll: bool) {
+    /// Scroll the framebuffer by `scroll_delta` rows and preserves the top and bottom margins.
+    pub fn scroll_framebuffer(&self, scroll_delta: usize) {
         let mut fb = framebuffer::driver().device();
         let fb_info = framebuffer::driver().info();
+        let background = self.theme.background;
+
+        fb.scroll_up(scroll_delta * font::HEIGHT, background);
+
+        // The top rows of text are scrolled into the top margin, clear both margins.
+        let view_bottom = VERTICAL_MARGIN + self.height * font::HEIGHT;
+        fb.fill_rect(0, 0, fb_info.width(), VERTICAL_MARGIN, background);
+        fb.fill_rect(
+            0,
+            view_bottom,
+            fb_info.width(),
+            fb_info.height() - view_bottom,
+            background,
+        );
+    }
 
-        let screen_rows = scroll_delta * font::HEIGHT;
-        fb.scroll(screen_rows);
-
-        if clear_scroll {
-            let clear_start = fb_info.buffer_len()
-                - fb_info.pitch() * (screen_rows * font::HEIGHT + VERTICAL_MARGIN);
-            fb.partial_fill(clear_start.., self.theme.background);
+    /// Mark `line` to be redrawn by the next [`Self::flush_dirty`], if it is on screen.
+    fn mark_dirty(&mut self, line: usize) {
+        if (self.scroll..=self.last_visible_line()).contains(&line) {
+            self.dirty.mark(line - self.scroll);
         }
+    }
 
-        fb.partial_fill(..fb_info.pitch() * VERTICAL_MARGIN, self.theme.background);
+    /// Redraw the rows changed since the last flush and refresh only these rows of the screen.
+    pub fn flush_dirty(&mut self) {
+        if self.dirty.all {
+            self.full_draw();
+        } else if !self.dirty.is_empty() {
+            let mut fb = framebuffer::driver().device();
+            for row in self.dirty.iter() {
+                self.draw_line(&mut fb, self.scroll + row);
+                fb.refresh_rows(VERTICAL_MARGIN + font::HEIGHT * row, font::HEIGHT);
+            }
+        }
+        self.dirty.clear();
     }
 
-    /// Draw only the specified line, does not refresh the screen
-    pub fn line_draw(&self, line: usize) {
-        if line >= self.buffer.max_lines {
+    /// Draw only the specified line, does not refresh the screen.
+    fn draw_line(&self, fb: &mut Framebuffer, line: usize) {
+        if !(self.scroll..=self.last_visible_line()).contains(&line)
+            || line >= self.buffer.max_lines
+        {
             return;
         }
         let row = self.buffer.get_view(line, 1);
 
-        let mut fb = framebuffer::driver().device();
         let fb_width = fb.width();
         let y_offset = VERTICAL_MARGIN + font::HEIGHT * (line - self.scroll);
         let mut x_offset = HORIZONTAL_MARGIN;
 
-        let line_start = y_offset * fb_width;
-        let line_end = (y_offset + font::HEIGHT) * fb_width;
-        fb.partial_fill(line_start..line_end, self.theme.background);
+        fb.fill_rect(0, y_offset, fb_width, font::HEIGHT, self.theme.background);
+
+        for cell in row {
+            let Some(cell) = cell else {
+                x_offset += font::WIDTH;
+                continue;
+            };
+            let colors = self.style_to_rgb(cell.style);
+            self.draw_cell(fb, cell, colors, x_offset, y_offset);
+            x_offset += font::WIDTH;
+        }
+        if line == self.cursor.line {
+            self.draw_cursor_cell(fb);
+        }
+    }
 
-        for cell in row.iter().flatten() {
-            let raster = font::get_raster(cell.content).unwrap();
+    /// Draw only the cell under the cursor and refresh the rows of the screen it spans.
+    fn cursor_draw(&self) {
+        if !(self.scroll..=self.last_visible_line()).contains(&self.cursor.line) {
+            return;
+        }
 
-            for (char_y, char_row) in raster.raster().iter().enumerate() {
-                for (char_x, alpha) in char_row.iter().enumerate() {
-                    let fg_color = self.ansi_to_rgb(cell.style.foreground);
-                    let bg_color = self.ansi_to_rgb(cell.style.background);
-                    let color = RGB::alpha_blend(fg_color, bg_color, *alpha);
+        let mut fb = framebuffer::driver().device();
+        self.draw_cursor_cell(&mut fb);
+        let y = VERTICAL_MARGIN + (self.cursor.line - self.scroll) * font::HEIGHT;
+        fb.refresh_rows(y, font::HEIGHT);
+    }
 
-                    fb.set_pixel(char_x + x_offset, char_y + y_offset, color);
-                }
-            }
-            x_offset += font::WIDTH;
+    /// Draw the cell under the cursor, using the cursor colors if it is shown. Empty cells, such
+    /// as the ones past the end of a line, are drawn as blanks. Does not refresh the screen.
+    fn draw_cursor_cell(&self, fb: &mut Framebuffer) {
+        if !(self.scroll..=self.last_visible_line()).contains(&self.cursor.line) {
+            return;
         }
-        fb.refresh();
+
+        let cell = self
+            .buffer
+            .get_cell(self.cursor.line, self.cursor.column)
+            .unwrap_or(TextCell::blank());
+        let colors = if self.cursor_shown() {
+            (self.theme.cursor_text_color(), self.theme.cursor)
+        } else {
+            self.style_to_rgb(cell.style)
+        };
+
+        let x = HORIZONTAL_MARGIN + self.cursor.column * font::WIDTH;
+        let y = VERTICAL_MARGIN + (self.cursor.line - self.scroll) * font::HEIGHT;
+        self.draw_cell(fb, &cell, colors, x, y);
     }
 }
 
@@ -405,6 +1144,14 @@ impl TextCell {
     fn empty() -> Option<Self> {
         None
     }
+
+    /// Space with the default style, used to draw empty cells.
+    fn blank() -> Self {
+        Self {
+            style: Style::default(),
+            content: ' ',
+        }
+    }
 }
 
 /// Buffer for a terminal. Owns an array of [`TextCell`]s.
@@ -419,8 +1166,19 @@ struct TerminalBuffer<'txt> {
 
 impl<'txt> TerminalBuffer<'txt> {
     fn new(lines: usize, columns: usize) -> Self {
-        let length = lines * columns;
-        let cells_buffer = unsafe {
+        Self {
+            max_lines: lines,
+            max_columns: columns,
+            buffer: unsafe { Self::allocate_cells(lines * columns) },
+            end_ptr: 0,
+        }
+    }
+
+    /// Allocate a slice of `length` empty cells.
+    ///
+    /// SAFETY: The returned slice must be released with [`frame_allocator::free`] once unused.
+    unsafe fn allocate_cells(length: usize) -> &'txt mut [Option<TextCell>] {
+        unsafe {
             let cells_layout = Layout::array::<Option<TextCell>>(length).unwrap();
             let cells_ptr = frame_allocator::allocate(cells_layout.size())
                 .to_virtual()
@@ -431,14 +1189,58 @@ impl<'txt> TerminalBuffer<'txt> {
             }
 
             slice::from_raw_parts_mut(cells_ptr, length)
-        };
+        }
+    }
 
-        Self {
-            max_lines: lines,
-            max_columns: columns,
-            buffer: cells_buffer,
-            end_ptr: 0,
+    /// Copy the cells to a new buffer of `columns` columns and at least `min_lines` lines,
+    /// rewrapping the lines to the new width. Returns the new buffer and the position `cursor`
+    /// moves to.
+    ///
+    /// The buffer does not record where lines were wrapped, so a line whose last cell is not empty
+    /// is considered to continue on the next one.
+    fn reflow(&self, columns: usize, min_lines: usize, cursor: Pos) -> (Self, Pos) {
+        let last_line = (0..self.max_lines)
+            .rev()
+            .find(|&line| self.get_line_length(line) != 0)
+            .unwrap_or(0)
+            .max(cursor.line);
+
+        let mut reflowed = Self::new(min_lines, columns);
+        let mut new_cursor = Pos::origin();
+        // First line of the current logical line in the new buffer, and offset in that line.
+        let (mut line, mut offset) = (0, 0);
+        for old_line in 0..=last_line {
+            let continued =
+                old_line != last_line && self.get_cell(old_line, self.max_columns - 1).is_some();
+            let length = if continued {
+                self.max_columns
+            } else {
+                self.get_line_length(old_line)
+            };
+
+            if old_line == cursor.line {
+                let cursor_offset = offset + cursor.column;
+                new_cursor = Pos {
+                    line: line + cursor_offset / columns,
+                    column: cursor_offset % columns,
+                };
+            }
+
+            for column in 0..length {
+                let cell_offset = offset + column;
+                let position = (line + cell_offset / columns) * columns + cell_offset % columns;
+                reflowed.reserve_cells(position + 1);
+                reflowed.buffer[position] = self.buffer[old_line * self.max_columns + column];
+            }
+
+            offset += length;
+            if !continued {
+                line += offset.div_ceil(columns).max(1);
+                offset = 0;
+            }
         }
+
+        (reflowed, new_cursor)
     }
 
     /// Write the specified `text` to the buffer using the provided `style` and position. Returns the number of cells occupied by the text.
@@ -451,16 +1253,13 @@ impl<'txt> TerminalBuffer<'txt> {
         let ptr = line * self.max_columns + column;
         let mut offset = 0;
         for ch in text.into_iter() {
-            if ptr + offset + 1 == self.buffer.len() {
-                unsafe { self.grow_buffer() };
-            }
-
+            self.reserve_cells(ptr + offset + 1);
             self.buffer[ptr + offset] = Some(TextCell { style, content: ch });
             offset += 1;
         }
 
         if ptr + offset > self.end_ptr {
-            self.end_ptr = ptr;
+            self.end_ptr = ptr + offset;
         }
 
         offset
@@ -469,11 +1268,16 @@ impl<'txt> TerminalBuffer<'txt> {
     #[inline(always)]
     fn write_char(&mut self, ch: char, line: usize, column: usize, style: Style) {
         let pos = line * self.max_columns + column;
-        if pos + 1 >= self.buffer.len() {
+        self.reserve_cells(pos + 1);
+        self.buffer[pos] = Some(TextCell { style, content: ch });
+    }
+
+    /// Ensure the buffer holds at least `len` cells, growing it as many times as necessary.
+    #[inline(always)]
+    fn reserve_cells(&mut self, len: usize) {
+        while len >= self.buffer.len() {
             unsafe { self.grow_buffer() };
         }
-
-        self.buffer[pos] = Some(TextCell { style, content: ch });
     }
 
     /// Compute the length of the specified line.
@@ -488,6 +1292,14 @@ impl<'txt> TerminalBuffer<'txt> {
         0
     }
 
+    /// Returns the cell at the specified position, if it is not empty.
+    fn get_cell(&self, line: usize, column: usize) -> Option<TextCell> {
+        self.buffer
+            .get(line * self.max_columns + column)
+            .copied()
+            .flatten()
+    }
+
     /// Clear the specified range of cells
     fn clear_range(&mut self, start: usize, len: usize) {
         for i in start..start + len {
@@ -495,29 +1307,232 @@ impl<'txt> TerminalBuffer<'txt> {
         }
     }
 
+    /// Shift the cells of `line` from `column` right by `count`. The cells pushed past the end of
+    /// the line are dropped and the vacated ones are cleared.
+    fn insert_cells(&mut self, line: usize, column: usize, count: usize) {
+        let (start, end) = (
+            line * self.max_columns + column,
+            (line + 1) * self.max_columns,
+        );
+        let count = count.min(end - start);
+        self.reserve_cells(end);
+        self.buffer.copy_within(start..end - count, start + count);
+        self.clear_range(start, count);
+    }
+
+    /// Remove `count` cells of `line` from `column`, shifting the rest of the line left. The
+    /// vacated cells at the end of the line are cleared.
+    fn delete_cells(&mut self, line: usize, column: usize, count: usize) {
+        let (start, end) = (
+            line * self.max_columns + column,
+            (line + 1) * self.max_columns,
+        );
+        let count = count.min(end - start);
+        self.reserve_cells(end);
+        self.buffer.copy_within(start + count..end, start);
+        self.clear_range(end - count, count);
+    }
+
+    /// Shift the lines from `first` to `last` down by `count` lines. The lines pushed past `last`
+    /// are dropped, the vacated ones are cleared and the lines outside of the range are left
+    /// untouched.
+    fn insert_lines(&mut self, first: usize, last: usize, count: usize) {
+        let columns = self.max_columns;
+        let count = count.min(last + 1 - first);
+        self.reserve_cells((last + 1) * columns);
+        self.buffer.copy_within(
+            first * columns..(last + 1 - count) * columns,
+            (first + count) * columns,
+        );
+        self.clear_range(first * columns, count * columns);
+    }
+
+    /// Shift the lines from `first + count` to `last` up by `count` lines, dropping the lines
+    /// they overwrite. The vacated lines are cleared and the lines outside of the range are left
+    /// untouched.
+    fn delete_lines(&mut self, first: usize, last: usize, count: usize) {
+        let columns = self.max_columns;
+        let count = count.min(last + 1 - first);
+        self.reserve_cells((last + 1) * columns);
+        self.buffer.copy_within(
+            (first + count) * columns..(last + 1) * columns,
+            first * columns,
+        );
+        self.clear_range((last + 1 - count) * columns, count * columns);
+    }
+
+    /// Clear every cell of the buffer.
+    fn clear(&mut self) {
+        self.buffer.fill(None);
+        self.end_ptr = 0;
+    }
+
     /// Returns the slice of TextCells between lines `start_line` and `start_line + height`.
     fn get_view(&self, start_line: usize, height: usize) -> &[Option<TextCell>] {
         &self.buffer[start_line * self.max_columns..(start_line + height) * self.max_columns]
     }
 
+    /// Double the number of lines of the buffer, copying the existing lines row by row into a
+    /// new allocation.
     unsafe fn grow_buffer(&mut self) {
+        let new_lines = self.max_lines * 2;
+        let new_buffer = unsafe { Self::allocate_cells(new_lines * self.max_columns) };
+
+        for (line, row) in self.buffer.chunks(self.max_columns).enumerate() {
+            let start = line * self.max_columns;
+            new_buffer[start..start + self.max_columns].copy_from_slice(row);
+        }
+
+        let old_buffer = core::mem::replace(&mut self.buffer, new_buffer);
         unsafe {
-            let old_len = self.buffer.len();
-            let new_len = self.buffer.len() * 2;
-            let cells_layout = Layout::array::<TextCell>(new_len).unwrap();
-            let ptr = frame_allocator::reallocate(
-                VirtualAddress::from_ptr(self.buffer).to_physical(),
-                cells_layout.size(),
-            )
-            .to_virtual()
-            .to_ptr::<Option<TextCell>>();
-
-            for i in old_len..new_len {
-                ptr::write(ptr.add(i), None);
-            }
+            frame_allocator::free(VirtualAddress::from_ptr(old_buffer.as_ptr()).to_physical());
+        }
+        self.max_lines = new_lines;
+    }
+}
+
+impl Drop for TerminalBuffer<'_> {
+    fn drop(&mut self) {
+        unsafe {
+            frame_allocator::free(VirtualAddress::from_ptr(self.buffer.as_ptr()).to_physical());
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Returns the text visible on the screen of `terminal`, serialized in `out`.
+    fn screen_text<'out>(terminal: &Terminal, out: &'out mut [u8]) -> &'out str {
+        let length = terminal.screen_text(out);
+        core::str::from_utf8(&out[..length]).unwrap()
+    }
+
+    #[test_case]
+    fn screen_text_trims_rows_and_fills_gaps() {
+        let mut terminal = Terminal::new();
+        // The tab skips the cells up to the next tab stop without writing them.
+        let _ = write!(terminal, "one  \n\ntwo\tthree");
+
+        let mut out = [0; 1024];
+        let text = screen_text(&terminal, &mut out);
+        let (start, rest) = text.split_at("one\n\ntwo     three".len());
+        assert_eq!(start, "one\n\ntwo     three");
+        assert!(rest.chars().all(|ch| ch == '\n'));
+        assert_eq!(rest.len(), terminal.height - 3);
+    }
+
+    #[test_case]
+    fn status_report_replies_ok() {
+        let mut terminal = Terminal::new();
+        let _ = write!(terminal, "\x1b[5n");
+
+        assert_eq!(terminal.pending_reply.as_str(), "\x1b[0n");
+    }
+
+    #[test_case]
+    fn cursor_position_report_is_one_based() {
+        let mut terminal = Terminal::new();
+        let _ = write!(terminal, "ab\ncd\x1b[6n");
+
+        assert_eq!(terminal.pending_reply.as_str(), "\x1b[2;3R");
+    }
+
+    #[test_case]
+    fn output_in_scroll_region_leaves_header_alone() {
+        let mut terminal = Terminal::new();
+        let _ = writeln!(terminal, "header\x1b[2r");
+        for _ in 0..terminal.height * 2 {
+            let _ = writeln!(terminal, "line");
+        }
+
+        let mut out = [0; 1024];
+        assert_eq!(
+            screen_text(&terminal, &mut out).lines().next(),
+            Some("header")
+        );
+        assert_eq!(terminal.scroll, 0);
+    }
+
+    #[test_case]
+    fn scroll_up_in_scroll_region_leaves_header_alone() {
+        let mut terminal = Terminal::new();
+        let _ = write!(terminal, "header\x1b[2r\nregion\x1b[3S");
+
+        let mut out = [0; 1024];
+        let mut rows = screen_text(&terminal, &mut out).lines();
+        assert_eq!(rows.next(), Some("header"));
+        assert_eq!(rows.nth(3), Some("region"));
+        assert_eq!(terminal.scroll, 0);
+    }
+
+    #[test_case]
+    fn sgr_reset_restores_default_style() {
+        let mut terminal = Terminal::new();
+        let _ = write!(terminal, "\x1b[38;2;255;128;0;1mtext");
+        assert!(terminal.style.bold);
+
+        let _ = write!(terminal, "\x1b[0m");
+        assert_eq!(terminal.style, Style::default());
+    }
+
+    #[test_case]
+    fn buffer_grows_for_text_written_near_its_end() {
+        let mut buffer = TerminalBuffer::new(2, 4);
+        let text = "0123456789abcdefghij";
+        let written = buffer.write_formatted(text.chars(), 1, 2, Style::default());
+
+        assert_eq!(written, text.len());
+        assert!(buffer.end_ptr <= buffer.buffer.len());
+        assert_eq!(buffer.buffer.len(), buffer.max_lines * buffer.max_columns);
+        for (i, ch) in text.chars().enumerate() {
+            let cell = 4 + 2 + i;
+            let cell = buffer.get_cell(cell / 4, cell % 4);
+            assert!(cell.is_some_and(|cell| cell.content == ch));
+        }
+    }
+
+    #[test_case]
+    fn tabs_move_to_the_default_tab_stops() {
+        let mut terminal = Terminal::new();
+        let mut columns = [0; 3];
+        for column in &mut columns {
+            let _ = write!(terminal, "ab\t");
+            *column = terminal.cursor.column;
+        }
+
+        assert_eq!(columns, [8, 16, 24]);
+    }
+
+    #[test_case]
+    fn tabs_move_to_the_stops_of_the_tab_width() {
+        let mut terminal = Terminal::new();
+        terminal.set_tab_width(4);
+        let mut columns = [0; 3];
+        for column in &mut columns {
+            let _ = write!(terminal, "ab\t");
+            *column = terminal.cursor.column;
+        }
+
+        assert_eq!(columns, [4, 8, 12]);
+    }
 
-            self.buffer = slice::from_raw_parts_mut(ptr, new_len);
+    #[test_case]
+    fn one_line_past_the_screen_scrolls_by_one() {
+        let mut terminal = Terminal::new();
+        let _ = write!(terminal, "line");
+        for _ in 1..terminal.height {
+            let _ = write!(terminal, "\nline");
         }
-        self.max_lines *= 2;
+        assert_eq!(terminal.scroll, 0);
+
+        let _ = write!(terminal, "\nlast");
+        assert_eq!(terminal.scroll, 1);
+        let mut out = [0; 1024];
+        assert_eq!(
+            screen_text(&terminal, &mut out).lines().last(),
+            Some("last")
+        );
     }
 }
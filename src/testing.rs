@@ -0,0 +1,29 @@
+//! Harness for the `#[test_case]` functions, run inside QEMU by `cargo test`.
+//!
+//! Results are reported over serial and the virtual machine exits through
+//! [`qemu::exit`](crate::cpu::qemu::exit) with a code telling whether all tests passed.
+
+use crate::cpu::qemu::{self, QemuExitCode};
+use crate::drivers::serial::{serial_print, serial_println};
+
+pub trait Testable {
+    fn run(&self);
+}
+
+impl<T: Fn()> Testable for T {
+    fn run(&self) {
+        serial_print!("{}...\t", core::any::type_name::<T>());
+        self();
+        serial_println!("[ok]");
+    }
+}
+
+/// Run every test in order. A failing test panics, in which case the panic handler reports the
+/// failure and exits.
+pub fn test_runner(tests: &[&dyn Testable]) {
+    serial_println!("Running {} tests", tests.len());
+    for test in tests {
+        test.run();
+    }
+    qemu::exit(QemuExitCode::Success);
+}
@@ -0,0 +1,70 @@
+//! Small helpers shared by the rest of the kernel.
+
+use core::fmt;
+
+/// Fixed-size buffer to format text into without a heap.
+///
+/// Text that does not fit is dropped and recorded as truncated, in which case the buffer holds
+/// the beginning of the text, cut at a character boundary.
+pub struct FmtBuf<const N: usize> {
+    buffer: [u8; N],
+    len: usize,
+    truncated: bool,
+}
+
+impl<const N: usize> FmtBuf<N> {
+    pub const fn new() -> Self {
+        Self {
+            buffer: [0; N],
+            len: 0,
+            truncated: false,
+        }
+    }
+
+    pub fn as_str(&self) -> &str {
+        // Only whole characters of `&str`s are ever copied into the buffer.
+        unsafe { core::str::from_utf8_unchecked(&self.buffer[..self.len]) }
+    }
+
+    /// Returns whether some text did not fit in the buffer and was dropped.
+    pub fn truncated(&self) -> bool {
+        self.truncated
+    }
+
+    /// Empty the buffer so that it can be reused.
+    pub fn clear(&mut self) {
+        self.len = 0;
+        self.truncated = false;
+    }
+}
+
+impl<const N: usize> Default for FmtBuf<N> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<const N: usize> fmt::Write for FmtBuf<N> {
+    /// Append `s` to the buffer. If it does not fit, as much of `s` as possible is written
+    /// without splitting a character and an error is returned. Nothing is appended once the
+    /// buffer is truncated, so that it never holds text with a gap in the middle.
+    fn write_str(&mut self, s: &str) -> fmt::Result {
+        if self.truncated {
+            return Err(fmt::Error);
+        }
+
+        let mut fitting = s.len().min(N - self.len);
+        while !s.is_char_boundary(fitting) {
+            fitting -= 1;
+        }
+        self.buffer[self.len..self.len + fitting].copy_from_slice(&s.as_bytes()[..fitting]);
+        self.len += fitting;
+
+        if fitting < s.len() {
+            self.truncated = true;
+            Err(fmt::Error)
+        } else {
+            Ok(())
+        }
+    }
+}